@@ -6,5 +6,14 @@ pub const APP_ICON_SIZE: u32 = 64;
 pub const APP_ICON: &[u8] = include_bytes!("../../assets/logo_64.ico");
 pub const GRAPH_THICK_LINE_WIDTH: f32 = 2.0;
 pub const GRAPH_THIN_LINE_WIDTH: f32 = 1.0;
+pub const GRAPH_POINT_RADIUS: f32 = 3.0;
+pub const GRAPH_IMPLICIT_GRID_STEP: f32 = 4.0;
+pub const GRAPH_ARROW_HEAD_SIZE: f32 = 8.0;
+pub const GRAPH_EQUATION_COLORS: &[(u8, u8, u8)] =
+    &[(0, 255, 0), (0, 191, 255), (255, 64, 129), (255, 235, 59)];
+pub const FORMULA_FONT_SIZE: f32 = 18.0;
+pub const FORMULA_CHAR_WIDTH: f32 = 0.6;
+pub const FORMULA_PADDING: f32 = 4.0;
+pub const FORMULA_MAX_HEIGHT: f32 = 320.0;
 pub const REPL_TEXT_INPUT_ID: &str = "1";
 pub const REPL_BACKGROUND_COLOR: (u8, u8, u8) = (18, 18, 18);
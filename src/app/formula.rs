@@ -0,0 +1,731 @@
+use super::constants::FORMULA_CHAR_WIDTH;
+use super::constants::FORMULA_PADDING;
+use super::types::MyMathBoardMessage;
+use iced::mouse;
+use iced::widget::canvas;
+use iced::widget::canvas::Stroke;
+use iced::widget::canvas::Text;
+use iced::widget::text::LineHeight;
+use iced::Color;
+use iced::Font;
+use iced::Pixels;
+use iced::Point;
+use iced::Rectangle;
+use iced::Renderer;
+use iced::Theme;
+use iced::Vector;
+
+/// A formula typeset from the MathML the REPL produces: fractions stacked,
+/// exponents raised, radicals and brackets drawn to the height of what they
+/// enclose. It is laid out once and drawn on a canvas of its own size.
+#[derive(Debug, Clone)]
+pub struct Formula {
+    layout: Layout,
+}
+
+impl Formula {
+    /// Lay out a formula written in MathML at a font size, or `None` if the
+    /// MathML cannot be read.
+    pub fn from_mathml(mathml: &str, size: f32) -> Option<Formula> {
+        let element = parse(mathml)?;
+        Some(Formula {
+            layout: layout(&element, size),
+        })
+    }
+
+    pub fn width(&self) -> f32 {
+        self.layout.width + 2.0 * FORMULA_PADDING
+    }
+
+    pub fn height(&self) -> f32 {
+        self.layout.ascent + self.layout.descent + 2.0 * FORMULA_PADDING
+    }
+}
+
+impl canvas::Program<MyMathBoardMessage> for Formula {
+    type State = ();
+
+    fn draw(
+        &self,
+        _state: &(),
+        renderer: &Renderer,
+        _theme: &Theme,
+        bounds: Rectangle,
+        _cursor: mouse::Cursor,
+    ) -> Vec<canvas::Geometry> {
+        let mut frame = canvas::Frame::new(renderer, bounds.size());
+        let origin = Vector::new(FORMULA_PADDING, FORMULA_PADDING + self.layout.ascent);
+
+        for item in &self.layout.items {
+            match item {
+                Item::Glyphs {
+                    text,
+                    position,
+                    size,
+                } => frame.fill_text(Text {
+                    content: text.clone(),
+                    // The text box is one line high with the baseline at 80% of it.
+                    position: *position + origin - Vector::new(0.0, 0.8 * size),
+                    color: Color::WHITE,
+                    size: Pixels(*size),
+                    line_height: LineHeight::Relative(1.0),
+                    font: Font::MONOSPACE,
+                    ..Text::default()
+                }),
+                Item::Line { from, to, width } => frame.stroke(
+                    &canvas::Path::line(*from + origin, *to + origin),
+                    Stroke::default()
+                        .with_width(*width)
+                        .with_color(Color::WHITE),
+                ),
+                Item::Curve {
+                    from,
+                    control,
+                    to,
+                    width,
+                } => frame.stroke(
+                    &canvas::Path::new(|builder: &mut canvas::path::Builder| {
+                        builder.move_to(*from + origin);
+                        builder.quadratic_curve_to(*control + origin, *to + origin);
+                    }),
+                    Stroke::default()
+                        .with_width(*width)
+                        .with_color(Color::WHITE),
+                ),
+            }
+        }
+
+        vec![frame.into_geometry()]
+    }
+}
+
+/// A MathML element or the text inside one.
+#[derive(Debug)]
+enum Element {
+    Node(String, Vec<Element>),
+    Text(String),
+}
+
+/// Something to draw, placed relative to the left end of the baseline, with
+/// `y` growing downwards as on the canvas.
+#[derive(Debug, Clone)]
+enum Item {
+    Glyphs {
+        text: String,
+        position: Point,
+        size: f32,
+    },
+    Line {
+        from: Point,
+        to: Point,
+        width: f32,
+    },
+    Curve {
+        from: Point,
+        control: Point,
+        to: Point,
+        width: f32,
+    },
+}
+
+impl Item {
+    fn shifted(self, offset: Vector) -> Item {
+        match self {
+            Item::Glyphs {
+                text,
+                position,
+                size,
+            } => Item::Glyphs {
+                text,
+                position: position + offset,
+                size,
+            },
+            Item::Line { from, to, width } => Item::Line {
+                from: from + offset,
+                to: to + offset,
+                width,
+            },
+            Item::Curve {
+                from,
+                control,
+                to,
+                width,
+            } => Item::Curve {
+                from: from + offset,
+                control: control + offset,
+                to: to + offset,
+                width,
+            },
+        }
+    }
+}
+
+/// A laid out box: how far it extends right of its origin and above and
+/// below its baseline, and what to draw in it.
+#[derive(Debug, Clone, Default)]
+struct Layout {
+    width: f32,
+    ascent: f32,
+    descent: f32,
+    items: Vec<Item>,
+}
+
+impl Layout {
+    fn text(text: &str, size: f32) -> Layout {
+        Layout {
+            width: text.chars().count() as f32 * FORMULA_CHAR_WIDTH * size,
+            ascent: 0.8 * size,
+            descent: 0.2 * size,
+            items: vec![Item::Glyphs {
+                text: text.to_string(),
+                position: Point::ORIGIN,
+                size,
+            }],
+        }
+    }
+
+    fn space(width: f32) -> Layout {
+        Layout {
+            width,
+            ..Layout::default()
+        }
+    }
+
+    // Place another box with its origin at an offset from this one's.
+    fn place(&mut self, other: Layout, offset: Vector) {
+        self.items
+            .extend(other.items.into_iter().map(|item| item.shifted(offset)));
+    }
+}
+
+// The height of fraction bars and of the middle of matrices above the baseline.
+fn axis(size: f32) -> f32 {
+    0.3 * size
+}
+
+fn stroke_width(size: f32) -> f32 {
+    (0.06 * size).max(1.0)
+}
+
+fn layout(element: &Element, size: f32) -> Layout {
+    let (name, children) = match element {
+        Element::Text(text) => return Layout::text(text, size),
+        Element::Node(name, children) => (name.as_str(), children),
+    };
+    let child = |index: usize, size: f32| match children.get(index) {
+        Some(child) => layout(child, size),
+        None => Layout::default(),
+    };
+
+    match name {
+        "mi" | "mn" => Layout::text(&text_of(children), size),
+        "mo" => operator(&text_of(children), size, false),
+        "mfrac" => fraction(child(0, size), child(1, size), size),
+        "msup" => script(child(0, size), None, Some(child(1, 0.7 * size)), size),
+        "msub" => script(child(0, size), Some(child(1, 0.7 * size)), None, size),
+        "msubsup" => script(
+            child(0, size),
+            Some(child(1, 0.7 * size)),
+            Some(child(2, 0.7 * size)),
+            size,
+        ),
+        "msqrt" => root(row(children, size), size),
+        "mtable" => table(children, size),
+        _ => row(children, size),
+    }
+}
+
+// Children side by side, with space around operators other than a leading
+// sign, and brackets as tall as the rest of the row.
+fn row(children: &[Element], size: f32) -> Layout {
+    let mut boxes: Vec<Option<Layout>> = children
+        .iter()
+        .enumerate()
+        .map(|(index, child)| match child {
+            _ if fence_of(child).is_some() => None,
+            Element::Node(name, text) if name == "mo" => {
+                Some(operator(&text_of(text), size, index > 0))
+            }
+            child => Some(layout(child, size)),
+        })
+        .collect();
+
+    let ascent = boxes
+        .iter()
+        .flatten()
+        .map(|b| b.ascent)
+        .fold(0.8 * size, f32::max);
+    let descent = boxes
+        .iter()
+        .flatten()
+        .map(|b| b.descent)
+        .fold(0.2 * size, f32::max);
+
+    for (index, child) in children.iter().enumerate() {
+        if let Some(fence) = fence_of(child) {
+            boxes[index] = Some(stretched_fence(fence, ascent, descent, size));
+        }
+    }
+
+    let mut result = Layout {
+        ascent,
+        descent,
+        ..Layout::default()
+    };
+    for b in boxes.into_iter().flatten() {
+        let width = b.width;
+        result.place(b, Vector::new(result.width, 0.0));
+        result.width += width;
+    }
+    result
+}
+
+fn operator(text: &str, size: f32, is_infix: bool) -> Layout {
+    match text {
+        // Function application and invisible times take no room.
+        "\u{2061}" | "\u{2062}" => Layout::space(0.1 * size),
+        "," => {
+            let mut comma = Layout::text(",", size);
+            comma.width += 0.3 * size;
+            comma
+        }
+        _ if is_infix && text != "!" => {
+            let mut spaced = Layout::space(0.0);
+            let glyphs = Layout::text(text, size);
+            spaced.ascent = glyphs.ascent;
+            spaced.descent = glyphs.descent;
+            spaced.width = glyphs.width + 0.4 * size;
+            spaced.place(glyphs, Vector::new(0.2 * size, 0.0));
+            spaced
+        }
+        _ => Layout::text(text, size),
+    }
+}
+
+fn fence_of(element: &Element) -> Option<char> {
+    match element {
+        Element::Node(name, children) if name == "mo" => {
+            let text = text_of(children);
+            match text.as_str() {
+                "(" | ")" | "[" | "]" | "|" => text.chars().next(),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn stretched_fence(fence: char, ascent: f32, descent: f32, size: f32) -> Layout {
+    let width = 0.4 * size;
+    let (top, bottom) = (-ascent, descent);
+    let middle = (top + bottom) / 2.0;
+    let line_width = stroke_width(size);
+    let line = |from: Point, to: Point| Item::Line {
+        from,
+        to,
+        width: line_width,
+    };
+
+    let items = match fence {
+        '(' => vec![Item::Curve {
+            from: Point::new(0.75 * width, top),
+            control: Point::new(0.0, middle),
+            to: Point::new(0.75 * width, bottom),
+            width: line_width,
+        }],
+        ')' => vec![Item::Curve {
+            from: Point::new(0.25 * width, top),
+            control: Point::new(width, middle),
+            to: Point::new(0.25 * width, bottom),
+            width: line_width,
+        }],
+        '[' => vec![
+            line(Point::new(0.7 * width, top), Point::new(0.3 * width, top)),
+            line(
+                Point::new(0.3 * width, top),
+                Point::new(0.3 * width, bottom),
+            ),
+            line(
+                Point::new(0.3 * width, bottom),
+                Point::new(0.7 * width, bottom),
+            ),
+        ],
+        ']' => vec![
+            line(Point::new(0.3 * width, top), Point::new(0.7 * width, top)),
+            line(
+                Point::new(0.7 * width, top),
+                Point::new(0.7 * width, bottom),
+            ),
+            line(
+                Point::new(0.7 * width, bottom),
+                Point::new(0.3 * width, bottom),
+            ),
+        ],
+        _ => vec![line(
+            Point::new(0.5 * width, top),
+            Point::new(0.5 * width, bottom),
+        )],
+    };
+
+    Layout {
+        width,
+        ascent,
+        descent,
+        items,
+    }
+}
+
+fn fraction(numerator: Layout, denominator: Layout, size: f32) -> Layout {
+    let gap = 0.15 * size;
+    let margin = 0.15 * size;
+    let axis = axis(size);
+    let width = numerator.width.max(denominator.width) + 2.0 * margin;
+
+    let mut result = Layout {
+        width,
+        ascent: axis + gap + numerator.ascent + numerator.descent,
+        descent: gap + denominator.ascent + denominator.descent - axis,
+        items: vec![Item::Line {
+            from: Point::new(0.5 * margin, -axis),
+            to: Point::new(width - 0.5 * margin, -axis),
+            width: stroke_width(size),
+        }],
+    };
+
+    let numerator_offset = Vector::new(
+        (width - numerator.width) / 2.0,
+        -(axis + gap + numerator.descent),
+    );
+    let denominator_offset = Vector::new(
+        (width - denominator.width) / 2.0,
+        -axis + gap + denominator.ascent,
+    );
+    result.place(numerator, numerator_offset);
+    result.place(denominator, denominator_offset);
+    result
+}
+
+fn script(
+    base: Layout,
+    subscript: Option<Layout>,
+    superscript: Option<Layout>,
+    size: f32,
+) -> Layout {
+    let (base_ascent, base_descent) = (base.ascent, base.descent);
+    let script_x = base.width + 0.05 * size;
+    let mut script_width: f32 = 0.0;
+    let mut result = Layout {
+        width: base.width,
+        ascent: base_ascent,
+        descent: base_descent,
+        ..Layout::default()
+    };
+    result.place(base, Vector::ZERO);
+
+    if let Some(superscript) = superscript {
+        let raise = (0.45 * size).max(base_ascent - 0.5 * superscript.ascent);
+        result.ascent = result.ascent.max(raise + superscript.ascent);
+        script_width = script_width.max(superscript.width);
+        result.place(superscript, Vector::new(script_x, -raise));
+    }
+    if let Some(subscript) = subscript {
+        let lower = (0.25 * size).max(base_descent);
+        result.descent = result.descent.max(lower + subscript.descent);
+        script_width = script_width.max(subscript.width);
+        result.place(subscript, Vector::new(script_x, lower));
+    }
+
+    result.width = script_x + script_width;
+    result
+}
+
+fn root(radicand: Layout, size: f32) -> Layout {
+    let gap = 0.15 * size;
+    let sign = 0.6 * size;
+    let top = -(radicand.ascent + gap);
+    let bottom = radicand.descent;
+    let height = bottom - top;
+    let width = sign + radicand.width + 0.15 * size;
+    let line_width = stroke_width(size);
+
+    let points = [
+        Point::new(0.0, bottom - 0.45 * height),
+        Point::new(0.15 * size, bottom - 0.5 * height),
+        Point::new(0.35 * size, bottom),
+        Point::new(sign, top),
+        Point::new(width, top),
+    ];
+
+    let mut result = Layout {
+        width,
+        ascent: -top + line_width,
+        descent: bottom,
+        items: points
+            .windows(2)
+            .map(|pair| Item::Line {
+                from: pair[0],
+                to: pair[1],
+                width: line_width,
+            })
+            .collect(),
+    };
+    result.place(radicand, Vector::new(sign + 0.05 * size, 0.0));
+    result
+}
+
+// The rows of a matrix, each cell centred in its column, the whole centred
+// on the axis. The brackets around it come from the enclosing row.
+fn table(rows: &[Element], size: f32) -> Layout {
+    let column_gap = 0.8 * size;
+    let row_gap = 0.25 * size;
+
+    let cells: Vec<Vec<Layout>> = rows
+        .iter()
+        .map(|row| match row {
+            Element::Node(_, cells) => cells.iter().map(|cell| layout(cell, size)).collect(),
+            Element::Text(_) => Vec::new(),
+        })
+        .collect();
+
+    let columns = cells.iter().map(Vec::len).max().unwrap_or(0);
+    let column_widths: Vec<f32> = (0..columns)
+        .map(|column| {
+            cells
+                .iter()
+                .filter_map(|row| row.get(column))
+                .map(|cell| cell.width)
+                .fold(0.0, f32::max)
+        })
+        .collect();
+    let row_extents: Vec<(f32, f32)> = cells
+        .iter()
+        .map(|row| {
+            row.iter().fold((0.8 * size, 0.2 * size), |(a, d), cell| {
+                (a.max(cell.ascent), d.max(cell.descent))
+            })
+        })
+        .collect();
+
+    let height = row_extents.iter().map(|(a, d)| a + d).sum::<f32>()
+        + row_gap * (cells.len().saturating_sub(1)) as f32;
+    let width = column_widths.iter().sum::<f32>()
+        + column_gap * (columns.saturating_sub(1)) as f32
+        + 0.2 * size;
+    let top = -axis(size) - height / 2.0;
+
+    let mut result = Layout {
+        width,
+        ascent: -top,
+        descent: height + top,
+        ..Layout::default()
+    };
+
+    let mut y = top;
+    for (row, (ascent, descent)) in cells.into_iter().zip(row_extents) {
+        let mut x = 0.1 * size;
+        for (cell, column_width) in row.into_iter().zip(&column_widths) {
+            let offset = Vector::new(x + (column_width - cell.width) / 2.0, y + ascent);
+            result.place(cell, offset);
+            x += column_width + column_gap;
+        }
+        y += ascent + descent + row_gap;
+    }
+    result
+}
+
+fn text_of(children: &[Element]) -> String {
+    children
+        .iter()
+        .map(|child| match child {
+            Element::Text(text) => text.clone(),
+            Element::Node(_, children) => text_of(children),
+        })
+        .collect()
+}
+
+// Read the MathML the REPL writes: elements whose attributes are ignored,
+// text, and character references.
+fn parse(mathml: &str) -> Option<Element> {
+    let mut stack: Vec<(String, Vec<Element>)> = vec![(String::new(), Vec::new())];
+    let mut rest = mathml;
+
+    while !rest.is_empty() {
+        if let Some(tag) = rest.strip_prefix('<') {
+            let end = tag.find('>')?;
+            let inside = &tag[..end];
+            rest = &tag[end + 1..];
+
+            if let Some(name) = inside.strip_prefix('/') {
+                let (open, children) = stack.pop()?;
+                if open != name.trim() {
+                    return None;
+                }
+                stack.last_mut()?.1.push(Element::Node(open, children));
+            } else {
+                let name = inside.split_whitespace().next()?.to_string();
+                stack.push((name, Vec::new()));
+            }
+        } else {
+            let end = rest.find('<').unwrap_or(rest.len());
+            stack
+                .last_mut()?
+                .1
+                .push(Element::Text(decode(&rest[..end])?));
+            rest = &rest[end..];
+        }
+    }
+
+    let (_, mut roots) = stack.pop()?;
+    if !stack.is_empty() || roots.len() != 1 {
+        return None;
+    }
+    roots.pop()
+}
+
+fn decode(text: &str) -> Option<String> {
+    let mut decoded = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        let end = rest[start..].find(';')? + start;
+        let entity = &rest[start + 1..end];
+        decoded.push(match entity {
+            "lt" => '<',
+            "gt" => '>',
+            "amp" => '&',
+            _ => {
+                let code = entity.strip_prefix("#x")?;
+                char::from_u32(u32::from_str_radix(code, 16).ok()?)?
+            }
+        });
+        rest = &rest[end + 1..];
+    }
+
+    decoded.push_str(rest);
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: f32 = 20.0;
+
+    fn math(body: &str) -> String {
+        format!(
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\">{}</math>",
+            body
+        )
+    }
+
+    fn laid_out(body: &str) -> Layout {
+        layout(&parse(&math(body)).unwrap(), SIZE)
+    }
+
+    fn glyphs(layout: &Layout) -> Vec<(&str, Point)> {
+        layout
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Glyphs { text, position, .. } => Some((text.as_str(), *position)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_elements_and_references() {
+        let Some(Element::Node(name, children)) = parse(&math("<mo>&lt;</mo><mi>&#x3C0;</mi>"))
+        else {
+            panic!("no math element");
+        };
+        assert_eq!(name, "math");
+        assert_eq!(text_of(&children), "<π");
+    }
+
+    #[test]
+    fn fraction_is_stacked_around_the_axis() {
+        let number = Layout::text("1", SIZE);
+        let fraction = laid_out("<mfrac><mrow><mn>1</mn></mrow><mrow><mn>2</mn></mrow></mfrac>");
+
+        assert!(fraction.width > number.width);
+        assert!(fraction.ascent > number.ascent + number.descent);
+        assert!(fraction.descent > number.descent);
+
+        let glyphs = glyphs(&fraction);
+        assert_eq!(glyphs.len(), 2);
+        assert!(glyphs[0].1.y < -axis(SIZE) && glyphs[1].1.y > -axis(SIZE));
+        assert_eq!(glyphs[0].1.x, glyphs[1].1.x);
+    }
+
+    #[test]
+    fn superscript_is_raised_and_smaller() {
+        let power = laid_out("<msup><mrow><mi>x</mi></mrow><mrow><mn>2</mn></mrow></msup>");
+        let base = Layout::text("x", SIZE);
+
+        assert!(power.width > base.width && power.width < 2.0 * base.width);
+        assert!(power.ascent > base.ascent);
+        assert_eq!(power.descent, base.descent);
+
+        let items: Vec<f32> = power
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Glyphs { size, .. } => Some(*size),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(items, [SIZE, 0.7 * SIZE]);
+        assert!(glyphs(&power)[1].1.y < 0.0);
+    }
+
+    #[test]
+    fn radical_covers_its_radicand() {
+        let radicand = laid_out("<mrow><mi>x</mi><mo>+</mo><mn>1</mn></mrow>");
+        let root = laid_out("<msqrt><mrow><mi>x</mi><mo>+</mo><mn>1</mn></mrow></msqrt>");
+
+        assert!(root.width > radicand.width);
+        assert!(root.ascent > radicand.ascent);
+        assert_eq!(root.descent, radicand.descent);
+        assert!(root
+            .items
+            .iter()
+            .any(|item| matches!(item, Item::Line { .. })));
+    }
+
+    #[test]
+    fn matrix_has_a_cell_per_entry_and_tall_brackets() {
+        let matrix = laid_out(
+            "<mrow><mo>(</mo><mtable>\
+             <mtr><mtd><mn>1</mn></mtd><mtd><mn>2</mn></mtd></mtr>\
+             <mtr><mtd><mn>3</mn></mtd><mtd><mn>4</mn></mtd></mtr>\
+             </mtable><mo>)</mo></mrow>",
+        );
+        let number = Layout::text("1", SIZE);
+
+        let cells: Vec<&str> = glyphs(&matrix).into_iter().map(|(text, _)| text).collect();
+        assert_eq!(cells, ["1", "2", "3", "4"]);
+        assert!(matrix.width > 2.0 * number.width);
+        assert!(matrix.ascent + matrix.descent > 2.0 * (number.ascent + number.descent));
+        assert!(matrix.ascent > 0.0 && matrix.descent > 0.0);
+    }
+
+    #[test]
+    fn malformed_mathml_is_rejected() {
+        for mathml in [
+            "",
+            "<math><mi>x</mi>",
+            "<math><mi>x</mo></math>",
+            "<math><mi>x</mi></math><math></math>",
+            "</math>",
+            "<math><mi>x",
+            "<math><mi>&bogus;</mi></math>",
+            "<math><mi>&#xZZ;</mi></math>",
+            "<math><mi>&amp</mi></math>",
+            "<>",
+        ] {
+            assert!(Formula::from_mathml(mathml, SIZE).is_none(), "{}", mathml);
+        }
+    }
+}
@@ -1,9 +1,16 @@
 use super::constants::DEFAULT_CELL_SIZE;
+use super::constants::GRAPH_ARROW_HEAD_SIZE;
+use super::constants::GRAPH_EQUATION_COLORS;
+use super::constants::GRAPH_IMPLICIT_GRID_STEP;
+use super::constants::GRAPH_POINT_RADIUS;
 use super::constants::GRAPH_THICK_LINE_WIDTH;
 use super::constants::GRAPH_THIN_LINE_WIDTH;
 use super::types::MyMathBoardMessage;
-use evalexpr::context_map;
+use crate::repl::NumberFormat;
+use crate::repl::Scope;
+use evalexpr::HashMapContext;
 use evalexpr::Node;
+use evalexpr::Value;
 use iced::event;
 use iced::mouse;
 use iced::widget::canvas;
@@ -28,7 +35,16 @@ pub struct Graph {
     pub is_dragging: bool,
     pub viewport_offset: Vector,
     pub last_cursor_position: Option<Point>,
+    pub drag_origin: Option<Point>,
     pub equations: Vec<Node>,
+    pub implicit_equations: Vec<Node>,
+    pub context: HashMapContext,
+    pub sequences: Vec<Vec<(f32, f32)>>,
+    pub curves: Vec<Vec<(f32, f32)>>,
+    pub points: Vec<(f32, f32)>,
+    pub stems: Vec<(f32, f32)>,
+    pub arrows: Vec<((f32, f32), (f32, f32))>,
+    pub number_format: NumberFormat,
 }
 
 impl Default for Graph {
@@ -40,13 +56,23 @@ impl Default for Graph {
             is_dragging: false,
             viewport_offset: Vector::new(0.0, 0.0),
             last_cursor_position: None,
+            drag_origin: None,
             equations: Vec::new(),
+            implicit_equations: Vec::new(),
+            context: HashMapContext::new(),
+            sequences: Vec::new(),
+            curves: Vec::new(),
+            points: Vec::new(),
+            stems: Vec::new(),
+            arrows: Vec::new(),
+            number_format: NumberFormat::default(),
         }
     }
 }
 
 impl Graph {
     // Convert graph coordinates (x, y) to screen coordinates (screen_x, screen_y)
+    #[allow(clippy::too_many_arguments)]
     pub fn graph_to_screen(
         &self,
         x: f32,
@@ -62,6 +88,85 @@ impl Graph {
         (screen_x, screen_y)
     }
 
+    // Trace the curve where an implicit equation is zero with marching squares:
+    // sample the residual on a grid of screen pixels and, in every grid square
+    // where it changes sign, join the interpolated crossings on its edges.
+    fn implicit_curve(&self, equation: &Node, bounds: Rectangle, cell_size: f32) -> canvas::Path {
+        let step = GRAPH_IMPLICIT_GRID_STEP;
+        let columns = (bounds.width / step).ceil() as usize + 1;
+        let rows = (bounds.height / step).ceil() as usize + 1;
+
+        let to_graph = |screen_x: f32, screen_y: f32| {
+            (
+                (screen_x - bounds.width / 2.0) / cell_size + self.viewport_offset.x,
+                self.viewport_offset.y - (screen_y - bounds.height / 2.0) / cell_size,
+            )
+        };
+
+        let mut values = vec![f64::NAN; columns * rows];
+        for row in 0..rows {
+            for column in 0..columns {
+                let (x, y) = to_graph(column as f32 * step, row as f32 * step);
+                let scope = Scope::new(
+                    &self.context,
+                    vec![
+                        ("x".to_string(), Value::Float(x as f64)),
+                        ("y".to_string(), Value::Float(y as f64)),
+                    ],
+                );
+                if let Ok(value) = equation.eval_number_with_context(&scope) {
+                    values[row * columns + column] = value;
+                }
+            }
+        }
+
+        canvas::Path::new(|builder: &mut canvas::path::Builder| {
+            for row in 0..rows - 1 {
+                for column in 0..columns - 1 {
+                    let corners = [
+                        (column, row),
+                        (column + 1, row),
+                        (column + 1, row + 1),
+                        (column, row + 1),
+                    ];
+                    let value = |(c, r): (usize, usize)| values[r * columns + c];
+
+                    if corners.iter().any(|corner| !value(*corner).is_finite()) {
+                        continue;
+                    }
+
+                    let mut crossings = Vec::new();
+                    for edge in 0..4 {
+                        let (a, b) = (corners[edge], corners[(edge + 1) % 4]);
+                        let (value_a, value_b) = (value(a), value(b));
+
+                        if (value_a < 0.0) != (value_b < 0.0) {
+                            let t = (value_a / (value_a - value_b)) as f32;
+                            crossings.push(Point::new(
+                                (a.0 as f32 + t * (b.0 as f32 - a.0 as f32)) * step,
+                                (a.1 as f32 + t * (b.1 as f32 - a.1 as f32)) * step,
+                            ));
+                        }
+                    }
+
+                    for pair in crossings.chunks_exact(2) {
+                        builder.move_to(pair[0]);
+                        builder.line_to(pair[1]);
+                    }
+                }
+            }
+        })
+    }
+
+    /// An axis label, in the number format of the REPL once one is set.
+    fn axis_label(&self, value: f32) -> String {
+        if self.number_format.is_default() {
+            format!("{}", value)
+        } else {
+            self.number_format.format(value as f64)
+        }
+    }
+
     // Convert screen coordinates (screen_x, screen_y) to graph coordinates (x, y)
     pub fn screen_to_graph(
         &self,
@@ -101,10 +206,10 @@ impl canvas::Program<MyMathBoardMessage> for Graph {
         // Calculate the visible area.
         let screen_start_x = (self.viewport_offset.x / self.cell_size as f32).floor() as isize;
         let screen_start_y = (self.viewport_offset.y / self.cell_size as f32).floor() as isize;
-        let screen_end_x = ((self.viewport_offset.x + bounds.width as f32) / self.cell_size as f32)
-            .ceil() as isize;
-        let screen_end_y = ((self.viewport_offset.y + bounds.height as f32) / self.cell_size as f32)
-            .ceil() as isize;
+        let screen_end_x =
+            ((self.viewport_offset.x + bounds.width) / self.cell_size as f32).ceil() as isize;
+        let screen_end_y =
+            ((self.viewport_offset.y + bounds.height) / self.cell_size as f32).ceil() as isize;
 
         // Draw cells for the visible area.
         for y in screen_start_y..screen_end_y {
@@ -172,7 +277,7 @@ impl canvas::Program<MyMathBoardMessage> for Graph {
 
             if screen_x >= 0.0 && screen_x <= bounds.width {
                 frame.fill_text(Text {
-                    content: format!("{}", graph_x),
+                    content: self.axis_label(graph_x),
                     position: Point::new(screen_x, screen_center_y + 5.0),
                     color: Color::from_rgb(0.8, 0.8, 0.8),
                     size: Pixels(12.0),
@@ -194,7 +299,7 @@ impl canvas::Program<MyMathBoardMessage> for Graph {
 
             if screen_y >= 0.0 && screen_y <= bounds.height {
                 frame.fill_text(Text {
-                    content: format!("{}", graph_y),
+                    content: self.axis_label(graph_y),
                     position: Point::new(screen_center_x + 5.0, screen_y),
                     color: Color::from_rgb(0.8, 0.8, 0.8),
                     size: Pixels(12.0),
@@ -204,7 +309,8 @@ impl canvas::Program<MyMathBoardMessage> for Graph {
         }
 
         // Render equations in the visible area.
-        for equation in &self.equations {
+        for (index, equation) in self.equations.iter().enumerate() {
+            let (r, g, b) = GRAPH_EQUATION_COLORS[index % GRAPH_EQUATION_COLORS.len()];
             let start_x = self
                 .screen_to_graph(
                     0.0,
@@ -228,26 +334,35 @@ impl canvas::Program<MyMathBoardMessage> for Graph {
 
             let path = canvas::Path::new(|builder: &mut canvas::path::Builder| {
                 let mut x = start_x;
+                // Lift the pen where the equation is undefined, e.g. outside
+                // the data of an interpolant, instead of joining across the gap.
+                let mut is_drawing = false;
                 while x < end_x {
-                    let calc =
-                        equation.eval_with_context(&context_map! { "x" => x as f64 }.unwrap());
-                    if let Ok(result) = calc {
-                        let y = result.as_float().unwrap() as f32;
-                        let (screen_x, screen_y) = self.graph_to_screen(
-                            x,
-                            y,
-                            self.viewport_offset.x,
-                            self.viewport_offset.y,
-                            bounds.width,
-                            bounds.height,
-                            cell_size.height,
-                        );
+                    let scope = Scope::new(
+                        &self.context,
+                        vec![("x".to_string(), Value::Float(x as f64))],
+                    );
+                    match equation.eval_number_with_context(&scope) {
+                        Ok(y) if y.is_finite() => {
+                            let y = y as f32;
+                            let (screen_x, screen_y) = self.graph_to_screen(
+                                x,
+                                y,
+                                self.viewport_offset.x,
+                                self.viewport_offset.y,
+                                bounds.width,
+                                bounds.height,
+                                cell_size.height,
+                            );
 
-                        if (x - start_x).abs() < f32::EPSILON {
-                            builder.move_to(Point::new(screen_x, screen_y));
-                        } else {
-                            builder.line_to(Point::new(screen_x, screen_y));
+                            if is_drawing {
+                                builder.line_to(Point::new(screen_x, screen_y));
+                            } else {
+                                builder.move_to(Point::new(screen_x, screen_y));
+                            }
+                            is_drawing = true;
                         }
+                        _ => is_drawing = false,
                     }
                     x += 0.01;
                 }
@@ -256,7 +371,160 @@ impl canvas::Program<MyMathBoardMessage> for Graph {
                 &path,
                 Stroke::default()
                     .with_width(GRAPH_THIN_LINE_WIDTH)
-                    .with_color(Color::from_rgb(0.0, 255.0, 0.0)),
+                    .with_color(Color::from_rgb8(r, g, b)),
+            );
+        }
+
+        // Render equations such as `x^2 + y^2 = 4` where their residual changes sign.
+        for (index, equation) in self.implicit_equations.iter().enumerate() {
+            let (r, g, b) =
+                GRAPH_EQUATION_COLORS[(self.equations.len() + index) % GRAPH_EQUATION_COLORS.len()];
+            frame.stroke(
+                &self.implicit_curve(equation, bounds, cell_size.height),
+                Stroke::default()
+                    .with_width(GRAPH_THIN_LINE_WIDTH)
+                    .with_color(Color::from_rgb8(r, g, b)),
+            );
+        }
+
+        // Render curves given as points, such as the solutions of differential equations.
+        for curve in &self.curves {
+            let path = canvas::Path::new(|builder: &mut canvas::path::Builder| {
+                for (index, (x, y)) in curve.iter().enumerate() {
+                    let (screen_x, screen_y) = self.graph_to_screen(
+                        *x,
+                        *y,
+                        self.viewport_offset.x,
+                        self.viewport_offset.y,
+                        bounds.width,
+                        bounds.height,
+                        cell_size.height,
+                    );
+
+                    if index == 0 {
+                        builder.move_to(Point::new(screen_x, screen_y));
+                    } else {
+                        builder.line_to(Point::new(screen_x, screen_y));
+                    }
+                }
+            });
+            frame.stroke(
+                &path,
+                Stroke::default()
+                    .with_width(GRAPH_THIN_LINE_WIDTH)
+                    .with_color(Color::from_rgb8(0, 191, 255)),
+            );
+        }
+
+        // Render sequences as discrete points.
+        for sequence in &self.sequences {
+            for (x, y) in sequence {
+                let (screen_x, screen_y) = self.graph_to_screen(
+                    *x,
+                    *y,
+                    self.viewport_offset.x,
+                    self.viewport_offset.y,
+                    bounds.width,
+                    bounds.height,
+                    cell_size.height,
+                );
+
+                frame.fill(
+                    &canvas::Path::circle(Point::new(screen_x, screen_y), GRAPH_POINT_RADIUS),
+                    Color::from_rgb8(255, 165, 0),
+                );
+            }
+        }
+
+        // Render stems from the x axis, such as the magnitudes of a spectrum.
+        for (x, y) in &self.stems {
+            let to_screen = |y: f32| {
+                let (screen_x, screen_y) = self.graph_to_screen(
+                    *x,
+                    y,
+                    self.viewport_offset.x,
+                    self.viewport_offset.y,
+                    bounds.width,
+                    bounds.height,
+                    cell_size.height,
+                );
+                Point::new(screen_x, screen_y)
+            };
+            let (base, top) = (to_screen(0.0), to_screen(*y));
+
+            frame.stroke(
+                &canvas::Path::line(base, top),
+                Stroke::default()
+                    .with_width(GRAPH_THIN_LINE_WIDTH)
+                    .with_color(Color::from_rgb8(186, 104, 200)),
+            );
+            frame.fill(
+                &canvas::Path::circle(top, GRAPH_POINT_RADIUS),
+                Color::from_rgb8(186, 104, 200),
+            );
+        }
+
+        // Render marked points, such as the solutions of systems of equations.
+        for (x, y) in &self.points {
+            let (screen_x, screen_y) = self.graph_to_screen(
+                *x,
+                *y,
+                self.viewport_offset.x,
+                self.viewport_offset.y,
+                bounds.width,
+                bounds.height,
+                cell_size.height,
+            );
+
+            frame.fill(
+                &canvas::Path::circle(Point::new(screen_x, screen_y), GRAPH_POINT_RADIUS * 1.5),
+                Color::WHITE,
+            );
+        }
+
+        // Render arrows from a point, such as the gradient where the graph was clicked.
+        for ((x, y), (dx, dy)) in &self.arrows {
+            let to_screen = |x: f32, y: f32| {
+                let (screen_x, screen_y) = self.graph_to_screen(
+                    x,
+                    y,
+                    self.viewport_offset.x,
+                    self.viewport_offset.y,
+                    bounds.width,
+                    bounds.height,
+                    cell_size.height,
+                );
+                Point::new(screen_x, screen_y)
+            };
+            let (tail, tip) = (to_screen(*x, *y), to_screen(x + dx, y + dy));
+            let length = tail.distance(tip);
+            let color = Color::from_rgb8(255, 112, 67);
+
+            frame.fill(&canvas::Path::circle(tail, GRAPH_POINT_RADIUS), color);
+            if length == 0.0 {
+                continue;
+            }
+
+            // The head is two strokes back from the tip, 25 degrees either side.
+            let head = GRAPH_ARROW_HEAD_SIZE.min(length / 2.0);
+            let direction = ((tail.x - tip.x) / length, (tail.y - tip.y) / length);
+            let (sin, cos) = 25.0_f32.to_radians().sin_cos();
+            let path = canvas::Path::new(|builder: &mut canvas::path::Builder| {
+                builder.move_to(tail);
+                builder.line_to(tip);
+                for side in [1.0, -1.0] {
+                    builder.move_to(tip);
+                    builder.line_to(Point::new(
+                        tip.x + head * (direction.0 * cos - side * direction.1 * sin),
+                        tip.y + head * (side * direction.0 * sin + direction.1 * cos),
+                    ));
+                }
+            });
+            frame.stroke(
+                &path,
+                Stroke::default()
+                    .with_width(GRAPH_THICK_LINE_WIDTH)
+                    .with_color(color),
             );
         }
 
@@ -285,6 +553,21 @@ impl canvas::Program<MyMathBoardMessage> for Graph {
                 (event::Status::Ignored, None)
             }
             Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => {
+                // A press and release without moving in between is a click.
+                if self.drag_origin.is_some() && self.drag_origin == self.last_cursor_position {
+                    if let Some(position) = _cursor.position_in(bounds) {
+                        let cell_size = self.cell_size as f32;
+                        return (
+                            event::Status::Captured,
+                            Some(MyMathBoardMessage::GraphClicked(
+                                (position.x - bounds.width / 2.0) / cell_size
+                                    + self.viewport_offset.x,
+                                self.viewport_offset.y
+                                    - (position.y - bounds.height / 2.0) / cell_size,
+                            )),
+                        );
+                    }
+                }
                 (event::Status::Captured, Some(MyMathBoardMessage::EndDrag))
             }
             Event::Mouse(mouse::Event::CursorMoved { position }) => {
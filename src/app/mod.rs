@@ -1,4 +1,5 @@
 pub mod constants;
+pub mod formula;
 pub mod graph;
 pub mod types;
 pub mod ui;
@@ -1,3 +1,6 @@
+use super::formula::Formula;
+use crate::repl::Repl;
+use evalexpr::Node;
 use iced::Point;
 use iced::Vector;
 
@@ -6,6 +9,7 @@ pub enum MyMathBoardMessage {
     Dragged(Vector),
     StartDrag(Point, f32, f32),
     EndDrag,
+    GraphClicked(f32, f32),
     ZoomIn,
     ZoomOut,
     DrawEquation(String),
@@ -16,6 +20,14 @@ pub enum MyMathBoardMessage {
     SavePressed,
     OpenPressed,
     SaveAsPressed,
+    ToggleInspector,
+    ToggleExactForms,
+    InspectorEntryPressed(String),
+    CopyToClipboard(String),
+    /// The REPL after evaluating inputs on a worker thread, with what each of
+    /// them produced, or `None` if the worker died.
+    EvaluationFinished(Option<(Box<Repl>, Vec<Evaluated>)>),
+    CancelEvaluation,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -29,4 +41,43 @@ pub enum OutputHistoryItemType {
 pub struct OutputHistoryItem {
     pub value: String,
     pub kind: OutputHistoryItemType,
+    pub latex: Option<String>,
+    pub mathml: Option<String>,
+    pub formula: Option<Formula>,
+    pub exact: Option<String>,
+}
+
+impl OutputHistoryItem {
+    /// An item of plain text, without typeset or exact forms.
+    pub fn new(value: String, kind: OutputHistoryItemType) -> Self {
+        OutputHistoryItem {
+            value,
+            kind,
+            latex: None,
+            mathml: None,
+            formula: None,
+            exact: None,
+        }
+    }
+}
+
+/// What the graph gains from evaluating an input.
+#[derive(Debug, Clone, Default)]
+pub struct GraphUpdate {
+    pub sequences: Vec<Vec<(f32, f32)>>,
+    pub stems: Vec<(f32, f32)>,
+    pub curves: Vec<Vec<(f32, f32)>>,
+    pub points: Vec<(f32, f32)>,
+    pub equations: Vec<Node>,
+    pub implicit_equations: Vec<Node>,
+    /// Whether the new equations need the variables and functions of the REPL.
+    pub needs_context: bool,
+}
+
+/// What evaluating an input produced, to be shown once it is done.
+#[derive(Debug, Clone, Default)]
+pub struct Evaluated {
+    pub output: Vec<OutputHistoryItem>,
+    pub graph: GraphUpdate,
+    pub gradient_function: Option<String>,
 }
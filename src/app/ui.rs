@@ -2,16 +2,26 @@ use super::constants::APP_ICON;
 use super::constants::DEFAULT_APP_WINDOW_HEIGHT;
 use super::constants::DEFAULT_APP_WINDOW_WIDTH;
 use super::constants::DEFAUTL_APP_NAME;
+use super::constants::FORMULA_FONT_SIZE;
+use super::constants::FORMULA_MAX_HEIGHT;
 use super::constants::REPL_BACKGROUND_COLOR;
 use super::constants::REPL_TEXT_INPUT_ID;
+use super::formula::Formula;
 use super::graph::Graph;
+use super::types::Evaluated;
 use super::types::MyMathBoardMessage;
 use super::types::OutputHistoryItem;
 use super::types::OutputHistoryItemType;
 use super::utils::get_board_name;
+use crate::repl::spawn_evaluation;
+use crate::repl::Markup;
+use crate::repl::Plot;
 use crate::repl::Repl;
-use evalexpr::build_operator_tree;
+use crate::repl::ReplError;
 use iced::application;
+use iced::event;
+use iced::futures::channel::oneshot;
+use iced::keyboard;
 use iced::widget::button;
 use iced::widget::canvas;
 use iced::widget::container;
@@ -27,6 +37,7 @@ use iced::widget::Text;
 use iced::widget::TextInput;
 use iced::window;
 use iced::window::Settings;
+use iced::Alignment;
 use iced::Background;
 use iced::Border;
 use iced::Color;
@@ -36,6 +47,7 @@ use iced::Length;
 use iced::Point;
 use iced::Rectangle;
 use iced::Size;
+use iced::Subscription;
 use iced::Task;
 use image::ImageFormat;
 use image::RgbaImage;
@@ -55,6 +67,9 @@ pub struct MyMathBoardApp {
     repl_should_input_be_in_focus: bool,
     current_open_file_path: Option<String>,
     board_has_unsaved_changes: bool,
+    inspector_is_open: bool,
+    gradient_function: Option<String>,
+    is_evaluating: bool,
 }
 
 impl MyMathBoardApp {
@@ -80,14 +95,15 @@ impl MyMathBoardApp {
             icon: Some(window::icon::from_file_data(APP_ICON, Some(ImageFormat::Ico)).unwrap()),
             ..Settings::default()
         })
-        .run_with(|| MyMathBoardApp::new())
+        .subscription(MyMathBoardApp::subscription)
+        .run_with(MyMathBoardApp::new)
     }
 
     /// Get a new instance. You should prefer using the start() method.
     pub fn new() -> (Self, Task<MyMathBoardMessage>) {
-        let app = MyMathBoardApp {
+        let mut app = MyMathBoardApp {
             graph: Graph::default(),
-            repl: Repl::new(),
+            repl: Repl::with_prelude(),
             repl_input: String::new(),
             repl_input_id: REPL_TEXT_INPUT_ID.to_string(),
             repl_input_history: Vec::new(),
@@ -95,8 +111,19 @@ impl MyMathBoardApp {
             repl_should_input_be_in_focus: true,
             current_open_file_path: None,
             board_has_unsaved_changes: false,
+            inspector_is_open: false,
+            gradient_function: None,
+            is_evaluating: false,
         };
 
+        // Mistakes in the prelude would otherwise go unnoticed.
+        for error in app.repl.prelude_errors() {
+            app.repl_output_history.push(OutputHistoryItem::new(
+                format!("=> {}\n", ReplError::Prelude(error.clone())),
+                OutputHistoryItemType::ErrOutput,
+            ));
+        }
+
         let initial_task = text_input::focus(text_input::Id::new(app.repl_input_id.clone()));
 
         (app, initial_task)
@@ -117,6 +144,8 @@ impl MyMathBoardApp {
 
                 self.graph.last_cursor_position = Some(position);
 
+                self.graph.drag_origin = Some(position);
+
                 self.graph.width = width;
 
                 self.graph.height = height;
@@ -128,6 +157,29 @@ impl MyMathBoardApp {
 
                 Task::none()
             }
+            MyMathBoardMessage::GraphClicked(x, y) => {
+                self.graph.is_dragging = false;
+
+                // The REPL shares its budget with the one evaluating, which it
+                // would count against, and the gradient may be redefined.
+                if self.is_evaluating {
+                    return Task::none();
+                }
+
+                if let Some(function) = &self.gradient_function {
+                    let point = crate::repl::Point {
+                        x: x as f64,
+                        y: y as f64,
+                    };
+                    if let Ok(gradient) = self.repl.gradient_at(function, point) {
+                        self.graph
+                            .arrows
+                            .push(((x, y), (gradient.x as f32, gradient.y as f32)));
+                    }
+                }
+
+                Task::none()
+            }
             MyMathBoardMessage::ZoomIn => {
                 self.graph.cell_size = (self.graph.cell_size as f32 * 1.1).ceil() as u64;
 
@@ -139,10 +191,9 @@ impl MyMathBoardApp {
                 Task::none()
             }
             MyMathBoardMessage::DrawEquation(equation) => {
-                let node_formation = build_operator_tree(&equation);
-
-                if node_formation.is_ok() {
-                    self.graph.equations.push(node_formation.unwrap());
+                if let Ok(node) = self.repl.plot_expression(&equation) {
+                    self.graph.equations.push(node);
+                    self.graph.context = self.repl.graph_context();
                 }
 
                 Task::none()
@@ -153,19 +204,24 @@ impl MyMathBoardApp {
                 Task::none()
             }
             MyMathBoardMessage::InputSubmitted => {
+                if self.is_evaluating {
+                    return Task::none();
+                }
+
                 if self.current_open_file_path.is_some() {
                     self.board_has_unsaved_changes = true;
                 }
 
                 self.repl_input_history.push(self.repl_input.clone());
 
-                self.process_repl_input();
-
-                self.repl_input.clear();
+                let input = std::mem::take(&mut self.repl_input);
 
                 self.repl_should_input_be_in_focus = true;
 
-                text_input::focus(self.repl_input_id.clone())
+                Task::batch([
+                    self.start_evaluation(vec![input]),
+                    text_input::focus(self.repl_input_id.clone()),
+                ])
             }
             MyMathBoardMessage::ClearRepl => {
                 if self.current_open_file_path.is_some() {
@@ -178,7 +234,7 @@ impl MyMathBoardApp {
                 Task::none()
             }
             MyMathBoardMessage::ExportGraph => iced::window::get_latest()
-                .and_then(move |window| iced::window::screenshot(window))
+                .and_then(iced::window::screenshot)
                 .then(move |screenshot| {
                     if let Some(path) = FileDialog::new().add_filter("png", &["png"]).save_file() {
                         // Approximate calculation for the graph pane dimensions
@@ -195,10 +251,10 @@ impl MyMathBoardApp {
 
                         // Define the cropping region using the graph's position and dimensions
                         let crop_region = Rectangle {
-                            x: graph_x as u32,
-                            y: graph_y as u32,
-                            width: graph_width as u32,
-                            height: graph_height as u32,
+                            x: graph_x,
+                            y: graph_y,
+                            width: graph_width,
+                            height: graph_height,
                         };
 
                         // Crop the screenshot to the defined region
@@ -230,6 +286,10 @@ impl MyMathBoardApp {
                 Task::none()
             }
             MyMathBoardMessage::OpenPressed => {
+                if self.is_evaluating {
+                    return Task::none();
+                }
+
                 if let Some(path) = FileDialog::new()
                     .add_filter("MyMathBoard", &["mymathboard"])
                     .pick_file()
@@ -242,10 +302,7 @@ impl MyMathBoardApp {
 
                     if self.load_from_file(&path_str).is_ok() {
                         self.current_open_file_path = Some(path_str);
-                        for command in &self.repl_input_history.clone() {
-                            self.repl_input = command.clone();
-                            self.process_repl_input();
-                        }
+                        return self.start_evaluation(self.repl_input_history.clone());
                     }
                 }
                 Task::none()
@@ -261,54 +318,125 @@ impl MyMathBoardApp {
                 }
                 Task::none()
             }
+            MyMathBoardMessage::ToggleInspector => {
+                self.inspector_is_open = !self.inspector_is_open;
+
+                Task::none()
+            }
+            MyMathBoardMessage::ToggleExactForms => {
+                // The REPL is replaced by the one evaluating once it is done.
+                if self.is_evaluating {
+                    return Task::none();
+                }
+
+                self.repl.set_exact_forms(!self.repl.exact_forms());
+
+                Task::none()
+            }
+            MyMathBoardMessage::InspectorEntryPressed(name) => {
+                self.repl_input.push_str(&name);
+
+                text_input::focus(self.repl_input_id.clone())
+            }
+            MyMathBoardMessage::CopyToClipboard(contents) => iced::clipboard::write(contents),
+            MyMathBoardMessage::EvaluationFinished(evaluation) => {
+                self.is_evaluating = false;
+
+                match evaluation {
+                    Some((repl, results)) => self.finish_evaluation(*repl, results),
+                    None => self.repl_output_history.push(OutputHistoryItem::new(
+                        "=> evaluation stopped unexpectedly\n".to_string(),
+                        OutputHistoryItemType::ErrOutput,
+                    )),
+                }
+                self.repl.resume();
+
+                text_input::focus(self.repl_input_id.clone())
+            }
+            MyMathBoardMessage::CancelEvaluation => {
+                if self.is_evaluating {
+                    self.repl.cancel();
+                }
+
+                Task::none()
+            }
         }
     }
 
-    pub fn process_repl_input(&mut self) {
-        if self.repl_input.starts_with("draw(") && self.repl_input.ends_with(")") {
-            let equation = self
-                .repl_input
-                .strip_prefix("draw(")
-                .unwrap()
-                .strip_suffix(")")
-                .unwrap();
-
-            let result = "";
+    /// Esc cancels a running evaluation, even from the REPL input, which
+    /// otherwise takes the key.
+    pub fn subscription(&self) -> Subscription<MyMathBoardMessage> {
+        if !self.is_evaluating {
+            return Subscription::none();
+        }
 
-            let node_formation = build_operator_tree(&equation);
+        event::listen_with(|event, _status, _window| match event {
+            iced::Event::Keyboard(keyboard::Event::KeyPressed {
+                key: keyboard::Key::Named(keyboard::key::Named::Escape),
+                ..
+            }) => Some(MyMathBoardMessage::CancelEvaluation),
+            _ => None,
+        })
+    }
 
-            if node_formation.is_ok() {
-                self.graph.equations.push(node_formation.unwrap());
+    // Evaluate the inputs one after the other on a worker thread, so that the
+    // window stays responsive, on a clone of the REPL which replaces it once
+    // they are done. The clone shares the budget, so it can be cancelled here.
+    fn start_evaluation(&mut self, inputs: Vec<String>) -> Task<MyMathBoardMessage> {
+        self.is_evaluating = true;
+        self.repl.resume();
+
+        let mut repl = self.repl.clone();
+        let (sender, receiver) = oneshot::channel();
+
+        let worker = spawn_evaluation(move || {
+            let mut results = Vec::new();
+            for input in &inputs {
+                if repl.is_cancelled() {
+                    break;
+                }
+                results.push(evaluate_input(&mut repl, input));
             }
+            let _ = sender.send((Box::new(repl), results));
+        });
 
-            self.repl_output_history.push(OutputHistoryItem {
-                value: format!(">>> {}\n", self.repl_input),
-                kind: OutputHistoryItemType::PreviousInput,
-            });
+        if worker.is_err() {
+            return Task::done(MyMathBoardMessage::EvaluationFinished(None));
+        }
 
-            self.repl_output_history.push(OutputHistoryItem {
-                value: format!("=> {:?}\n", result),
-                kind: OutputHistoryItemType::OkOutput,
-            });
-        } else {
-            let result = self.repl.process_input(&self.repl_input);
+        Task::perform(receiver, |evaluation| {
+            MyMathBoardMessage::EvaluationFinished(evaluation.ok())
+        })
+    }
 
-            self.repl_output_history.push(OutputHistoryItem {
-                value: format!(">>> {}\n", self.repl_input),
-                kind: OutputHistoryItemType::PreviousInput,
-            });
+    fn finish_evaluation(&mut self, repl: Repl, results: Vec<Evaluated>) {
+        self.repl = repl;
+
+        for evaluated in results {
+            self.repl_output_history.extend(evaluated.output);
+
+            let graph = evaluated.graph;
+            self.graph.sequences.extend(graph.sequences);
+            self.graph.stems.extend(graph.stems);
+            self.graph.curves.extend(graph.curves);
+            self.graph.points.extend(graph.points);
+            self.graph.equations.extend(graph.equations);
+            self.graph
+                .implicit_equations
+                .extend(graph.implicit_equations);
+            if graph.needs_context {
+                self.graph.context = self.repl.graph_context();
+            }
 
-            self.repl_output_history.push(OutputHistoryItem {
-                value: format!("=> {:?}\n", result),
-                kind: match result {
-                    Ok(_) => OutputHistoryItemType::OkOutput,
-                    Err(_) => OutputHistoryItemType::ErrOutput,
-                },
-            });
+            if evaluated.gradient_function.is_some() {
+                self.gradient_function = evaluated.gradient_function;
+            }
         }
+
+        self.graph.number_format = self.repl.number_format();
     }
 
-    pub fn view(&self) -> Element<MyMathBoardMessage> {
+    pub fn view(&self) -> Element<'_, MyMathBoardMessage> {
         // CONTROL BAR
         let open_button = Button::new(Text::new("OPEN").size(14))
             .on_press(MyMathBoardMessage::OpenPressed)
@@ -340,6 +468,16 @@ impl MyMathBoardApp {
                 text_color: Color::WHITE,
                 ..Default::default()
             });
+        let inspector_button = Button::new(Text::new("VARS").size(14))
+            .on_press(MyMathBoardMessage::ToggleInspector)
+            .height(25)
+            .padding(2)
+            .style(|_theme, _status| button::Style {
+                background: Some(Background::Color(Color::from_rgb8(52, 134, 235))),
+                border: Border::default(),
+                text_color: Color::WHITE,
+                ..Default::default()
+            });
         let control_bar = Row::new()
             .push(Space::with_width(Length::Fixed(10.0)))
             .push(
@@ -357,6 +495,8 @@ impl MyMathBoardApp {
             .push(Space::with_width(Length::Fixed(10.0)))
             .push(save_as_button)
             .push(Space::with_width(Length::Fixed(10.0)))
+            .push(inspector_button)
+            .push(Space::with_width(Length::Fixed(10.0)))
             .height(Length::FillPortion(4))
             .width(Length::Fill);
 
@@ -400,10 +540,19 @@ impl MyMathBoardApp {
             self.graph.height,
         );
 
-        let coords_text = format!(
-            "x: {:5.1}, y: {:5.1}",
-            current_screen_point_x, current_screen_point_y
-        );
+        let number_format = self.repl.number_format();
+        let coords_text = if number_format.is_default() {
+            format!(
+                "x: {:5.1}, y: {:5.1}",
+                current_screen_point_x, current_screen_point_y
+            )
+        } else {
+            format!(
+                "x: {}, y: {}",
+                number_format.format(current_screen_point_x as f64),
+                number_format.format(current_screen_point_y as f64)
+            )
+        };
 
         let coords_display = Text::new(coords_text).color(Color::WHITE).size(16);
 
@@ -476,29 +625,116 @@ impl MyMathBoardApp {
             .height(25)
             .padding(2);
 
+        let exact_button = Button::new(
+            Text::new(if self.repl.exact_forms() {
+                "EXACT: ON"
+            } else {
+                "EXACT: OFF"
+            })
+            .color(Color::WHITE)
+            .size(14),
+        )
+        .on_press(MyMathBoardMessage::ToggleExactForms)
+        .style(|_theme, _status| button::Style {
+            background: Some(Background::Color(Color::from_rgb8(52, 134, 235))),
+            border: Border::default(),
+            text_color: Color::WHITE,
+            ..Default::default()
+        })
+        .height(25)
+        .padding(2);
+
+        let busy_indicator = Text::new(if self.is_evaluating {
+            "EVALUATING... (Esc to cancel)"
+        } else {
+            ""
+        })
+        .color(Color::from_rgb8(255, 235, 59))
+        .size(14);
+
         let bottom_bar = Row::new()
+            .push(busy_indicator)
             .push(Space::with_width(Length::Fill))
+            .push(exact_button)
+            .push(Space::with_width(Length::Fixed(10.0)))
             .push(clear_button)
             .height(Length::Fixed(30.0))
             .padding(5);
 
+        let copy_button = |label: &str, contents: &str| {
+            Button::new(Text::new(label.to_string()).size(12))
+                .on_press(MyMathBoardMessage::CopyToClipboard(contents.to_string()))
+                .padding(2)
+                .style(|_theme, _status| button::Style {
+                    background: Some(Background::Color(Color::from_rgb8(52, 134, 235))),
+                    border: Border::default(),
+                    text_color: Color::WHITE,
+                    ..Default::default()
+                })
+        };
+
         let mut repl_output = self.repl_output_history.iter().fold(
             Column::new().spacing(5).width(Length::Fill),
             |column, entry| {
-                column.push(
-                    Container::new(
-                        Text::new(&entry.value)
-                            .color(match entry.kind {
-                                OutputHistoryItemType::PreviousInput => Color::from_rgb8(0, 200, 0),
-                                OutputHistoryItemType::ErrOutput => Color::from_rgb8(255, 0, 0),
-                                OutputHistoryItemType::OkOutput => Color::from_rgb8(255, 255, 255),
-                            })
-                            .size(16)
-                            .font(Font::MONOSPACE),
-                    )
-                    .padding(2)
-                    .width(Length::Fill),
-                )
+                // Results that are mathematics are shown typeset, and can be
+                // copied as LaTeX or MathML.
+                let mut row = match &entry.formula {
+                    Some(formula) => Row::new()
+                        .spacing(5)
+                        .align_y(Alignment::Center)
+                        .push(
+                            Container::new(
+                                Text::new("=>")
+                                    .color(Color::from_rgb8(255, 255, 255))
+                                    .size(16)
+                                    .font(Font::MONOSPACE),
+                            )
+                            .padding(2),
+                        )
+                        .push(
+                            iced::widget::canvas(formula.clone())
+                                .width(Length::Fixed(formula.width()))
+                                .height(Length::Fixed(formula.height())),
+                        )
+                        .push(Space::with_width(Length::Fill)),
+                    None => Row::new().spacing(5).push(
+                        Container::new(
+                            Text::new(&entry.value)
+                                .color(match entry.kind {
+                                    OutputHistoryItemType::PreviousInput => {
+                                        Color::from_rgb8(0, 200, 0)
+                                    }
+                                    OutputHistoryItemType::ErrOutput => Color::from_rgb8(255, 0, 0),
+                                    OutputHistoryItemType::OkOutput => {
+                                        Color::from_rgb8(255, 255, 255)
+                                    }
+                                })
+                                .size(16)
+                                .font(Font::MONOSPACE),
+                        )
+                        .padding(2)
+                        .width(Length::Fill),
+                    ),
+                };
+                if let Some(exact) = &entry.exact {
+                    row = row.push(
+                        Container::new(
+                            Text::new(format!("= {}", exact))
+                                .color(Color::from_rgb8(0, 191, 255))
+                                .size(16)
+                                .font(Font::MONOSPACE),
+                        )
+                        .padding(2),
+                    );
+                }
+                if let Some(latex) = &entry.latex {
+                    row = row.push(copy_button("TeX", latex));
+                }
+                if let Some(mathml) = &entry.mathml {
+                    row = row.push(copy_button("MathML", mathml));
+                }
+
+                column.push(row)
             },
         );
 
@@ -562,19 +798,32 @@ impl MyMathBoardApp {
             ..Default::default()
         });
 
+        let mut panes = Row::new()
+            .push(graphing_pane)
+            .push(vertical_divider)
+            .push(repl_pane);
+
+        if self.inspector_is_open {
+            panes = panes
+                .push(
+                    Container::new(Space::with_width(Length::Fixed(1.0)))
+                        .height(Length::Fill)
+                        .style(|_theme| container::Style {
+                            background: Some(Background::Color(Color::WHITE)),
+                            ..Default::default()
+                        }),
+                )
+                .push(self.inspector_pane());
+        }
+
         let content = Column::new()
             .push(Space::with_height(Length::Fixed(2.0)))
             .push(control_bar)
             .push(horizontal_divider_top)
             .push(
-                Container::new(
-                    Row::new()
-                        .push(graphing_pane)
-                        .push(vertical_divider)
-                        .push(repl_pane),
-                )
-                .height(Length::FillPortion(95))
-                .width(Length::Fill),
+                Container::new(panes)
+                    .height(Length::FillPortion(95))
+                    .width(Length::Fill),
             );
 
         Container::new(content)
@@ -588,6 +837,59 @@ impl MyMathBoardApp {
             .into()
     }
 
+    /// The side panel listing everything defined in the REPL.
+    fn inspector_pane(&self) -> Element<'_, MyMathBoardMessage> {
+        let entries = self.repl.bindings().into_iter().fold(
+            Column::new().spacing(5).width(Length::Fill),
+            |column, binding| {
+                column.push(
+                    Button::new(
+                        Column::new()
+                            .push(
+                                Text::new(format!("{} ({})", binding.name, binding.kind))
+                                    .color(Color::from_rgb8(0, 200, 0))
+                                    .size(14)
+                                    .font(Font::MONOSPACE),
+                            )
+                            .push(
+                                Text::new(binding.value)
+                                    .color(Color::WHITE)
+                                    .size(14)
+                                    .font(Font::MONOSPACE),
+                            ),
+                    )
+                    .on_press(MyMathBoardMessage::InspectorEntryPressed(binding.name))
+                    .padding(2)
+                    .width(Length::Fill)
+                    .style(|_theme, _status| button::Style {
+                        background: None,
+                        border: Border::default(),
+                        text_color: Color::WHITE,
+                        ..Default::default()
+                    }),
+                )
+            },
+        );
+
+        Container::new(
+            Scrollable::new(entries)
+                .height(Length::Fill)
+                .width(Length::Fill),
+        )
+        .height(Length::Fill)
+        .width(Length::Fixed(220.0))
+        .padding(5)
+        .style(|_| container::Style {
+            background: Some(Background::Color(Color::from_rgb8(
+                REPL_BACKGROUND_COLOR.0,
+                REPL_BACKGROUND_COLOR.1,
+                REPL_BACKGROUND_COLOR.2,
+            ))),
+            ..Default::default()
+        })
+        .into()
+    }
+
     fn save_to_file(&self, file_path: &str) -> Result<(), std::io::Error> {
         let mut file = File::create(file_path)?;
 
@@ -609,3 +911,209 @@ impl MyMathBoardApp {
         Ok(())
     }
 }
+
+/// Evaluate an input on the REPL into the output and the additions to the graph
+/// it produces. This runs on a worker thread, as it may take long.
+fn evaluate_input(repl: &mut Repl, input: &str) -> Evaluated {
+    let mut evaluated = Evaluated::default();
+
+    if input.starts_with("draw_seq(") && input.ends_with(")") {
+        let arguments = input
+            .strip_prefix("draw_seq(")
+            .unwrap()
+            .strip_suffix(")")
+            .unwrap();
+
+        let result = repl.sequence_points(arguments);
+
+        evaluated.output.push(OutputHistoryItem::new(
+            format!(">>> {}\n", input),
+            OutputHistoryItemType::PreviousInput,
+        ));
+
+        match result {
+            Ok(points) => {
+                evaluated.graph.sequences.push(
+                    points
+                        .iter()
+                        .map(|point| (point.x as f32, point.y as f32))
+                        .collect(),
+                );
+
+                evaluated.output.push(OutputHistoryItem::new(
+                    format!("=> {:?}\n", ""),
+                    OutputHistoryItemType::OkOutput,
+                ));
+            }
+            Err(error) => {
+                evaluated.output.push(OutputHistoryItem::new(
+                    format!("=> {}\n", repl.format_result(&Err(error))),
+                    OutputHistoryItemType::ErrOutput,
+                ));
+            }
+        }
+    } else if input.starts_with("draw_spectrum(") && input.ends_with(")") {
+        let signal = input
+            .strip_prefix("draw_spectrum(")
+            .unwrap()
+            .strip_suffix(")")
+            .unwrap();
+
+        let result = repl.spectrum_points(signal);
+
+        evaluated.output.push(OutputHistoryItem::new(
+            format!(">>> {}\n", input),
+            OutputHistoryItemType::PreviousInput,
+        ));
+
+        match result {
+            Ok(points) => {
+                evaluated
+                    .graph
+                    .stems
+                    .extend(points.iter().map(|point| (point.x as f32, point.y as f32)));
+
+                evaluated.output.push(OutputHistoryItem::new(
+                    format!("=> {:?}\n", ""),
+                    OutputHistoryItemType::OkOutput,
+                ));
+            }
+            Err(error) => {
+                evaluated.output.push(OutputHistoryItem::new(
+                    format!("=> {}\n", repl.format_result(&Err(error))),
+                    OutputHistoryItemType::ErrOutput,
+                ));
+            }
+        }
+    } else if input.starts_with("draw_grad(") && input.ends_with(")") {
+        let function = input
+            .strip_prefix("draw_grad(")
+            .unwrap()
+            .strip_suffix(")")
+            .unwrap()
+            .trim()
+            .to_string();
+
+        // Show the gradient, and draw it as an arrow wherever the graph is clicked.
+        let result = repl.process_input(&format!("grad({}, (x, y))", function));
+        if result.is_ok() {
+            evaluated.gradient_function = Some(function);
+        }
+
+        evaluated.output.push(OutputHistoryItem::new(
+            format!(">>> {}\n", input),
+            OutputHistoryItemType::PreviousInput,
+        ));
+
+        evaluated.output.push(OutputHistoryItem::new(
+            format!("=> {}\n", repl.format_result(&result)),
+            match result {
+                Ok(_) => OutputHistoryItemType::OkOutput,
+                Err(_) => OutputHistoryItemType::ErrOutput,
+            },
+        ));
+    } else if input.starts_with("draw(") && input.ends_with(")") {
+        let equation = input
+            .strip_prefix("draw(")
+            .unwrap()
+            .strip_suffix(")")
+            .unwrap();
+
+        let result = "";
+
+        let equations = repl.plot_equations(equation).unwrap_or_default();
+        let has_equations = !equations.is_empty();
+        if has_equations {
+            evaluated.graph.implicit_equations.extend(equations);
+            evaluated.graph.needs_context = true;
+        }
+
+        match repl.plot_points(equation) {
+            Ok(Plot::Curves(curves)) => {
+                evaluated
+                    .graph
+                    .curves
+                    .extend(curves.into_iter().map(|curve| {
+                        curve
+                            .into_iter()
+                            .map(|point| (point.x as f32, point.y as f32))
+                            .collect()
+                    }));
+            }
+            Ok(Plot::Point(point)) => {
+                evaluated
+                    .graph
+                    .points
+                    .push((point.x as f32, point.y as f32));
+            }
+            Err(_) if has_equations => {}
+            Err(_) => {
+                if let Ok(node) = repl.plot_expression(equation) {
+                    evaluated.graph.equations.push(node);
+                    evaluated.graph.needs_context = true;
+                    evaluated.graph.points.extend(
+                        repl.interpolation_nodes(equation)
+                            .into_iter()
+                            .map(|point| (point.x as f32, point.y as f32)),
+                    );
+                }
+            }
+        }
+
+        evaluated.output.push(OutputHistoryItem::new(
+            format!(">>> {}\n", input),
+            OutputHistoryItemType::PreviousInput,
+        ));
+
+        let mathml = repl.typeset(equation, Markup::MathMl).ok();
+
+        evaluated.output.push(OutputHistoryItem {
+            value: format!("=> {:?}\n", result),
+            kind: OutputHistoryItemType::OkOutput,
+            latex: repl.typeset(equation, Markup::Latex).ok(),
+            formula: mathml
+                .as_deref()
+                .and_then(|mathml| Formula::from_mathml(mathml, FORMULA_FONT_SIZE))
+                .filter(|formula| formula.height() <= FORMULA_MAX_HEIGHT),
+            mathml,
+            exact: None,
+        });
+    } else {
+        let result = repl.process_input(input);
+
+        evaluated.output.push(OutputHistoryItem::new(
+            format!(">>> {}\n", input),
+            OutputHistoryItemType::PreviousInput,
+        ));
+
+        let exact = result
+            .as_ref()
+            .ok()
+            .and_then(|result| repl.recognised_form(result));
+
+        let (latex, mathml) = match &result {
+            Ok(result) => (
+                repl.typeset_entry(input, result, Markup::Latex),
+                repl.typeset_entry(input, result, Markup::MathMl),
+            ),
+            Err(_) => (None, None),
+        };
+
+        evaluated.output.push(OutputHistoryItem {
+            value: format!("=> {}\n", repl.format_result(&result)),
+            kind: match result {
+                Ok(_) => OutputHistoryItemType::OkOutput,
+                Err(_) => OutputHistoryItemType::ErrOutput,
+            },
+            latex,
+            formula: mathml
+                .as_deref()
+                .and_then(|mathml| Formula::from_mathml(mathml, FORMULA_FONT_SIZE))
+                .filter(|formula| formula.height() <= FORMULA_MAX_HEIGHT),
+            mathml,
+            exact,
+        });
+    }
+
+    evaluated
+}
@@ -0,0 +1,200 @@
+use mymathboard::repl::spawn_evaluation;
+use mymathboard::repl::Repl;
+use mymathboard::repl::ReplError;
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use std::error::Error;
+use std::io::BufRead;
+use std::io::IsTerminal;
+use std::io::Write;
+use std::process::ExitCode;
+
+/// Inputs that draw on the graph of the GUI, which the terminal does not have.
+const DRAW_COMMANDS: &[&str] = &["draw(", "draw_seq(", "draw_spectrum(", "draw_grad("];
+
+/// The input history, next to the prelude in the user config directory.
+const HISTORY_FILE: &str = "mymathboard/history.txt";
+
+/// The REPL of MyMathBoard in a terminal. Interactively it has line editing and
+/// a history kept across sessions. With inputs piped in, e.g. from a script, it
+/// prints the output of each and fails if any of them does.
+fn main() -> ExitCode {
+    let mut exact_forms = false;
+    for argument in std::env::args().skip(1) {
+        match argument.as_str() {
+            "--exact" => exact_forms = true,
+            _ => {
+                eprintln!("usage: mymathboard-cli [--exact] < input");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    // Everything runs on a thread with the stack the GUI evaluates on, which holds
+    // the deepest recursion the limits allow.
+    match spawn_evaluation(move || run(exact_forms)).map(|session| session.join()) {
+        Ok(Ok(status)) => status,
+        Ok(Err(_)) => ExitCode::FAILURE,
+        Err(error) => {
+            eprintln!("{}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(exact_forms: bool) -> ExitCode {
+    let mut repl = Repl::with_prelude();
+    repl.set_exact_forms(exact_forms);
+
+    for error in repl.prelude_errors() {
+        eprintln!("=> {}", ReplError::Prelude(error.clone()));
+    }
+
+    if std::io::stdin().is_terminal() {
+        interactive(&mut repl)
+    } else {
+        script(&mut repl, std::io::stdin().lock(), std::io::stdout().lock())
+    }
+}
+
+fn interactive(repl: &mut Repl) -> ExitCode {
+    let mut editor = match DefaultEditor::new() {
+        Ok(editor) => editor,
+        Err(error) => {
+            eprintln!("{}", error);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let history = dirs::config_dir().map(|dir| dir.join(HISTORY_FILE));
+    if let Some(path) = &history {
+        let _ = editor.load_history(path);
+    }
+
+    loop {
+        match editor.readline(">>> ") {
+            Ok(line) => {
+                let input = line.trim();
+                if input.is_empty() {
+                    continue;
+                }
+
+                let _ = editor.add_history_entry(input);
+                println!("{}", evaluate(repl, input).0);
+            }
+            // Ctrl-C drops the line being typed, Ctrl-D quits.
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(error) => {
+                eprintln!("{}", error);
+                break;
+            }
+        }
+    }
+
+    if let Some(path) = &history {
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        let _ = editor.save_history(path);
+    }
+
+    ExitCode::SUCCESS
+}
+
+// Evaluate every line of a script, skipping blank lines and `#` comments, and
+// write the output of each. Fails if any of them does.
+fn script(repl: &mut Repl, input: impl BufRead, mut output: impl Write) -> ExitCode {
+    let mut status = ExitCode::SUCCESS;
+
+    for line in input.lines() {
+        let Ok(line) = line else {
+            return ExitCode::FAILURE;
+        };
+
+        let input = line.trim();
+        if input.is_empty() || input.starts_with('#') {
+            continue;
+        }
+
+        let (result, is_ok) = evaluate(repl, input);
+        if writeln!(output, "{}", result).is_err() {
+            return ExitCode::FAILURE;
+        }
+        if !is_ok {
+            status = ExitCode::FAILURE;
+        }
+    }
+
+    status
+}
+
+// The output of an input as the GUI shows it, followed by its exact form if
+// there is one, and whether it succeeded.
+fn evaluate(repl: &mut Repl, input: &str) -> (String, bool) {
+    let result = if DRAW_COMMANDS
+        .iter()
+        .any(|command| input.starts_with(command))
+    {
+        let error = ReplError::Unsupported("drawing in the terminal".to_string());
+        Err(Box::new(error) as Box<dyn Error>)
+    } else {
+        repl.process_input(input)
+    };
+
+    let mut output = format!("=> {}", repl.format_result(&result));
+    if let Some(exact) = result
+        .as_ref()
+        .ok()
+        .and_then(|result| repl.recognised_form(result))
+    {
+        output.push_str(&format!("  = {}", exact));
+    }
+
+    (output, result.is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_script(input: &str) -> (ExitCode, String) {
+        let mut output = Vec::new();
+        let status = script(&mut Repl::new(), input.as_bytes(), &mut output);
+        (status, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn script_prints_each_output() {
+        let (status, output) = run_script("# a comment\n\nx = 2\n  x^2 + 1\n");
+        assert_eq!(status, ExitCode::SUCCESS);
+        assert_eq!(output, "=> Ok(Empty)\n=> Ok(Number(5.0))\n");
+    }
+
+    #[test]
+    fn script_fails_if_any_input_fails() {
+        let (status, output) = run_script("1 + 1\nundefined_name\n2 + 2\n");
+        assert_eq!(status, ExitCode::FAILURE);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("=> Err("));
+        assert_eq!(lines[2], "=> Ok(Number(4.0))");
+    }
+
+    #[test]
+    fn draw_commands_are_unsupported() {
+        for command in [
+            "draw(x^2)",
+            "draw_seq(a)",
+            "draw_spectrum((1, 0))",
+            "draw_grad(f)",
+        ] {
+            let (status, output) = run_script(command);
+            assert_eq!(status, ExitCode::FAILURE);
+            assert_eq!(
+                output.trim_end(),
+                "=> Err(Unsupported(\"drawing in the terminal\"))"
+            );
+        }
+    }
+}
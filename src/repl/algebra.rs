@@ -0,0 +1,1020 @@
+use super::forms::expect_arguments;
+use super::symbolic::Expr;
+use super::Repl;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+/// Powers of sums above this are left as powers rather than multiplied out.
+const EXPAND_MAX_EXPONENT: f64 = 64.0;
+
+/// Coefficients this small relative to the largest one are rounding error.
+const COEFFICIENT_TOLERANCE: f64 = 1e-12;
+
+/// `factor` works over the rationals, reading each coefficient as a fraction
+/// with a denominator up to this.
+const FACTOR_MAX_DENOMINATOR: i128 = 10_000;
+
+/// The largest leading or constant coefficient whose divisors `factor` tries
+/// as rational roots.
+const FACTOR_MAX_COEFFICIENT: i128 = 1_000_000_000_000;
+
+/// The exponent of each atom in a term, keyed by the atom's source.
+type Powers = BTreeMap<String, f64>;
+
+/// A coefficient times a product of powers of atoms.
+#[derive(Debug, Clone)]
+struct Term {
+    coefficient: f64,
+    powers: Powers,
+}
+
+/// A sum of terms. Atoms are the variables, along with anything that is not a
+/// sum, product or power, such as `sin(x)` or a sum in a denominator.
+#[derive(Debug, Clone, Default)]
+struct Polynomial {
+    terms: Vec<Term>,
+    atoms: BTreeMap<String, Expr>,
+}
+
+/// Whether products and powers of sums are multiplied out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Collect,
+    Expand,
+}
+
+impl Repl {
+    /// `simplify(expr)`, `expand(expr)` and `factor(expr)`, the expression
+    /// rewritten with user functions inlined.
+    pub(super) fn eval_algebra(
+        &mut self,
+        name: &str,
+        arguments: &[String],
+    ) -> Result<Expr, Box<dyn Error>> {
+        expect_arguments(arguments, 1)?;
+
+        let expanded = self.expand_expression_forms(&arguments[0])?;
+        let expression = self.inline_functions(&Expr::parse(&expanded)?)?;
+
+        Ok(match name {
+            "simplify" => simplify(&expression),
+            "expand" => expand(&expression),
+            _ => factor(&expression),
+        })
+    }
+}
+
+/// A rational expression with common factors cancelled, otherwise the shortest
+/// of the expression with like terms collected, fully expanded, and as written.
+/// Ties go to the first of these, so that like terms are collected even when
+/// that is no shorter.
+pub fn simplify(expression: &Expr) -> Expr {
+    let (numerator, denominator) = fraction(expression);
+    let (numerator, denominator, is_reduced) = cancel(numerator, denominator);
+    if is_reduced {
+        return quotient(&numerator, &denominator);
+    }
+
+    let mut collected = polynomial(expression, Mode::Collect);
+    collected.pythagorean();
+    let mut expanded = polynomial(expression, Mode::Expand);
+    expanded.pythagorean();
+
+    [
+        collected.to_expr(),
+        expanded.to_expr(),
+        expression.simplify(),
+        quotient(&numerator, &denominator),
+    ]
+    .into_iter()
+    .min_by_key(|candidate| candidate.to_string().replace(' ', "").len())
+    .unwrap()
+}
+
+/// Multiply out every product and integer power of sums and collect like terms.
+pub fn expand(expression: &Expr) -> Expr {
+    polynomial(expression, Mode::Expand).to_expr()
+}
+
+/// Factor over the rationals: the common factor of all terms, then linear factors
+/// of polynomials in one variable, or homogeneous polynomials in two.
+pub fn factor(expression: &Expr) -> Expr {
+    let (numerator, denominator) = fraction(expression);
+    let (numerator, denominator, _) = cancel(numerator, denominator);
+
+    match denominator.nonzero_constant() {
+        Some(value) => factor_polynomial(&numerator.scale(1.0 / value)),
+        None => factor_polynomial(&numerator) / factor_polynomial(&denominator),
+    }
+}
+
+/// The coefficients of the numerator of an expression as a polynomial in
+/// `variable`, lowest power first, once common factors with the denominator
+/// are cancelled. None unless it is a polynomial in `variable` alone.
+pub fn numerator_coefficients(expression: &Expr, variable: &str) -> Option<Vec<f64>> {
+    let (numerator, denominator) = fraction(expression);
+    let (numerator, _, _) = cancel(numerator, denominator);
+
+    if let Some(value) = numerator.constant_value() {
+        return Some(vec![value]);
+    }
+
+    match numerator.dense()? {
+        (key, coefficients) if key == variable => Some(coefficients),
+        _ => None,
+    }
+}
+
+/// The coefficient of each variable and the constant term of an expression that
+/// is linear in `variables`. None if it is not.
+pub fn linear_coefficients(expression: &Expr, variables: &[String]) -> Option<(Vec<f64>, f64)> {
+    let mut coefficients = vec![0.0; variables.len()];
+    let mut constant = 0.0;
+
+    for term in polynomial(expression, Mode::Expand).terms {
+        match term.powers.iter().collect::<Vec<_>>().as_slice() {
+            [] => constant += term.coefficient,
+            [(key, exponent)] if **exponent == 1.0 => {
+                let index = variables.iter().position(|variable| variable == *key)?;
+                coefficients[index] += term.coefficient;
+            }
+            _ => return None,
+        }
+    }
+
+    Some((coefficients, constant))
+}
+
+// The polynomial in atoms equal to an expression.
+fn polynomial(expression: &Expr, mode: Mode) -> Polynomial {
+    match expression {
+        Expr::Number(value) => Polynomial::constant(*value),
+        Expr::Variable(_) => Polynomial::atom(expression.clone(), 1.0),
+        Expr::Neg(u) => polynomial(u, mode).scale(-1.0),
+        Expr::Add(u, v) => polynomial(u, mode).add(&polynomial(v, mode)),
+        Expr::Sub(u, v) => polynomial(u, mode).add(&polynomial(v, mode).scale(-1.0)),
+        Expr::Mul(u, v) => {
+            let (p, q) = (polynomial(u, mode), polynomial(v, mode));
+            if mode == Mode::Expand || p.terms.len() <= 1 || q.terms.len() <= 1 {
+                p.mul(&q)
+            } else {
+                p.as_atom(1.0).mul(&q.as_atom(1.0))
+            }
+        }
+        Expr::Div(u, v) => {
+            let q = polynomial(v, mode);
+            match q.reciprocal() {
+                Some(reciprocal) => polynomial(u, mode).mul(&reciprocal),
+                None => Polynomial::atom(expression.clone(), 1.0),
+            }
+        }
+        Expr::Pow(u, v) => {
+            let (p, q) = (polynomial(u, mode), polynomial(v, mode));
+            match q.constant_value() {
+                Some(exponent) => p.pow(exponent, mode),
+                None => Polynomial::atom(p.to_expr().pow(q.to_expr()), 1.0),
+            }
+        }
+        Expr::Call(name, arguments) => {
+            let arguments: Vec<Expr> = arguments
+                .iter()
+                .map(|argument| polynomial(argument, mode).to_expr())
+                .collect();
+
+            match (name.as_str(), arguments.as_slice()) {
+                ("sqrt", [u]) => polynomial(u, mode).pow(0.5, mode),
+                ("ln", [Expr::Call(inner, u)]) | ("exp", [Expr::Call(inner, u)])
+                    if u.len() == 1 && (name == "ln") == (inner == "exp") =>
+                {
+                    polynomial(&u[0], mode)
+                }
+                _ => Polynomial::atom(Expr::Call(name.clone(), arguments), 1.0),
+            }
+        }
+    }
+}
+
+// A pair of expanded polynomials whose quotient is the expression, with every
+// fraction brought over a common denominator. Common factors are left for
+// `cancel` to divide out.
+fn fraction(expression: &Expr) -> (Polynomial, Polynomial) {
+    let one = || Polynomial::constant(1.0);
+
+    let (numerator, denominator) = match expression {
+        Expr::Neg(u) => {
+            let (n, d) = fraction(u);
+            (n.scale(-1.0), d)
+        }
+        Expr::Add(u, v) | Expr::Sub(u, v) => {
+            let sign = if matches!(expression, Expr::Sub(..)) {
+                -1.0
+            } else {
+                1.0
+            };
+            let (n1, d1) = fraction(u);
+            let (n2, d2) = fraction(v);
+            if d1.to_expr() == d2.to_expr() {
+                (n1.add(&n2.scale(sign)), d1)
+            } else {
+                (n1.mul(&d2).add(&n2.mul(&d1).scale(sign)), d1.mul(&d2))
+            }
+        }
+        Expr::Mul(u, v) => {
+            let (n1, d1) = fraction(u);
+            let (n2, d2) = fraction(v);
+            (n1.mul(&n2), d1.mul(&d2))
+        }
+        Expr::Div(u, v) => {
+            let (n1, d1) = fraction(u);
+            let (n2, d2) = fraction(v);
+            if n2.terms.is_empty() {
+                return (polynomial(expression, Mode::Expand), one());
+            }
+            (n1.mul(&d2), d1.mul(&n2))
+        }
+        Expr::Pow(u, v) => match v.simplify().as_number() {
+            Some(exponent) if exponent.fract() == 0.0 && exponent.abs() <= EXPAND_MAX_EXPONENT => {
+                let (n, d) = fraction(u);
+                let (n, d) = if exponent < 0.0 { (d, n) } else { (n, d) };
+                (
+                    n.pow(exponent.abs(), Mode::Expand),
+                    d.pow(exponent.abs(), Mode::Expand),
+                )
+            }
+            _ => (polynomial(expression, Mode::Expand), one()),
+        },
+        _ => (polynomial(expression, Mode::Expand), one()),
+    };
+
+    (numerator, denominator)
+}
+
+// Divide out what the numerator and denominator have in common: a constant
+// denominator, shared powers of atoms, and the greatest common divisor of
+// polynomials in the same single atom. Also tells whether anything but a
+// constant was divided out.
+fn cancel(numerator: Polynomial, denominator: Polynomial) -> (Polynomial, Polynomial, bool) {
+    if let Some(value) = denominator.nonzero_constant() {
+        return (
+            numerator.scale(1.0 / value),
+            Polynomial::constant(1.0),
+            false,
+        );
+    }
+
+    let common = numerator.common_powers(&denominator);
+    let mut is_reduced = !common.is_empty();
+    let (mut numerator, mut denominator) = (
+        numerator.divide_powers(&common),
+        denominator.divide_powers(&common),
+    );
+
+    if let (Some((x, n)), Some((y, d))) = (numerator.dense(), denominator.dense()) {
+        if x == y {
+            let divisor = dense_gcd(&n, &d);
+            if divisor.len() > 1 {
+                is_reduced = true;
+                let atom = numerator.atoms[&x].clone();
+                numerator = Polynomial::from_dense(&dense_divide(&n, &divisor), &x, &atom);
+                denominator = Polynomial::from_dense(&dense_divide(&d, &divisor), &x, &atom);
+            }
+        }
+    }
+
+    if let Some(value) = denominator.nonzero_constant() {
+        return (
+            numerator.scale(1.0 / value),
+            Polynomial::constant(1.0),
+            is_reduced,
+        );
+    }
+
+    // Keep the denominator's leading coefficient positive.
+    if denominator.sorted_terms()[0].coefficient < 0.0 {
+        (numerator.scale(-1.0), denominator.scale(-1.0), is_reduced)
+    } else {
+        (numerator, denominator, is_reduced)
+    }
+}
+
+fn quotient(numerator: &Polynomial, denominator: &Polynomial) -> Expr {
+    match denominator.nonzero_constant() {
+        Some(value) => numerator.scale(1.0 / value).to_expr(),
+        None => numerator.to_expr() / denominator.to_expr(),
+    }
+}
+
+impl Polynomial {
+    fn constant(value: f64) -> Polynomial {
+        let mut polynomial = Polynomial::default();
+        if value != 0.0 {
+            polynomial.terms.push(Term {
+                coefficient: value,
+                powers: Powers::new(),
+            });
+        }
+        polynomial
+    }
+
+    fn atom(expression: Expr, exponent: f64) -> Polynomial {
+        let key = expression.to_source();
+        let mut polynomial = Polynomial::default();
+        polynomial.terms.push(Term {
+            coefficient: 1.0,
+            powers: Powers::from([(key.clone(), exponent)]),
+        });
+        polynomial.atoms.insert(key, expression);
+        polynomial
+    }
+
+    // The whole polynomial as a single atom, such as `(x + 1)`.
+    fn as_atom(&self, exponent: f64) -> Polynomial {
+        Polynomial::atom(self.to_expr(), exponent)
+    }
+
+    fn constant_value(&self) -> Option<f64> {
+        match self.terms.as_slice() {
+            [] => Some(0.0),
+            [term] if term.powers.is_empty() => Some(term.coefficient),
+            _ => None,
+        }
+    }
+
+    // The value of a non-zero constant polynomial.
+    fn nonzero_constant(&self) -> Option<f64> {
+        self.constant_value().filter(|value| *value != 0.0)
+    }
+
+    fn scale(&self, factor: f64) -> Polynomial {
+        let mut result = self.clone();
+        for term in &mut result.terms {
+            term.coefficient *= factor;
+        }
+        result.collect()
+    }
+
+    fn add(&self, other: &Polynomial) -> Polynomial {
+        let mut result = self.clone();
+        result.terms.extend(other.terms.iter().cloned());
+        result.atoms.extend(other.atoms.clone());
+        result.collect()
+    }
+
+    fn mul(&self, other: &Polynomial) -> Polynomial {
+        let mut result = Polynomial {
+            terms: Vec::new(),
+            atoms: self.atoms.clone(),
+        };
+        result.atoms.extend(other.atoms.clone());
+
+        for a in &self.terms {
+            for b in &other.terms {
+                let mut powers = a.powers.clone();
+                for (key, exponent) in &b.powers {
+                    *powers.entry(key.clone()).or_insert(0.0) += exponent;
+                }
+                powers.retain(|_, exponent| *exponent != 0.0);
+
+                result.terms.push(Term {
+                    coefficient: a.coefficient * b.coefficient,
+                    powers,
+                });
+            }
+        }
+
+        result.collect()
+    }
+
+    fn pow(&self, exponent: f64, mode: Mode) -> Polynomial {
+        if let Some(value) = self.constant_value() {
+            let power = value.powf(exponent);
+            if power.is_finite() {
+                return Polynomial::constant(power);
+            }
+        }
+
+        let is_integer = exponent.fract() == 0.0;
+
+        match self.terms.as_slice() {
+            // A single term raised to an integer power, or a lone atom to any power.
+            [term]
+                if is_integer
+                    || (term.coefficient == 1.0
+                        && term.powers.len() == 1
+                        && term.powers.values().all(|e| *e == 1.0)) =>
+            {
+                let mut result = self.clone();
+                result.terms[0].coefficient = term.coefficient.powf(exponent);
+                for value in result.terms[0].powers.values_mut() {
+                    *value *= exponent;
+                }
+                result
+            }
+            _ if mode == Mode::Expand
+                && is_integer
+                && (0.0..=EXPAND_MAX_EXPONENT).contains(&exponent) =>
+            {
+                (0..exponent as usize).fold(Polynomial::constant(1.0), |power, _| power.mul(self))
+            }
+            _ => self.as_atom(exponent),
+        }
+    }
+
+    // `1/p` as a polynomial in atoms, or nothing when `p` is zero.
+    fn reciprocal(&self) -> Option<Polynomial> {
+        match self.terms.as_slice() {
+            [] => None,
+            [_] => Some(self.pow(-1.0, Mode::Collect)),
+            _ => Some(self.as_atom(-1.0)),
+        }
+    }
+
+    // Merge terms with the same powers and drop those that cancel.
+    fn collect(mut self) -> Polynomial {
+        let mut terms: Vec<Term> = Vec::new();
+        for term in self.terms {
+            match terms.iter_mut().find(|other| other.powers == term.powers) {
+                Some(other) => other.coefficient += term.coefficient,
+                None => terms.push(term),
+            }
+        }
+
+        let largest = terms
+            .iter()
+            .map(|term| term.coefficient.abs())
+            .fold(0.0, f64::max);
+        terms.retain(|term| {
+            term.coefficient != 0.0 && term.coefficient.abs() > COEFFICIENT_TOLERANCE * largest
+        });
+
+        self.terms = terms;
+        self
+    }
+
+    // Replace `sin(u)^2 + cos(u)^2`, times any common factor, by one.
+    fn pythagorean(&mut self) {
+        while let Some((i, j, rest)) = self.pythagorean_pair() {
+            self.terms[i].powers = rest;
+            self.terms.remove(j);
+            *self = std::mem::take(self).collect();
+        }
+    }
+
+    // Two terms `c*m*sin(u)^2` and `c*m*cos(u)^2`, and the powers in `m`.
+    fn pythagorean_pair(&self) -> Option<(usize, usize, Powers)> {
+        for (i, term) in self.terms.iter().enumerate() {
+            for (sine, exponent) in &term.powers {
+                let Some(argument) = sine.strip_prefix("sin(") else {
+                    continue;
+                };
+                if *exponent < 2.0 {
+                    continue;
+                }
+
+                let mut rest = term.powers.clone();
+                *rest.get_mut(sine).unwrap() -= 2.0;
+                rest.retain(|_, exponent| *exponent != 0.0);
+                let mut partner = rest.clone();
+                *partner.entry(format!("cos({}", argument)).or_insert(0.0) += 2.0;
+
+                let found = self.terms.iter().position(|other| {
+                    other.powers == partner
+                        && (other.coefficient - term.coefficient).abs()
+                            <= COEFFICIENT_TOLERANCE * term.coefficient.abs()
+                });
+                if let Some(j) = found {
+                    return Some((i, j, rest));
+                }
+            }
+        }
+
+        None
+    }
+
+    // The atoms every term has a positive power of, with the smallest such power,
+    // across this polynomial and another.
+    fn common_powers(&self, other: &Polynomial) -> Powers {
+        let terms: Vec<&Term> = self.terms.iter().chain(&other.terms).collect();
+        let Some(first) = terms.first() else {
+            return Powers::new();
+        };
+
+        first
+            .powers
+            .keys()
+            .filter_map(|key| {
+                terms
+                    .iter()
+                    .map(|term| term.powers.get(key).copied().unwrap_or(0.0))
+                    .try_fold(f64::INFINITY, |least, exponent| {
+                        (exponent > 0.0).then(|| least.min(exponent))
+                    })
+                    .map(|least| (key.clone(), least))
+            })
+            .collect()
+    }
+
+    fn divide_powers(&self, powers: &Powers) -> Polynomial {
+        let mut result = self.clone();
+        for term in &mut result.terms {
+            for (key, exponent) in powers {
+                if let Some(value) = term.powers.get_mut(key) {
+                    *value -= exponent;
+                }
+            }
+            term.powers.retain(|_, exponent| *exponent != 0.0);
+        }
+        result
+    }
+
+    // The coefficients of a polynomial in at most one atom with non-negative
+    // integer powers, lowest power first.
+    fn dense(&self) -> Option<(String, Vec<f64>)> {
+        let mut atom = None;
+        let mut coefficients = Vec::new();
+
+        for term in &self.terms {
+            let power = match term.powers.iter().collect::<Vec<_>>().as_slice() {
+                [] => 0,
+                [(key, exponent)] if exponent.fract() == 0.0 && **exponent > 0.0 => {
+                    if *atom.get_or_insert_with(|| (*key).clone()) != **key {
+                        return None;
+                    }
+                    **exponent as usize
+                }
+                _ => return None,
+            };
+
+            if coefficients.len() <= power {
+                coefficients.resize(power + 1, 0.0);
+            }
+            coefficients[power] += term.coefficient;
+        }
+
+        Some((atom?, coefficients))
+    }
+
+    fn from_dense(coefficients: &[f64], key: &str, atom: &Expr) -> Polynomial {
+        let mut polynomial = Polynomial::default();
+        polynomial.atoms.insert(key.to_string(), atom.clone());
+
+        for (power, coefficient) in coefficients.iter().enumerate() {
+            let mut powers = Powers::new();
+            if power > 0 {
+                powers.insert(key.to_string(), power as f64);
+            }
+            polynomial.terms.push(Term {
+                coefficient: *coefficient,
+                powers,
+            });
+        }
+
+        polynomial.collect()
+    }
+
+    // Highest degree first, ties broken by the atoms in alphabetical order.
+    fn sorted_terms(&self) -> Vec<&Term> {
+        let degree = |term: &Term| term.powers.values().sum::<f64>();
+        let mut terms: Vec<&Term> = self.terms.iter().collect();
+
+        terms.sort_by(|a, b| {
+            degree(b).total_cmp(&degree(a)).then_with(|| {
+                let exponents = |term: &Term| {
+                    self.atoms
+                        .keys()
+                        .map(|key| term.powers.get(key).copied().unwrap_or(0.0))
+                        .collect::<Vec<f64>>()
+                };
+                exponents(b)
+                    .partial_cmp(&exponents(a))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+        });
+
+        terms
+    }
+
+    fn to_expr(&self) -> Expr {
+        let mut result: Option<Expr> = None;
+
+        for term in self.sorted_terms() {
+            let magnitude = self.term_expr(term.coefficient.abs(), &term.powers);
+            result = Some(match (result, term.coefficient < 0.0) {
+                (None, false) => magnitude,
+                (None, true) => -magnitude,
+                (Some(sum), false) => sum + magnitude,
+                (Some(sum), true) => sum - magnitude,
+            });
+        }
+
+        result.unwrap_or(Expr::Number(0.0))
+    }
+
+    // A positive coefficient times powers of atoms, with negative powers written
+    // as a denominator and halves as square roots.
+    fn term_expr(&self, coefficient: f64, powers: &Powers) -> Expr {
+        let factor = |key: &String, exponent: f64| {
+            let atom = self.atoms[key].clone();
+            match exponent {
+                1.0 => atom,
+                0.5 => Expr::call("sqrt", vec![atom]),
+                _ => atom.pow(Expr::Number(exponent)),
+            }
+        };
+        let product = |factors: Vec<Expr>| factors.into_iter().reduce(|a, b| a * b);
+
+        let numerator = product(
+            powers
+                .iter()
+                .filter(|(_, exponent)| **exponent > 0.0)
+                .map(|(key, exponent)| factor(key, *exponent))
+                .collect(),
+        );
+        let denominator = product(
+            powers
+                .iter()
+                .filter(|(_, exponent)| **exponent < 0.0)
+                .map(|(key, exponent)| factor(key, -exponent))
+                .collect(),
+        );
+
+        // Write `x/2` rather than `0.5*x`.
+        let inverse = 1.0 / coefficient;
+        let (coefficient, divisor) = if coefficient < 1.0 && inverse.fract() == 0.0 {
+            (1.0, inverse)
+        } else {
+            (coefficient, 1.0)
+        };
+
+        let numerator = match (numerator, coefficient) {
+            (Some(numerator), 1.0) => numerator,
+            (Some(numerator), _) => Expr::Number(coefficient) * numerator,
+            (None, _) => Expr::Number(coefficient),
+        };
+        let denominator = match (denominator, divisor) {
+            (Some(denominator), 1.0) => Some(denominator),
+            (Some(denominator), _) => Some(Expr::Number(divisor) * denominator),
+            (None, 1.0) => None,
+            (None, _) => Some(Expr::Number(divisor)),
+        };
+
+        match denominator {
+            Some(denominator) => numerator / denominator,
+            None => numerator,
+        }
+    }
+}
+
+// The greatest common divisor of two polynomials, by Euclid's algorithm, monic.
+fn dense_gcd(a: &[f64], b: &[f64]) -> Vec<f64> {
+    let scale = a
+        .iter()
+        .chain(b)
+        .map(|value| value.abs())
+        .fold(0.0, f64::max);
+    let trim = |mut p: Vec<f64>| {
+        while p.last().is_some_and(|c| c.abs() <= 1e-9 * scale) {
+            p.pop();
+        }
+        p
+    };
+
+    let (mut a, mut b) = (trim(a.to_vec()), trim(b.to_vec()));
+    while !b.is_empty() {
+        let remainder = trim(dense_remainder(&a, &b));
+        a = b;
+        b = remainder;
+    }
+
+    let leading = a.last().copied().unwrap_or(1.0);
+    a.iter().map(|c| c / leading).collect()
+}
+
+fn dense_remainder(a: &[f64], b: &[f64]) -> Vec<f64> {
+    let mut remainder = a.to_vec();
+    while remainder.len() >= b.len() {
+        let factor = remainder.last().unwrap() / b.last().unwrap();
+        let shift = remainder.len() - b.len();
+        for (i, c) in b.iter().enumerate() {
+            remainder[shift + i] -= factor * c;
+        }
+        remainder.pop();
+    }
+    remainder
+}
+
+fn dense_divide(a: &[f64], b: &[f64]) -> Vec<f64> {
+    let mut remainder = a.to_vec();
+    let mut quotient = vec![0.0; a.len().saturating_sub(b.len()) + 1];
+    while remainder.len() >= b.len() {
+        let factor = remainder.last().unwrap() / b.last().unwrap();
+        let shift = remainder.len() - b.len();
+        quotient[shift] = factor;
+        for (i, c) in b.iter().enumerate() {
+            remainder[shift + i] -= factor * c;
+        }
+        remainder.pop();
+    }
+    quotient
+}
+
+// The factored form of an expanded polynomial.
+fn factor_polynomial(polynomial: &Polynomial) -> Expr {
+    if polynomial.constant_value().is_some() {
+        return polynomial.to_expr();
+    }
+
+    // Take out the largest common factor of the coefficients, as a fraction,
+    // along with the sign of the leading term.
+    let integers = polynomial
+        .terms
+        .iter()
+        .map(|term| rational(term.coefficient))
+        .collect::<Option<Vec<(i128, i128)>>>();
+    let leading_sign = polynomial.sorted_terms()[0].coefficient.signum();
+    let content = match &integers {
+        Some(fractions) => {
+            let numerator = fractions.iter().fold(0, |g, (p, _)| gcd(g, *p));
+            let denominator = fractions.iter().fold(1, |l, (_, q)| l / gcd(l, *q) * q);
+            leading_sign * numerator as f64 / denominator as f64
+        }
+        None => leading_sign,
+    };
+
+    let common = polynomial.common_powers(&Polynomial::default());
+    let rest = polynomial.divide_powers(&common).scale(1.0 / content);
+
+    let mut factors: Vec<Expr> = common
+        .iter()
+        .map(|(key, exponent)| Polynomial::atom(polynomial.atoms[key].clone(), *exponent).to_expr())
+        .collect();
+    factors.extend(factor_primitive(&rest));
+
+    match content {
+        1.0 => {}
+        -1.0 => factors[0] = -factors[0].clone(),
+        _ => factors.insert(0, Expr::Number(content)),
+    }
+
+    factors.into_iter().reduce(|a, b| a * b).unwrap()
+}
+
+// Split a polynomial with coprime integer coefficients into linear factors
+// `q*x - p` for its rational roots `p/q`, and what is left. This works for
+// polynomials in one atom, and for homogeneous ones in two, `q*x - p*y`.
+fn factor_primitive(polynomial: &Polynomial) -> Vec<Expr> {
+    if polynomial.constant_value() == Some(1.0) {
+        return Vec::new();
+    }
+
+    let whole = || vec![polynomial.to_expr()];
+
+    let used: Vec<&String> = polynomial
+        .atoms
+        .keys()
+        .filter(|key| {
+            polynomial
+                .terms
+                .iter()
+                .any(|term| term.powers.contains_key(*key))
+        })
+        .collect();
+
+    let (x, y) = match used.as_slice() {
+        [x] => ((*x).clone(), None),
+        [x, y] => ((*x).clone(), Some((*y).clone())),
+        _ => return whole(),
+    };
+
+    // Coefficients by the power of `x`; with two atoms every term must have
+    // the same total degree.
+    let mut degree = None;
+    let mut coefficients: Vec<i128> = Vec::new();
+    for term in &polynomial.terms {
+        let power = term.powers.get(&x).copied().unwrap_or(0.0);
+        let total = term.powers.values().sum::<f64>();
+        let other = term
+            .powers
+            .keys()
+            .any(|key| *key != x && Some(key) != y.as_ref());
+        let coefficient = term.coefficient.round();
+
+        if other
+            || power.fract() != 0.0
+            || power < 0.0
+            || total.fract() != 0.0
+            || (term.coefficient - coefficient).abs() > 1e-9
+            || (y.is_some() && *degree.get_or_insert(total) != total)
+        {
+            return whole();
+        }
+
+        let power = power as usize;
+        if coefficients.len() <= power {
+            coefficients.resize(power + 1, 0);
+        }
+        coefficients[power] += coefficient as i128;
+    }
+
+    let atom = |key: &String| polynomial.atoms[key].clone();
+    let mut factors: Vec<(Expr, u32)> = Vec::new();
+
+    while coefficients.len() > 1 {
+        let Some((p, q)) = rational_root(&coefficients) else {
+            break;
+        };
+        coefficients = divide_linear(&coefficients, p, q);
+
+        let mut linear = Polynomial::atom(atom(&x), 1.0).scale(q as f64);
+        let constant = match &y {
+            Some(y) => Polynomial::atom(atom(y), 1.0).scale(p as f64),
+            None => Polynomial::constant(p as f64),
+        };
+        linear = linear.add(&constant.scale(-1.0));
+        let linear = linear.to_expr();
+
+        match factors.iter_mut().find(|(factor, _)| *factor == linear) {
+            Some((_, multiplicity)) => *multiplicity += 1,
+            None => factors.push((linear, 1)),
+        }
+    }
+
+    if factors.is_empty() {
+        return whole();
+    }
+
+    let mut result: Vec<Expr> = factors
+        .into_iter()
+        .map(|(factor, multiplicity)| match multiplicity {
+            1 => factor,
+            _ => factor.pow(Expr::Number(multiplicity as f64)),
+        })
+        .collect();
+
+    // What is left, written back in `x` (and `y`).
+    if coefficients.len() > 1 || coefficients.first().is_some_and(|c| *c != 1) {
+        let degree = coefficients.len() - 1;
+        let mut rest = Polynomial::constant(0.0);
+        for (power, coefficient) in coefficients.iter().enumerate() {
+            let mut term = Polynomial::constant(*coefficient as f64);
+            if power > 0 {
+                term = term.mul(&Polynomial::atom(atom(&x), power as f64));
+            }
+            if let (Some(y), true) = (&y, power < degree) {
+                term = term.mul(&Polynomial::atom(atom(y), (degree - power) as f64));
+            }
+            rest = rest.add(&term);
+        }
+        result.push(rest.to_expr());
+    }
+
+    result
+}
+
+// A root `p/q` of a polynomial with integer coefficients, lowest power first,
+// by the rational root theorem.
+pub fn rational_root(coefficients: &[i128]) -> Option<(i128, i128)> {
+    let constant = coefficients[0];
+    let leading = *coefficients.last()?;
+
+    if constant == 0 {
+        return Some((0, 1));
+    }
+    if constant.abs() > FACTOR_MAX_COEFFICIENT || leading.abs() > FACTOR_MAX_COEFFICIENT {
+        return None;
+    }
+
+    let mut candidates: Vec<(i128, i128)> = Vec::new();
+    for q in divisors(leading) {
+        for p in divisors(constant) {
+            if gcd(p, q) == 1 {
+                candidates.push((p, q));
+                candidates.push((-p, q));
+            }
+        }
+    }
+    candidates.sort_by(|a, b| (a.0 * b.1).cmp(&(b.0 * a.1)));
+
+    candidates.into_iter().find(|&(p, q)| {
+        // q^n P(p/q), exactly, as long as it does not overflow.
+        let n = coefficients.len() - 1;
+        let mut total: Option<i128> = Some(0);
+        for (i, c) in coefficients.iter().enumerate() {
+            total = total.and_then(|total| {
+                let term = c
+                    .checked_mul(p.checked_pow(i as u32)?)?
+                    .checked_mul(q.checked_pow((n - i) as u32)?)?;
+                total.checked_add(term)
+            });
+        }
+        total == Some(0)
+    })
+}
+
+// Divide by `q*x - p`, which is exact when `p/q` is a root.
+pub fn divide_linear(coefficients: &[i128], p: i128, q: i128) -> Vec<i128> {
+    let n = coefficients.len() - 1;
+    let mut quotient = vec![0; n];
+    quotient[n - 1] = coefficients[n] / q;
+    for i in (1..n).rev() {
+        quotient[i - 1] = (coefficients[i] + p * quotient[i]) / q;
+    }
+    quotient
+}
+
+fn divisors(value: i128) -> Vec<i128> {
+    let value = value.abs();
+    let mut result = Vec::new();
+    let mut d = 1;
+    while d * d <= value {
+        if value % d == 0 {
+            result.push(d);
+            if d * d != value {
+                result.push(value / d);
+            }
+        }
+        d += 1;
+    }
+    result
+}
+
+pub fn gcd(a: i128, b: i128) -> i128 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+// A coefficient as a fraction `p/q`, if it is one with a small denominator.
+pub fn rational(value: f64) -> Option<(i128, i128)> {
+    let (mut p0, mut q0, mut p1, mut q1) = (0_i128, 1_i128, 1_i128, 0_i128);
+    let mut x = value.abs();
+
+    for _ in 0..32 {
+        let a = x.floor();
+        if a > 1e15 {
+            return None;
+        }
+        let a = a as i128;
+        (p0, q0, p1, q1) = (p1, q1, a * p1 + p0, a * q1 + q0);
+
+        if q1 > FACTOR_MAX_DENOMINATOR {
+            return None;
+        }
+        if (p1 as f64 / q1 as f64 - value.abs()).abs() <= 1e-9 * value.abs().max(1.0) {
+            return Some((p1 * value.signum() as i128, q1));
+        }
+
+        let fraction = x - a as f64;
+        if fraction == 0.0 {
+            break;
+        }
+        x = 1.0 / fraction;
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repl::ReplResult;
+
+    fn rewrite(input: &str) -> String {
+        match Repl::new().process_input(input).unwrap() {
+            ReplResult::Expression(expression) => expression,
+            result => panic!("{} gave {:?}", input, result),
+        }
+    }
+
+    #[test]
+    fn simplify_cancels_and_collects() {
+        assert_eq!(rewrite("simplify((x^2 - 1)/(x - 1))"), "x + 1");
+        assert_eq!(rewrite("simplify(sin(x)^2 + cos(x)^2)"), "1");
+        assert_eq!(rewrite("simplify(x + x)"), "2*x");
+        assert_eq!(rewrite("simplify(x + 1 + x)"), "2*x + 1");
+    }
+
+    #[test]
+    fn expand_multiplies_out() {
+        assert_eq!(rewrite("expand((x + 1)^2)"), "x^2 + 2*x + 1");
+        assert_eq!(rewrite("expand((x + y)*(x - y))"), "x^2 - y^2");
+    }
+
+    #[test]
+    fn factor_finds_rational_roots() {
+        assert_eq!(rewrite("factor(x^2 - 5x + 6)"), "(x - 2)*(x - 3)");
+        assert_eq!(rewrite("factor(2x^2 - 2)"), "2*(x + 1)*(x - 1)");
+        assert_eq!(rewrite("factor(x^2 - y^2)"), "(x + y)*(x - y)");
+    }
+
+    #[test]
+    fn coefficients_as_fractions() {
+        assert_eq!(rational(0.75), Some((3, 4)));
+        assert_eq!(rational(-2.0 / 3.0), Some((-2, 3)));
+        assert_eq!(rational(std::f64::consts::PI), None);
+        assert_eq!(gcd(-12, 18), 6);
+        assert_eq!(rational_root(&[6, -5, 1]), Some((2, 1)));
+    }
+}
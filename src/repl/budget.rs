@@ -0,0 +1,295 @@
+use super::ReplError;
+use evalexpr::EvalexprError;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use std::time::Instant;
+
+/// The stack of the threads that evaluations run on, see `spawn_evaluation`.
+pub const EVALUATION_STACK_SIZE: usize = 64 << 20;
+
+/// The most stack a user function call takes, with room to spare. Debug
+/// builds take the most, a little under 40 KB a call.
+const CALL_STACK_SIZE: usize = 64 << 10;
+
+/// The largest limits that can be set, far beyond anything useful, which keep
+/// the arithmetic on them from overflowing. The recursion depth is what the
+/// evaluation stack holds.
+const MAX_SECONDS: f64 = 86_400.0;
+const MAX_RECURSION_DEPTH: f64 = (EVALUATION_STACK_SIZE / CALL_STACK_SIZE) as f64;
+const MAX_MEGABYTES: f64 = 1e6;
+
+/// How much a single evaluation may use before it is stopped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    pub time: Duration,
+    /// The deepest nesting of user function calls, which stops runaway
+    /// recursion before it overflows the stack. The default fits the stack of
+    /// the main thread, deeper needs the stack of `spawn_evaluation`.
+    pub recursion_depth: usize,
+    /// Bytes held by lists and memoised terms built during the evaluation.
+    pub memory: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            time: Duration::from_secs(5),
+            recursion_depth: 100,
+            memory: 64 << 20,
+        }
+    }
+}
+
+impl fmt::Display for Limits {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} s, {} calls deep, {} MB",
+            self.time.as_secs_f64(),
+            self.recursion_depth,
+            self.memory >> 20
+        )
+    }
+}
+
+impl Limits {
+    /// Read the arguments of `limits(seconds, depth, megabytes)`.
+    pub fn parse(arguments: &[f64]) -> Result<Self, ReplError> {
+        let &[seconds, depth, megabytes] = arguments else {
+            return Err(ReplError::InvalidArgument);
+        };
+        let is_valid = seconds > 0.0
+            && seconds <= MAX_SECONDS
+            && (1.0..=MAX_RECURSION_DEPTH).contains(&depth)
+            && (1.0..=MAX_MEGABYTES).contains(&megabytes);
+        if !is_valid {
+            return Err(ReplError::InvalidArgument);
+        }
+
+        Ok(Limits {
+            time: Duration::from_secs_f64(seconds),
+            recursion_depth: depth as usize,
+            memory: (megabytes as usize) << 20,
+        })
+    }
+}
+
+/// Run an evaluation on a thread with a stack that holds the deepest recursion
+/// the limits allow.
+pub fn spawn_evaluation<T: Send + 'static>(
+    evaluate: impl FnOnce() -> T + Send + 'static,
+) -> io::Result<JoinHandle<T>> {
+    thread::Builder::new()
+        .stack_size(EVALUATION_STACK_SIZE)
+        .spawn(evaluate)
+}
+
+/// What the running evaluation has used so far. It is shared with the user
+/// functions in the context, which is where runaway evaluations spend their
+/// time, so they can stop it from the inside.
+#[derive(Debug, Clone, Default)]
+pub struct Budget {
+    state: Arc<Mutex<BudgetState>>,
+}
+
+#[derive(Debug, Default)]
+struct BudgetState {
+    limits: Limits,
+    deadline: Option<Instant>,
+    depth: usize,
+    memory: usize,
+    exceeded: Option<ReplError>,
+    cancelled: bool,
+}
+
+/// The evaluation started by `Budget::start`, which ends when dropped.
+pub struct Evaluation {
+    budget: Option<Budget>,
+}
+
+/// A user function call counted by `Budget::enter`, which returns when dropped.
+pub struct Call {
+    budget: Budget,
+}
+
+impl Budget {
+    pub fn limits(&self) -> Limits {
+        self.state.lock().unwrap().limits
+    }
+
+    pub fn set_limits(&self, limits: Limits) {
+        self.state.lock().unwrap().limits = limits;
+    }
+
+    /// Start the clock on an evaluation. An evaluation started while another
+    /// is running is part of it. Outside of an evaluation, e.g. while drawing,
+    /// only the recursion depth is limited.
+    pub fn start(&self) -> Evaluation {
+        let mut state = self.state.lock().unwrap();
+        if state.deadline.is_some() {
+            return Evaluation { budget: None };
+        }
+
+        state.deadline = Some(Instant::now() + state.limits.time);
+        state.memory = 0;
+        state.exceeded = None;
+
+        Evaluation {
+            budget: Some(self.clone()),
+        }
+    }
+
+    /// Stop the running evaluation, and any started until `resume`, as soon
+    /// as they next check the budget. This is meant to be called from another
+    /// thread than the one evaluating.
+    pub fn cancel(&self) {
+        self.state.lock().unwrap().cancelled = true;
+    }
+
+    pub fn resume(&self) {
+        self.state.lock().unwrap().cancelled = false;
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.lock().unwrap().cancelled
+    }
+
+    /// Fail if the evaluation has been cancelled or has run out of time, or
+    /// already hit a limit.
+    pub fn check(&self) -> Result<(), EvalexprError> {
+        let mut state = self.state.lock().unwrap();
+        if state.deadline.is_some() && state.exceeded.is_none() {
+            if state.cancelled {
+                state.exceeded = Some(ReplError::Cancelled);
+            } else if state
+                .deadline
+                .is_some_and(|deadline| Instant::now() > deadline)
+            {
+                state.exceeded = Some(ReplError::Timeout(state.limits.time));
+            }
+        }
+
+        match &state.exceeded {
+            Some(error) => Err(EvalexprError::CustomMessage(error.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Count a user function call for as long as the returned guard lives.
+    pub fn enter(&self) -> Result<Call, EvalexprError> {
+        self.check()?;
+
+        let mut state = self.state.lock().unwrap();
+        if state.depth >= state.limits.recursion_depth {
+            let error = ReplError::RecursionLimit(state.limits.recursion_depth);
+            return Err(state.exceed(error));
+        }
+        state.depth += 1;
+
+        Ok(Call {
+            budget: self.clone(),
+        })
+    }
+
+    /// Account for memory the evaluation holds on to.
+    pub fn allocate(&self, bytes: usize) -> Result<(), EvalexprError> {
+        self.check()?;
+
+        let mut state = self.state.lock().unwrap();
+        state.memory += bytes;
+        if state.memory > state.limits.memory {
+            let error = ReplError::MemoryLimit(state.limits.memory);
+            return Err(state.exceed(error));
+        }
+
+        Ok(())
+    }
+
+    /// The result of an evaluation, unless it hit a limit. That is reported as
+    /// such even if the error was wrapped or recovered from along the way.
+    pub fn finish<T>(&self, result: Result<T, Box<dyn Error>>) -> Result<T, Box<dyn Error>> {
+        match self.state.lock().unwrap().exceeded.take() {
+            Some(error) => Err(Box::new(error)),
+            None => result,
+        }
+    }
+}
+
+impl BudgetState {
+    // Remember the limit that was hit until the evaluation finishes, so that it
+    // stops even if the error is recovered from. Outside of an evaluation there
+    // is nothing to stop, only this call fails.
+    fn exceed(&mut self, error: ReplError) -> EvalexprError {
+        let message = EvalexprError::CustomMessage(error.to_string());
+        if self.deadline.is_some() {
+            self.exceeded = Some(error);
+        }
+        message
+    }
+}
+
+impl Drop for Evaluation {
+    fn drop(&mut self) {
+        if let Some(budget) = &self.budget {
+            budget.state.lock().unwrap().deadline = None;
+        }
+    }
+}
+
+impl Drop for Call {
+    fn drop(&mut self) {
+        self.budget.state.lock().unwrap().depth -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repl::Repl;
+
+    #[test]
+    fn limits_are_parsed_within_range() {
+        let limits = Limits::parse(&[2.0, 50.0, 8.0]).unwrap();
+        assert_eq!(limits.time, Duration::from_secs(2));
+        assert_eq!(limits.recursion_depth, 50);
+        assert_eq!(limits.memory, 8 << 20);
+
+        assert!(Limits::parse(&[5.0, MAX_RECURSION_DEPTH + 1.0, 64.0]).is_err());
+        assert!(Limits::parse(&[0.0, 100.0, 64.0]).is_err());
+        assert!(Limits::parse(&[5.0, 100.0]).is_err());
+    }
+
+    #[test]
+    fn cancelling_reaches_a_reset_repl() {
+        let mut repl = Repl::new();
+        let worker = repl.clone();
+        repl.process_input("reset()").unwrap();
+
+        worker.cancel();
+        assert!(repl.is_cancelled());
+    }
+
+    #[test]
+    fn deepest_recursion_stops_before_the_stack_overflows() {
+        let result = spawn_evaluation(|| {
+            let mut repl = Repl::new();
+            let limits = format!("limits(5, {}, 64)", MAX_RECURSION_DEPTH);
+            repl.process_input(&limits).unwrap();
+            repl.process_input("f(x) = f(x - 1) + 1").unwrap();
+            repl.process_input("f(3)")
+                .map_err(|error| error.to_string())
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+
+        let error = ReplError::RecursionLimit(MAX_RECURSION_DEPTH as usize);
+        assert_eq!(result.unwrap_err(), error.to_string());
+    }
+}
@@ -0,0 +1,278 @@
+use super::forms::bound_variable;
+use super::forms::expect_arguments;
+use super::forms::is_infinity;
+use super::forms::value_to_number;
+use super::forms::Body;
+use super::symbolic::Expr;
+use super::Repl;
+use super::ReplError;
+use evalexpr::Value;
+use std::error::Error;
+
+/// Limits at a point are estimated from the values at a distance of 2^-k from
+/// it. Going much closer only adds rounding noise.
+const LIMIT_SAMPLES: i32 = 16;
+
+/// Limits at infinity are estimated from the values at 2^k.
+const LIMIT_SAMPLES_AT_INFINITY: i32 = 40;
+
+/// Steps of Richardson extrapolation applied to the samples of a limit.
+const LIMIT_RICHARDSON_STEPS: usize = 6;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Approach {
+    Left,
+    Right,
+    Both,
+}
+
+impl Repl {
+    /// `limit(expr, x, a)`, where `a` may be `inf` or `-inf`.
+    pub(super) fn eval_limit(
+        &mut self,
+        arguments: &[String],
+        bindings: &[(String, Value)],
+    ) -> Result<Value, Box<dyn Error>> {
+        self.eval_limit_from(arguments, bindings, Approach::Both)
+    }
+
+    /// `limit_left(expr, x, a)`, approaching `a` from below.
+    pub(super) fn eval_limit_left(
+        &mut self,
+        arguments: &[String],
+        bindings: &[(String, Value)],
+    ) -> Result<Value, Box<dyn Error>> {
+        self.eval_limit_from(arguments, bindings, Approach::Left)
+    }
+
+    /// `limit_right(expr, x, a)`, approaching `a` from above.
+    pub(super) fn eval_limit_right(
+        &mut self,
+        arguments: &[String],
+        bindings: &[(String, Value)],
+    ) -> Result<Value, Box<dyn Error>> {
+        self.eval_limit_from(arguments, bindings, Approach::Right)
+    }
+
+    /// `taylor(expr, x, a, n)`, the Taylor polynomial of degree `n` around `a`.
+    pub(super) fn eval_taylor(
+        &mut self,
+        arguments: &[String],
+        bindings: &[(String, Value)],
+    ) -> Result<Expr, Box<dyn Error>> {
+        expect_arguments(arguments, 4)?;
+
+        let expanded = self.expand_expression_forms(&arguments[0])?;
+        let expression = self.inline_functions(&Expr::parse(&expanded)?)?;
+        let variable = bound_variable(&arguments[1])?;
+        let center = self.evaluate_number(&arguments[2], bindings)?;
+        let degree = self.evaluate(&arguments[3], bindings)?.as_int()?;
+
+        if degree < 0 {
+            return Err(Box::new(ReplError::InvalidArgument));
+        }
+
+        let offset = if center == 0.0 {
+            Expr::Variable(variable.clone())
+        } else {
+            Expr::Variable(variable.clone()) - Expr::Number(center)
+        };
+
+        let mut polynomial = Expr::Number(0.0);
+        let mut derivative = expression;
+        let mut factorial = 1.0;
+
+        for k in 0..=degree {
+            if k > 0 {
+                derivative = derivative.derivative(&variable)?;
+                factorial *= k as f64;
+            }
+
+            let mut point = bindings.to_vec();
+            point.push((variable.clone(), Value::Float(center)));
+            let coefficient = self.evaluate_number(&derivative.to_source(), &point)? / factorial;
+
+            if !coefficient.is_finite() {
+                return Err(Box::new(ReplError::InvalidArgument));
+            }
+
+            if coefficient.abs() > 1e-15 {
+                polynomial = polynomial
+                    + Expr::Number(coefficient) * offset.clone().pow(Expr::Number(k as f64));
+            }
+        }
+
+        Ok(polynomial.simplify())
+    }
+
+    fn eval_limit_from(
+        &mut self,
+        arguments: &[String],
+        bindings: &[(String, Value)],
+        approach: Approach,
+    ) -> Result<Value, Box<dyn Error>> {
+        expect_arguments(arguments, 3)?;
+
+        let variable = bound_variable(&arguments[1])?;
+        let body = self.compile_body(&arguments[0])?;
+        let target = arguments[2].as_str();
+
+        let limit = if is_infinity(target) || target.strip_prefix('+').is_some_and(is_infinity) {
+            self.one_sided_limit(&body, bindings, &variable, f64::INFINITY, 1.0)?
+        } else if target.strip_prefix('-').is_some_and(is_infinity) {
+            self.one_sided_limit(&body, bindings, &variable, f64::NEG_INFINITY, -1.0)?
+        } else {
+            let point = self.evaluate_number(target, bindings)?;
+
+            match approach {
+                Approach::Left => self.one_sided_limit(&body, bindings, &variable, point, -1.0)?,
+                Approach::Right => self.one_sided_limit(&body, bindings, &variable, point, 1.0)?,
+                Approach::Both => {
+                    let left = self.one_sided_limit(&body, bindings, &variable, point, -1.0)?;
+                    let right = self.one_sided_limit(&body, bindings, &variable, point, 1.0)?;
+
+                    if left == right {
+                        left
+                    } else if left.is_finite() && (left - right).abs() <= 1e-6 * left.abs().max(1.0)
+                    {
+                        (left + right) / 2.0
+                    } else {
+                        return Err(Box::new(ReplError::Unsupported(format!(
+                            "the left limit {} and the right limit {} differ",
+                            left, right
+                        ))));
+                    }
+                }
+            }
+        };
+
+        Ok(Value::Float(limit))
+    }
+
+    // Sample the body ever closer to the point (or ever further out, for
+    // infinite points) and extrapolate the samples to a step of zero.
+    fn one_sided_limit(
+        &mut self,
+        body: &Body,
+        bindings: &[(String, Value)],
+        variable: &str,
+        point: f64,
+        direction: f64,
+    ) -> Result<f64, Box<dyn Error>> {
+        let mut samples = Vec::new();
+        let sample_count = if point.is_infinite() {
+            LIMIT_SAMPLES_AT_INFINITY
+        } else {
+            LIMIT_SAMPLES
+        };
+
+        for k in 0..sample_count {
+            let step = 2f64.powi(-k);
+            let x = if point.is_infinite() {
+                direction / step
+            } else {
+                point + direction * step * point.abs().max(1.0) * 0.1
+            };
+
+            let mut scope = bindings.to_vec();
+            scope.push((variable.to_string(), Value::Float(x)));
+            samples.push(value_to_number(self.evaluate_body(body, &scope)?)?);
+        }
+
+        let last = samples[samples.len() - 1];
+        let second_last = samples[samples.len() - 2];
+        let growing = samples
+            .windows(2)
+            .rev()
+            .take(8)
+            .all(|pair| pair[1].abs() > pair[0].abs() * 1.2);
+
+        if last.is_infinite() || (growing && last.abs() > 1e4) {
+            return Ok(f64::INFINITY.copysign(last));
+        }
+
+        let mut best = last;
+        let mut best_error = f64::INFINITY;
+        let mut previous_row: Vec<f64> = Vec::new();
+
+        for sample in samples {
+            let mut row = vec![sample];
+            for step in 1..=previous_row.len().min(LIMIT_RICHARDSON_STEPS) {
+                let factor = (1u64 << step) as f64 - 1.0;
+                row.push(row[step - 1] + (row[step - 1] - previous_row[step - 1]) / factor);
+            }
+
+            for (index, estimate) in row.iter().enumerate().skip(1) {
+                let error = (estimate - previous_row[index - 1]).abs();
+                if error < best_error {
+                    best = *estimate;
+                    best_error = error;
+                }
+            }
+
+            previous_row = row;
+        }
+
+        if best.is_finite() && best_error <= 1e-6 * best.abs().max(1.0) {
+            Ok(best)
+        } else if (last - second_last).abs() <= 1e-6 * last.abs().max(1.0) {
+            Ok(last)
+        } else {
+            Err(Box::new(ReplError::DidNotConverge))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repl::ReplResult;
+
+    fn result(repl: &mut Repl, input: &str) -> ReplResult {
+        repl.process_input(input).unwrap()
+    }
+
+    fn number(repl: &mut Repl, input: &str) -> f64 {
+        match result(repl, input) {
+            ReplResult::Number(value) => value,
+            result => panic!("{} gave {:?}", input, result),
+        }
+    }
+
+    #[test]
+    fn one_sided_limits() {
+        let mut repl = Repl::new();
+        assert_eq!(number(&mut repl, "limit_left(abs(x)/x, x, 0)"), -1.0);
+        assert_eq!(number(&mut repl, "limit_right(abs(x)/x, x, 0)"), 1.0);
+        assert_eq!(
+            number(&mut repl, "limit_left(1/x, x, 0)"),
+            f64::NEG_INFINITY
+        );
+        assert_eq!(number(&mut repl, "limit_right(1/x, x, 0)"), f64::INFINITY);
+        assert!(repl.process_input("limit(abs(x)/x, x, 0)").is_err());
+    }
+
+    #[test]
+    fn two_sided_limits() {
+        let mut repl = Repl::new();
+        assert!((number(&mut repl, "limit(sin(x)/x, x, 0)") - 1.0).abs() < 1e-9);
+        let e = number(&mut repl, "limit((1 + 1/x)^x, x, inf)");
+        assert!((e - std::f64::consts::E).abs() < 1e-9);
+    }
+
+    #[test]
+    fn taylor_coefficients() {
+        let mut repl = Repl::new();
+        for (input, expected) in [
+            ("taylor(1/(1-x), x, 0, 3)", "1 + x + x^2 + x^3"),
+            ("taylor(exp(x), x, 0, 2)", "1 + x + 0.5*x^2"),
+            ("taylor(ln(x), x, 1, 2)", "x - 1 - 0.5*(x - 1)^2"),
+        ] {
+            match result(&mut repl, input) {
+                ReplResult::Expression(expression) => assert_eq!(expression, expected),
+                result => panic!("{} gave {:?}", input, result),
+            }
+        }
+        assert!(repl.process_input("taylor(exp(x), x, 0, -1)").is_err());
+    }
+}
@@ -0,0 +1,188 @@
+use super::Repl;
+use super::ReplResult;
+use std::f64::consts::E;
+use std::f64::consts::LN_2;
+use std::f64::consts::PI;
+
+/// The largest denominator of a plain fraction that is recognised.
+const MAX_DENOMINATOR: i64 = 1000;
+
+/// The largest denominator of a rational multiple of a constant, kept small
+/// as every constant is another chance of a coincidence.
+const MAX_CONSTANT_DENOMINATOR: i64 = 100;
+
+/// How many units in the last place a value may be off a closed form, which
+/// allows for the rounding in computing it but not for a mere coincidence.
+const TOLERANCE_ULPS: f64 = 64.0;
+
+impl Repl {
+    /// Whether results are shown with the exact form they are recognised as,
+    /// e.g. `0.7853981633974483` as `π/4`.
+    pub fn exact_forms(&self) -> bool {
+        self.exact_forms
+    }
+
+    pub fn set_exact_forms(&mut self, enabled: bool) {
+        self.exact_forms = enabled;
+    }
+
+    /// The exact form of a numeric result, if exact forms are shown and every
+    /// number in it is an integer or is recognised, and at least one is not
+    /// an integer.
+    pub fn recognised_form(&self, result: &ReplResult) -> Option<String> {
+        if !self.exact_forms {
+            return None;
+        }
+
+        let numbers: Vec<f64> = match result {
+            ReplResult::Number(value) => vec![*value],
+            ReplResult::Point(point) => vec![point.x, point.y],
+            ReplResult::Bindings(bindings) => bindings.iter().map(|(_, value)| *value).collect(),
+            _ => return None,
+        };
+        if numbers.iter().all(|value| value.fract() == 0.0) {
+            return None;
+        }
+
+        let forms = numbers
+            .iter()
+            .map(|value| {
+                if value.fract() == 0.0 {
+                    Some(format!("{}", value))
+                } else {
+                    closed_form(*value)
+                }
+            })
+            .collect::<Option<Vec<String>>>()?;
+
+        Some(match result {
+            ReplResult::Point(_) => format!("({})", forms.join(", ")),
+            ReplResult::Bindings(bindings) => bindings
+                .iter()
+                .zip(forms)
+                .map(|((name, _), form)| format!("{} = {}", name, form))
+                .collect::<Vec<String>>()
+                .join(", "),
+            _ => forms.join(""),
+        })
+    }
+}
+
+/// Recognise a number as a fraction, or as a rational multiple of one of a
+/// few constants, e.g. `1/3`, `3√2/4` or `-2π/3`. Fractions are only given
+/// when they are no longer than the decimal.
+pub fn closed_form(value: f64) -> Option<String> {
+    if !value.is_finite() || value == 0.0 || value.abs() > 1e6 || value.abs() < 1e-6 {
+        return None;
+    }
+
+    let constants = [
+        (1.0, "", MAX_DENOMINATOR),
+        (2f64.sqrt(), "√2", MAX_CONSTANT_DENOMINATOR),
+        (3f64.sqrt(), "√3", MAX_CONSTANT_DENOMINATOR),
+        (5f64.sqrt(), "√5", MAX_CONSTANT_DENOMINATOR),
+        (6f64.sqrt(), "√6", MAX_CONSTANT_DENOMINATOR),
+        (PI, "π", MAX_CONSTANT_DENOMINATOR),
+        (PI * PI, "π²", MAX_CONSTANT_DENOMINATOR),
+        (PI.sqrt(), "√π", MAX_CONSTANT_DENOMINATOR),
+        (E, "e", MAX_CONSTANT_DENOMINATOR),
+        (LN_2, "ln 2", MAX_CONSTANT_DENOMINATOR),
+    ];
+
+    constants
+        .iter()
+        .find_map(|&(constant, name, max_denominator)| {
+            let (numerator, denominator) = rational(value / constant, max_denominator)?;
+            let form = format_multiple(numerator, denominator, name);
+            // A fraction no shorter than the decimal, e.g. `123456789/1000`,
+            // tells nothing more.
+            (!name.is_empty() || form.len() <= format!("{}", value).len()).then_some(form)
+        })
+}
+
+// The fraction with the smallest denominator within tolerance of `x`, from
+// the convergents of its continued fraction.
+fn rational(x: f64, max_denominator: i64) -> Option<(i64, i64)> {
+    let (mut previous_numerator, mut numerator) = (0_i64, 1_i64);
+    let (mut previous_denominator, mut denominator) = (1_i64, 0_i64);
+    let mut remainder = x;
+
+    loop {
+        let term = remainder.floor();
+        if term.abs() > 1e9 {
+            return None;
+        }
+
+        (previous_numerator, numerator) = (numerator, term as i64 * numerator + previous_numerator);
+        (previous_denominator, denominator) = (
+            denominator,
+            term as i64 * denominator + previous_denominator,
+        );
+        if denominator > max_denominator {
+            return None;
+        }
+
+        if (numerator as f64 / denominator as f64 - x).abs()
+            <= TOLERANCE_ULPS * f64::EPSILON * x.abs()
+        {
+            return Some((numerator, denominator));
+        }
+
+        let fraction = remainder - term;
+        if fraction == 0.0 {
+            return None;
+        }
+        remainder = 1.0 / fraction;
+    }
+}
+
+// `p·c/q` written out, leaving out a coefficient or denominator of one.
+fn format_multiple(numerator: i64, denominator: i64, constant: &str) -> String {
+    let sign = if numerator < 0 { "-" } else { "" };
+    let coefficient = match (numerator.abs(), constant) {
+        (1, "") => "1".to_string(),
+        (1, _) => String::new(),
+        (coefficient, "ln 2") => format!("{} ", coefficient),
+        (coefficient, _) => coefficient.to_string(),
+    };
+
+    if denominator == 1 {
+        format!("{}{}{}", sign, coefficient, constant)
+    } else {
+        format!("{}{}{}/{}", sign, coefficient, constant, denominator)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognises_fractions_and_multiples_of_constants() {
+        assert_eq!(closed_form(0.75).as_deref(), Some("3/4"));
+        assert_eq!(closed_form(0.1 + 0.2).as_deref(), Some("3/10"));
+        assert_eq!(closed_form(-2.0 * PI / 3.0).as_deref(), Some("-2π/3"));
+        assert_eq!(
+            closed_form(3.0 * 2f64.sqrt() / 4.0).as_deref(),
+            Some("3√2/4")
+        );
+    }
+
+    #[test]
+    fn values_without_a_closed_form_are_left_alone() {
+        assert_eq!(closed_form(123456.789), None);
+        assert_eq!(closed_form(0.123456789), None);
+        assert_eq!(closed_form(1.0 / 1001.0), None);
+        assert_eq!(closed_form(13456790.0 / 109.0 + 1e-6), None);
+    }
+
+    #[test]
+    fn computed_results_are_recognised() {
+        let mut repl = Repl::new();
+        repl.set_exact_forms(true);
+        let basel = repl.process_input("sum(k, 1, inf, 1/k^2)").unwrap();
+        assert_eq!(repl.recognised_form(&basel).as_deref(), Some("π²/6"));
+        let decimal = repl.process_input("123456.789").unwrap();
+        assert_eq!(repl.recognised_form(&decimal), None);
+    }
+}
@@ -0,0 +1,515 @@
+use super::special::beta_regularized;
+use super::special::erfc;
+use super::special::gamma_p;
+use super::special::gamma_q;
+use super::special::ln_beta;
+use super::special::ln_gamma;
+use super::special::normal_quantile;
+use super::Repl;
+use evalexpr::ContextWithMutableFunctions;
+use evalexpr::EvalexprError;
+use evalexpr::Function;
+use evalexpr::Value;
+use std::cmp::Ordering;
+use std::f64::consts::PI;
+
+/// The definition of a distribution function, which returns `None` for
+/// parameters outside the distribution's domain.
+pub type Definition = fn(&[f64]) -> Option<f64>;
+
+/// A distribution function, its accepted argument counts, and its definition.
+type DistributionFunction = (&'static str, &'static [usize], Definition);
+
+/// The probability distributions. Continuous distributions have `_pdf`, `_cdf`
+/// and `_quantile` functions, discrete ones `_pmf`, `_cdf` and `_quantile`.
+/// The normal distribution defaults to the standard normal when only `x` is given.
+const DISTRIBUTION_FUNCTIONS: &[DistributionFunction] = &[
+    ("normal_pdf", &[1, 3], normal_pdf),
+    ("normal_cdf", &[1, 3], normal_cdf),
+    ("normal_quantile", &[1, 3], normal_inverse),
+    ("binomial_pmf", &[3], binomial_pmf),
+    ("binomial_cdf", &[3], binomial_cdf),
+    ("binomial_quantile", &[3], binomial_quantile),
+    ("poisson_pmf", &[2], poisson_pmf),
+    ("poisson_cdf", &[2], poisson_cdf),
+    ("poisson_quantile", &[2], poisson_quantile),
+    ("uniform_pdf", &[3], uniform_pdf),
+    ("uniform_cdf", &[3], uniform_cdf),
+    ("uniform_quantile", &[3], uniform_quantile),
+    ("exponential_pdf", &[2], exponential_pdf),
+    ("exponential_cdf", &[2], exponential_cdf),
+    ("exponential_quantile", &[2], exponential_quantile),
+    ("t_pdf", &[2], t_pdf),
+    ("t_cdf", &[2], t_cdf),
+    ("t_quantile", &[2], t_quantile),
+    ("chi2_pdf", &[2], chi2_pdf),
+    ("chi2_cdf", &[2], chi2_cdf),
+    ("chi2_quantile", &[2], chi2_quantile),
+    ("f_pdf", &[3], f_pdf),
+    ("f_cdf", &[3], f_cdf),
+    ("f_quantile", &[3], f_quantile),
+];
+
+impl Repl {
+    pub(super) fn setup_distribution_functions(&mut self) {
+        for (name, arities, definition) in DISTRIBUTION_FUNCTIONS {
+            self.context
+                .set_function(
+                    name.to_string(),
+                    Function::new(move |args| {
+                        let arguments = match args {
+                            Value::Tuple(tuple) => tuple.clone(),
+                            value => vec![value.clone()],
+                        };
+
+                        if !arities.contains(&arguments.len()) {
+                            return Err(EvalexprError::wrong_function_argument_amount(
+                                arguments.len(),
+                                arities[arities.len() - 1],
+                            ));
+                        }
+
+                        let numbers: Vec<f64> = arguments
+                            .iter()
+                            .map(Value::as_number)
+                            .collect::<Result<_, _>>()?;
+
+                        match definition(&numbers) {
+                            Some(value) => Ok(Value::Float(value)),
+                            None => Err(EvalexprError::CustomMessage(format!(
+                                "{} is not defined for these parameters",
+                                name
+                            ))),
+                        }
+                    }),
+                )
+                .unwrap();
+        }
+    }
+}
+
+/// The quantile function of a distribution named as in `sample(normal(0, 1), n)`.
+pub fn distribution_quantile(name: &str) -> Option<Definition> {
+    let quantile = format!("{}_quantile", name);
+    DISTRIBUTION_FUNCTIONS
+        .iter()
+        .find(|(function, _, _)| *function == quantile)
+        .map(|(_, _, definition)| *definition)
+}
+
+fn normal_parameters(args: &[f64]) -> Option<(f64, f64, f64)> {
+    match *args {
+        [x] => Some((x, 0.0, 1.0)),
+        [x, mu, sigma] if sigma > 0.0 => Some((x, mu, sigma)),
+        _ => None,
+    }
+}
+
+fn normal_pdf(args: &[f64]) -> Option<f64> {
+    let (x, mu, sigma) = normal_parameters(args)?;
+    let z = (x - mu) / sigma;
+    Some((-0.5 * z * z).exp() / (sigma * (2.0 * PI).sqrt()))
+}
+
+fn normal_cdf(args: &[f64]) -> Option<f64> {
+    let (x, mu, sigma) = normal_parameters(args)?;
+    Some(0.5 * erfc(-(x - mu) / (sigma * 2f64.sqrt())))
+}
+
+fn normal_inverse(args: &[f64]) -> Option<f64> {
+    let (p, mu, sigma) = normal_parameters(args)?;
+    probability(p)?;
+    Some(mu + sigma * normal_quantile(p))
+}
+
+fn binomial_parameters(args: &[f64]) -> Option<(f64, f64, f64)> {
+    match *args {
+        [k, n, p] if n >= 0.0 && n.fract() == 0.0 && (0.0..=1.0).contains(&p) => Some((k, n, p)),
+        _ => None,
+    }
+}
+
+fn binomial_pmf(args: &[f64]) -> Option<f64> {
+    let (k, n, p) = binomial_parameters(args)?;
+
+    if k < 0.0 || k > n || k.fract() != 0.0 {
+        return Some(0.0);
+    }
+
+    // Avoid 0 * ln(0) at the ends of the range of p.
+    let ln_pk = if k == 0.0 { 0.0 } else { k * p.ln() };
+    let ln_qk = if k == n {
+        0.0
+    } else {
+        (n - k) * (1.0 - p).ln()
+    };
+
+    Some((ln_gamma(n + 1.0) - ln_gamma(k + 1.0) - ln_gamma(n - k + 1.0) + ln_pk + ln_qk).exp())
+}
+
+fn binomial_cdf(args: &[f64]) -> Option<f64> {
+    let (k, n, p) = binomial_parameters(args)?;
+    let k = k.floor();
+
+    Some(if k < 0.0 {
+        0.0
+    } else if k >= n || p == 0.0 {
+        1.0
+    } else if p == 1.0 {
+        0.0
+    } else {
+        beta_regularized(1.0 - p, n - k, k + 1.0)
+    })
+}
+
+fn binomial_quantile(args: &[f64]) -> Option<f64> {
+    let (q, n, p) = binomial_parameters(args)?;
+    probability(q)?;
+    Some(discrete_quantile(
+        |k| binomial_cdf(&[k, n, p]).unwrap(),
+        q,
+        n,
+    ))
+}
+
+fn poisson_parameters(args: &[f64]) -> Option<(f64, f64)> {
+    match *args {
+        [k, lambda] if lambda >= 0.0 => Some((k, lambda)),
+        _ => None,
+    }
+}
+
+fn poisson_pmf(args: &[f64]) -> Option<f64> {
+    let (k, lambda) = poisson_parameters(args)?;
+
+    Some(if k < 0.0 || k.fract() != 0.0 {
+        0.0
+    } else if lambda == 0.0 {
+        if k == 0.0 {
+            1.0
+        } else {
+            0.0
+        }
+    } else {
+        (k * lambda.ln() - lambda - ln_gamma(k + 1.0)).exp()
+    })
+}
+
+fn poisson_cdf(args: &[f64]) -> Option<f64> {
+    let (k, lambda) = poisson_parameters(args)?;
+    let k = k.floor();
+
+    Some(if k < 0.0 {
+        0.0
+    } else {
+        gamma_q(k + 1.0, lambda)
+    })
+}
+
+fn poisson_quantile(args: &[f64]) -> Option<f64> {
+    let (q, lambda) = poisson_parameters(args)?;
+    probability(q)?;
+    Some(discrete_quantile(
+        |k| poisson_cdf(&[k, lambda]).unwrap(),
+        q,
+        f64::INFINITY,
+    ))
+}
+
+fn uniform_parameters(args: &[f64]) -> Option<(f64, f64, f64)> {
+    match *args {
+        [x, a, b] if a < b => Some((x, a, b)),
+        _ => None,
+    }
+}
+
+fn uniform_pdf(args: &[f64]) -> Option<f64> {
+    let (x, a, b) = uniform_parameters(args)?;
+    Some(if (a..=b).contains(&x) {
+        1.0 / (b - a)
+    } else {
+        0.0
+    })
+}
+
+fn uniform_cdf(args: &[f64]) -> Option<f64> {
+    let (x, a, b) = uniform_parameters(args)?;
+    Some(((x - a) / (b - a)).clamp(0.0, 1.0))
+}
+
+fn uniform_quantile(args: &[f64]) -> Option<f64> {
+    let (p, a, b) = uniform_parameters(args)?;
+    Some(a + probability(p)? * (b - a))
+}
+
+fn exponential_parameters(args: &[f64]) -> Option<(f64, f64)> {
+    match *args {
+        [x, lambda] if lambda > 0.0 => Some((x, lambda)),
+        _ => None,
+    }
+}
+
+fn exponential_pdf(args: &[f64]) -> Option<f64> {
+    let (x, lambda) = exponential_parameters(args)?;
+    Some(if x < 0.0 {
+        0.0
+    } else {
+        lambda * (-lambda * x).exp()
+    })
+}
+
+fn exponential_cdf(args: &[f64]) -> Option<f64> {
+    let (x, lambda) = exponential_parameters(args)?;
+    Some(if x < 0.0 {
+        0.0
+    } else {
+        -(-lambda * x).exp_m1()
+    })
+}
+
+fn exponential_quantile(args: &[f64]) -> Option<f64> {
+    let (p, lambda) = exponential_parameters(args)?;
+    Some(-(-probability(p)?).ln_1p() / lambda)
+}
+
+fn degrees_of_freedom(args: &[f64]) -> Option<(f64, f64)> {
+    match *args {
+        [x, nu] if nu > 0.0 => Some((x, nu)),
+        _ => None,
+    }
+}
+
+fn t_pdf(args: &[f64]) -> Option<f64> {
+    let (x, nu) = degrees_of_freedom(args)?;
+    let ln_normalization = ln_gamma((nu + 1.0) / 2.0) - ln_gamma(nu / 2.0) - 0.5 * (nu * PI).ln();
+    Some((ln_normalization - (nu + 1.0) / 2.0 * (x * x / nu).ln_1p()).exp())
+}
+
+fn t_cdf(args: &[f64]) -> Option<f64> {
+    let (x, nu) = degrees_of_freedom(args)?;
+
+    // Near the centre nu / (nu + x^2) rounds to 1, so the complementary form
+    // of the incomplete beta function keeps the precision there.
+    if x * x < nu {
+        let centre = 0.5 * beta_regularized(x * x / (nu + x * x), 0.5, nu / 2.0);
+        return Some(if x > 0.0 { 0.5 + centre } else { 0.5 - centre });
+    }
+
+    let tail = 0.5 * beta_regularized(nu / (nu + x * x), nu / 2.0, 0.5);
+    Some(if x > 0.0 { 1.0 - tail } else { tail })
+}
+
+fn t_quantile(args: &[f64]) -> Option<f64> {
+    let (p, nu) = degrees_of_freedom(args)?;
+    probability(p)?;
+    Some(continuous_quantile(
+        |x| t_cdf(&[x, nu]).unwrap(),
+        p,
+        f64::NEG_INFINITY,
+    ))
+}
+
+fn chi2_pdf(args: &[f64]) -> Option<f64> {
+    let (x, k) = degrees_of_freedom(args)?;
+    let half = k / 2.0;
+
+    Some(if x < 0.0 {
+        0.0
+    } else if x == 0.0 {
+        match k.partial_cmp(&2.0) {
+            Some(Ordering::Less) => f64::INFINITY,
+            Some(Ordering::Equal) => 0.5,
+            _ => 0.0,
+        }
+    } else {
+        ((half - 1.0) * x.ln() - x / 2.0 - half * 2f64.ln() - ln_gamma(half)).exp()
+    })
+}
+
+fn chi2_cdf(args: &[f64]) -> Option<f64> {
+    let (x, k) = degrees_of_freedom(args)?;
+    Some(gamma_p(k / 2.0, x / 2.0))
+}
+
+fn chi2_quantile(args: &[f64]) -> Option<f64> {
+    let (p, k) = degrees_of_freedom(args)?;
+    probability(p)?;
+    Some(continuous_quantile(|x| chi2_cdf(&[x, k]).unwrap(), p, 0.0))
+}
+
+fn f_parameters(args: &[f64]) -> Option<(f64, f64, f64)> {
+    match *args {
+        [x, d1, d2] if d1 > 0.0 && d2 > 0.0 => Some((x, d1, d2)),
+        _ => None,
+    }
+}
+
+fn f_pdf(args: &[f64]) -> Option<f64> {
+    let (x, d1, d2) = f_parameters(args)?;
+
+    Some(if x < 0.0 {
+        0.0
+    } else if x == 0.0 {
+        match d1.partial_cmp(&2.0) {
+            Some(Ordering::Less) => f64::INFINITY,
+            Some(Ordering::Equal) => 1.0,
+            _ => 0.0,
+        }
+    } else {
+        (0.5 * (d1 * (d1 * x).ln() + d2 * d2.ln() - (d1 + d2) * (d1 * x + d2).ln())
+            - x.ln()
+            - ln_beta(d1 / 2.0, d2 / 2.0))
+        .exp()
+    })
+}
+
+fn f_cdf(args: &[f64]) -> Option<f64> {
+    let (x, d1, d2) = f_parameters(args)?;
+    Some(beta_regularized(d1 * x / (d1 * x + d2), d1 / 2.0, d2 / 2.0))
+}
+
+fn f_quantile(args: &[f64]) -> Option<f64> {
+    let (p, d1, d2) = f_parameters(args)?;
+    probability(p)?;
+    Some(continuous_quantile(
+        |x| f_cdf(&[x, d1, d2]).unwrap(),
+        p,
+        0.0,
+    ))
+}
+
+fn probability(p: f64) -> Option<f64> {
+    (0.0..=1.0).contains(&p).then_some(p)
+}
+
+// Invert an increasing cdf by bisection, after growing a bracket around the
+// quantile. `lower` is the lower end of the support.
+fn continuous_quantile(cdf: impl Fn(f64) -> f64, p: f64, lower: f64) -> f64 {
+    if p == 0.0 {
+        return lower;
+    }
+    if p == 1.0 {
+        return f64::INFINITY;
+    }
+
+    let mut low = if lower.is_finite() { lower } else { -1.0 };
+    let mut high = 1.0;
+
+    while cdf(low) > p {
+        low *= 2.0;
+    }
+    while cdf(high) < p {
+        high *= 2.0;
+    }
+
+    loop {
+        let middle = 0.5 * (low + high);
+        if high - low <= 4.0 * f64::EPSILON * middle.abs().max(f64::MIN_POSITIVE) {
+            return middle;
+        }
+
+        if cdf(middle) < p {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+}
+
+// The smallest integer k with cdf(k) >= p, by bisection over [0, upper]. The
+// cdf rounds to 1 long before the end of the support, so for p = 1 that is the
+// end, unless all of the mass is at 0.
+fn discrete_quantile(cdf: impl Fn(f64) -> f64, p: f64, upper: f64) -> f64 {
+    let mut low = 0.0;
+    if cdf(low) >= p {
+        return low;
+    }
+    if p >= 1.0 {
+        return upper;
+    }
+
+    let mut high = 1.0f64;
+    while high < upper && cdf(high) < p {
+        high *= 2.0;
+    }
+    let mut high = high.min(upper);
+
+    while high - low > 1.0 {
+        let middle = ((low + high) / 2.0).floor();
+        if cdf(middle) >= p {
+            high = middle;
+        } else {
+            low = middle;
+        }
+    }
+
+    high
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Option<f64>, expected: f64, tolerance: f64) {
+        let actual = actual.unwrap();
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn normal_distribution() {
+        assert_close(normal_pdf(&[0.0]), 1.0 / (2.0 * PI).sqrt(), 1e-15);
+        assert_close(normal_cdf(&[0.0]), 0.5, 1e-15);
+        assert_close(normal_inverse(&[0.975]), 1.959_963_984_540_054, 1e-9);
+        assert_close(
+            normal_inverse(&[0.975, 10.0, 2.0]),
+            13.919_927_969_080_108,
+            1e-8,
+        );
+        assert!(normal_pdf(&[0.0, 0.0, -1.0]).is_none());
+        assert!(normal_inverse(&[1.5]).is_none());
+    }
+
+    #[test]
+    fn discrete_distributions() {
+        assert_close(binomial_pmf(&[2.0, 4.0, 0.5]), 0.375, 1e-12);
+        assert_close(binomial_cdf(&[2.0, 4.0, 0.5]), 0.6875, 1e-12);
+        assert_close(binomial_quantile(&[0.5, 4.0, 0.5]), 2.0, 0.0);
+        assert_close(poisson_pmf(&[0.0, 2.0]), (-2.0f64).exp(), 1e-12);
+        assert_close(poisson_cdf(&[1.0, 2.0]), 3.0 * (-2.0f64).exp(), 1e-12);
+    }
+
+    #[test]
+    fn discrete_quantiles_at_the_ends() {
+        assert_eq!(poisson_quantile(&[1.0, 3.0]), Some(f64::INFINITY));
+        assert_eq!(poisson_quantile(&[0.0, 3.0]), Some(0.0));
+        assert_eq!(poisson_quantile(&[1.0, 0.0]), Some(0.0));
+        assert_eq!(binomial_quantile(&[1.0, 10.0, 0.3]), Some(10.0));
+        assert_eq!(binomial_quantile(&[1.0, 10.0, 0.0]), Some(0.0));
+    }
+
+    #[test]
+    fn continuous_distributions() {
+        assert_close(uniform_quantile(&[0.25, 0.0, 4.0]), 1.0, 1e-15);
+        assert_close(exponential_cdf(&[1.0, 1.0]), 1.0 - (-1.0f64).exp(), 1e-15);
+        assert_close(t_quantile(&[0.975, 10.0]), 2.228_138_851_986_274, 1e-8);
+        assert_close(chi2_quantile(&[0.95, 1.0]), 3.841_458_820_694_124, 1e-8);
+        assert_close(f_quantile(&[0.95, 5.0, 10.0]), 3.325_834_530_413_011, 1e-8);
+    }
+
+    #[test]
+    fn quantiles_invert_cdfs() {
+        for p in [0.01, 0.3, 0.5, 0.9] {
+            assert_close(t_cdf(&[t_quantile(&[p, 4.0]).unwrap(), 4.0]), p, 1e-10);
+            assert_close(
+                chi2_cdf(&[chi2_quantile(&[p, 3.0]).unwrap(), 3.0]),
+                p,
+                1e-10,
+            );
+        }
+    }
+}
@@ -0,0 +1,941 @@
+use super::algebra::divide_linear;
+use super::algebra::gcd;
+use super::algebra::linear_coefficients;
+use super::algebra::numerator_coefficients;
+use super::algebra::rational;
+use super::algebra::rational_root;
+use super::forms::bound_variable;
+use super::forms::expect_arguments;
+use super::solve::residual;
+use super::solve::tuple_sources;
+use super::symbolic::Expr;
+use super::Repl;
+use super::ReplError;
+use evalexpr::EvalexprError;
+use evalexpr::IterateVariablesContext;
+use evalexpr::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fmt;
+
+/// Imaginary parts this small relative to the root are rounding error in the
+/// numeric value of a root written with radicals.
+const EXACT_REAL_TOLERANCE: f64 = 1e-9;
+
+/// Square factors are taken out of a radicand by trial division up to this.
+const SQUARE_FREE_MAX_DIVISOR: i128 = 1_000_000;
+
+type Complex = (f64, f64);
+
+/// The solution of `solve_exact`, written with fractions and radicals.
+pub struct ExactSolution {
+    /// Each root or unknown written exactly, e.g. `("x", "±sqrt(2)")`.
+    pub bindings: Vec<(String, String)>,
+    /// The real values, for using the solution inside a larger expression.
+    pub values: Vec<f64>,
+}
+
+impl ExactSolution {
+    /// A single real value as a number, several as a tuple, like `nsolve`.
+    pub fn to_value(&self) -> Result<Value, Box<dyn Error>> {
+        match self.values.as_slice() {
+            [] => Err(Box::new(EvalexprError::CustomMessage(
+                "no real solutions".to_string(),
+            ))),
+            [value] => Ok(Value::Float(*value)),
+            values => Ok(Value::Tuple(
+                values.iter().map(|value| Value::Float(*value)).collect(),
+            )),
+        }
+    }
+}
+
+/// A fraction in lowest terms with a positive denominator. The arithmetic is
+/// checked, as the numbers in the formulas for cubics and quartics grow quickly.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Rational {
+    numerator: i128,
+    denominator: i128,
+}
+
+/// `rational + coefficient * sqrt(radicand)` with a square free radicand, which
+/// is negative for an imaginary part.
+#[derive(Debug, Clone, Copy)]
+struct Surd {
+    rational: Rational,
+    coefficient: Rational,
+    radicand: i128,
+}
+
+/// A root as a fraction, or as radicals along with its numeric values. One
+/// radical stands for both roots of a pair written with `±`.
+enum ExactRoot {
+    Rational(Rational),
+    Radical { text: String, values: Vec<Complex> },
+}
+
+impl Repl {
+    /// `solve_exact(x^2 - 2 = 0, x)`, the roots of a polynomial equation of
+    /// degree up to four in radicals, or `solve_exact((eq1, eq2), (x, y))` for
+    /// linear equations, solved in fractions. Variables other than the unknowns
+    /// take their values from the context.
+    pub(super) fn eval_solve_exact(
+        &mut self,
+        arguments: &[String],
+    ) -> Result<ExactSolution, Box<dyn Error>> {
+        expect_arguments(arguments, 2)?;
+
+        let equations = tuple_sources(&arguments[0]);
+        let names = tuple_sources(&arguments[1])
+            .iter()
+            .map(|name| bound_variable(name))
+            .collect::<Result<Vec<String>, Box<dyn Error>>>()?;
+
+        if equations.len() != names.len() {
+            return Err(Box::new(ReplError::InvalidArgument));
+        }
+
+        let known: HashMap<String, Expr> = self
+            .context
+            .iter_variables()
+            .filter(|(name, _)| !names.contains(name))
+            .filter_map(|(name, value)| match value {
+                Value::Float(value) => Some((name, Expr::Number(value))),
+                Value::Int(value) => Some((name, Expr::Number(value as f64))),
+                _ => None,
+            })
+            .collect();
+
+        let mut residuals = Vec::with_capacity(equations.len());
+        for equation in &equations {
+            let expanded = self.expand_expression_forms(&residual(equation))?;
+            residuals.push(
+                self.inline_functions(&Expr::parse(&expanded)?)?
+                    .substitute(&known),
+            );
+        }
+
+        if names.len() == 1 {
+            exact_roots(&residuals[0], &names[0])
+        } else {
+            exact_linear(&residuals, &names)
+        }
+    }
+
+    /// If the whole input is a call to `solve_exact`, its solution.
+    pub(super) fn exact_form(
+        &mut self,
+        input: &str,
+    ) -> Result<Option<ExactSolution>, Box<dyn Error>> {
+        match self.whole_form(input) {
+            Some((name, arguments)) if name == "solve_exact" => {
+                Ok(Some(self.eval_solve_exact(&arguments)?))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+// The roots of the numerator of `residual` as a polynomial in `name`.
+fn exact_roots(residual: &Expr, name: &str) -> Result<ExactSolution, Box<dyn Error>> {
+    let coefficients = numerator_coefficients(residual, name).ok_or_else(|| {
+        ReplError::Unsupported(format!(
+            "solve_exact of equations that are not polynomial in {}",
+            name
+        ))
+    })?;
+    let mut coefficients = rationals(&coefficients)?;
+    while coefficients.last().is_some_and(|c| c.is_zero()) {
+        coefficients.pop();
+    }
+
+    if coefficients.is_empty() {
+        return Err(Box::new(EvalexprError::CustomMessage(format!(
+            "every {} is a solution",
+            name
+        ))));
+    }
+
+    let roots = polynomial_roots(coefficients)?;
+    let mut values: Vec<f64> = roots.iter().flat_map(ExactRoot::real_values).collect();
+    values.sort_by(f64::total_cmp);
+
+    Ok(ExactSolution {
+        bindings: roots
+            .iter()
+            .map(|root| (name.to_string(), root.text()))
+            .collect(),
+        values,
+    })
+}
+
+// Linear equations, solved by Gaussian elimination over the rationals.
+fn exact_linear(residuals: &[Expr], names: &[String]) -> Result<ExactSolution, Box<dyn Error>> {
+    let mut matrix = Vec::with_capacity(residuals.len());
+    let mut rhs = Vec::with_capacity(residuals.len());
+
+    for residual in residuals {
+        let (coefficients, constant) = linear_coefficients(residual, names).ok_or_else(|| {
+            ReplError::Unsupported("solve_exact of nonlinear systems, use nsolve".to_string())
+        })?;
+        matrix.push(rationals(&coefficients)?);
+        rhs.push(rationals(&[constant])?[0].neg());
+    }
+
+    let solution = solve_rational(matrix, rhs)?;
+
+    Ok(ExactSolution {
+        bindings: names
+            .iter()
+            .cloned()
+            .zip(solution.iter().map(Rational::to_string))
+            .collect(),
+        values: solution.iter().map(|value| value.to_f64()).collect(),
+    })
+}
+
+fn rationals(values: &[f64]) -> Result<Vec<Rational>, ReplError> {
+    values
+        .iter()
+        .map(|value| Rational::from_f64(*value))
+        .collect::<Option<Vec<Rational>>>()
+        .ok_or_else(|| {
+            ReplError::Unsupported("solve_exact with irrational coefficients".to_string())
+        })
+}
+
+fn solve_rational(
+    mut matrix: Vec<Vec<Rational>>,
+    mut rhs: Vec<Rational>,
+) -> Result<Vec<Rational>, ReplError> {
+    let n = rhs.len();
+
+    for column in 0..n {
+        let pivot = (column..n)
+            .find(|&row| !matrix[row][column].is_zero())
+            .ok_or(ReplError::SingularMatrix)?;
+        matrix.swap(column, pivot);
+        rhs.swap(column, pivot);
+
+        let (upper, lower) = matrix.split_at_mut(column + 1);
+        let pivot_row = &upper[column];
+        for (offset, row) in lower.iter_mut().enumerate() {
+            let factor = row[column].div(pivot_row[column])?;
+            for (entry, pivot) in row[column..].iter_mut().zip(&pivot_row[column..]) {
+                *entry = entry.sub(factor.mul(*pivot)?)?;
+            }
+            rhs[column + 1 + offset] = rhs[column + 1 + offset].sub(factor.mul(rhs[column])?)?;
+        }
+    }
+
+    let mut solution = vec![Rational::ZERO; n];
+    for row in (0..n).rev() {
+        let mut value = rhs[row];
+        for k in row + 1..n {
+            value = value.sub(matrix[row][k].mul(solution[k])?)?;
+        }
+        solution[row] = value.div(matrix[row][row])?;
+    }
+
+    Ok(solution)
+}
+
+// The distinct roots of a polynomial with rational coefficients, lowest power
+// first: rational roots are divided out, and what is left is solved by the
+// formulas for quadratics, cubics and quartics.
+fn polynomial_roots(mut coefficients: Vec<Rational>) -> Result<Vec<ExactRoot>, ReplError> {
+    let mut roots = Vec::new();
+
+    while coefficients.len() > 3 {
+        let integers = integer_coefficients(&coefficients)?;
+        let Some((p, q)) = rational_root(&integers) else {
+            break;
+        };
+        roots.push(ExactRoot::Rational(Rational::new(p, q)));
+        coefficients = divide_linear(&integers, p, q)
+            .into_iter()
+            .map(Rational::integer)
+            .collect();
+    }
+
+    let leading = *coefficients.last().unwrap();
+    let monic = coefficients
+        .iter()
+        .map(|c| c.div(leading))
+        .collect::<Result<Vec<Rational>, ReplError>>()?;
+
+    match monic.as_slice() {
+        [_] => {}
+        [c, _] => roots.push(ExactRoot::Rational(c.neg())),
+        [c, b, _] => roots.extend(quadratic(*b, *c)?),
+        [c, b, a, _] => roots.extend(cubic(*a, *b, *c)?),
+        [d, c, b, a, _] => roots.extend(quartic(*a, *b, *c, *d)?),
+        _ => {
+            return Err(ReplError::Unsupported(
+                "exact roots of polynomials of degree five or more".to_string(),
+            ))
+        }
+    }
+
+    // Rational roots first, in order, each once.
+    let (mut rational_roots, radicals): (Vec<ExactRoot>, Vec<ExactRoot>) = roots
+        .into_iter()
+        .partition(|root| matches!(root, ExactRoot::Rational(_)));
+    rational_roots.sort_by(|a, b| a.real_values()[0].total_cmp(&b.real_values()[0]));
+    rational_roots.dedup_by(|a, b| match (a, b) {
+        (ExactRoot::Rational(a), ExactRoot::Rational(b)) => a == b,
+        _ => false,
+    });
+    rational_roots.extend(radicals);
+
+    Ok(rational_roots)
+}
+
+// The rational roots of a polynomial, as many as there are.
+fn rational_roots(coefficients: &[Rational]) -> Result<Vec<Rational>, ReplError> {
+    let mut integers = integer_coefficients(coefficients)?;
+    let mut roots = Vec::new();
+
+    while integers.len() > 1 {
+        let Some((p, q)) = rational_root(&integers) else {
+            break;
+        };
+        roots.push(Rational::new(p, q));
+        integers = divide_linear(&integers, p, q);
+    }
+
+    Ok(roots)
+}
+
+// The same polynomial with coprime integer coefficients.
+fn integer_coefficients(coefficients: &[Rational]) -> Result<Vec<i128>, ReplError> {
+    let mut multiple = 1_i128;
+    for c in coefficients {
+        multiple = (multiple / gcd(multiple, c.denominator))
+            .checked_mul(c.denominator)
+            .ok_or_else(too_large)?;
+    }
+
+    let integers = coefficients
+        .iter()
+        .map(|c| {
+            c.numerator
+                .checked_mul(multiple / c.denominator)
+                .ok_or_else(too_large)
+        })
+        .collect::<Result<Vec<i128>, ReplError>>()?;
+    let divisor = integers
+        .iter()
+        .fold(0, |divisor, c| gcd(divisor, *c))
+        .max(1);
+
+    Ok(integers.iter().map(|c| c / divisor).collect())
+}
+
+// `x^2 + b*x + c`.
+fn quadratic(b: Rational, c: Rational) -> Result<Vec<ExactRoot>, ReplError> {
+    let center = b.neg().div(Rational::integer(2))?;
+    let inner = center.mul(center)?.sub(c)?;
+
+    plus_minus_root(Surd::rational(center), Surd::rational(inner), 1)
+}
+
+// `x^3 + a*x^2 + b*x + c` without rational roots, through the depressed cubic
+// `t^3 + p*t + q` with `x = t - a/3`: Cardano's formula when it has one real
+// root, the trigonometric form when it has three.
+fn cubic(a: Rational, b: Rational, c: Rational) -> Result<Vec<ExactRoot>, ReplError> {
+    let shift = a.neg().div(Rational::integer(3))?;
+    let p = b.sub(a.mul(a)?.div(Rational::integer(3))?)?;
+    let q = Rational::new(2, 27)
+        .mul(a.mul(a)?.mul(a)?)?
+        .sub(a.mul(b)?.div(Rational::integer(3))?)?
+        .add(c)?;
+    let half_q = q.div(Rational::integer(2))?;
+    let delta = half_q
+        .mul(half_q)?
+        .add(p.mul(p)?.mul(p)?.div(Rational::integer(27))?)?;
+
+    if delta.is_zero() {
+        // A repeated root, which is rational.
+        return Ok(if p.is_zero() {
+            vec![ExactRoot::Rational(shift)]
+        } else {
+            let simple = Rational::integer(3).mul(q)?.div(p)?;
+            let double = simple.div(Rational::integer(-2))?;
+            vec![
+                ExactRoot::Rational(simple.add(shift)?),
+                ExactRoot::Rational(double.add(shift)?),
+            ]
+        });
+    }
+
+    let shift_value = shift.to_f64();
+
+    if delta.numerator > 0 {
+        let root = Surd::sqrt(delta)?;
+        let center = Surd::rational(half_q.neg());
+        let cubes: Vec<(String, f64)> = [center.plus(root)?, center.plus(root.neg())?]
+            .iter()
+            .filter(|surd| !surd.is_zero())
+            .map(|surd| (format!("cbrt({})", surd.text()), surd.value().0.cbrt()))
+            .collect();
+
+        let texts: Vec<&str> = cubes.iter().map(|(text, _)| text.as_str()).collect();
+        let (sum, difference) = match texts.as_slice() {
+            [u, v] => (format!("({} + {})", u, v), format!("({} - {})", u, v)),
+            _ => (texts[0].to_string(), texts[0].to_string()),
+        };
+        let u = cubes[0].1;
+        let v = cubes.get(1).map_or(0.0, |cube| cube.1);
+
+        let real = join_terms(Some(texts.join(" + ")), shift);
+        let half = Rational::new(1, 2);
+        let center_text = signed_text(
+            nonzero_text(shift),
+            true,
+            &product_text(half, 1, Some(&sum)),
+        );
+        let radius_text = product_text(half, -3, Some(&difference));
+        let center_value = shift_value - (u + v) / 2.0;
+        let radius_value = 3.0_f64.sqrt() * (u - v) / 2.0;
+
+        return Ok(vec![
+            ExactRoot::Radical {
+                text: real,
+                values: vec![(u + v + shift_value, 0.0)],
+            },
+            ExactRoot::Radical {
+                text: plus_minus(Some(center_text), &radius_text),
+                values: vec![(center_value, radius_value), (center_value, -radius_value)],
+            },
+        ]);
+    }
+
+    // Three real roots, 2*sqrt(-p/3)*cos(acos(k)/3 - 2*pi*j/3) with
+    // k = 3*q/(2*p)*sqrt(-3/p).
+    let amplitude = Surd::sqrt(p.neg().div(Rational::integer(3))?)?.scale(Rational::integer(2))?;
+    let cosine = Surd::sqrt(Rational::integer(-3).div(p)?)?.scale(
+        Rational::integer(3)
+            .mul(q)?
+            .div(Rational::integer(2).mul(p)?)?,
+    )?;
+    let angle = cosine.value().0.clamp(-1.0, 1.0).acos() / 3.0;
+
+    Ok(["", " - 2*pi/3", " - 4*pi/3"]
+        .iter()
+        .enumerate()
+        .map(|(j, offset)| {
+            let cos = format!("cos(acos({})/3{})", cosine.text(), offset);
+            let text = match amplitude.as_rational() {
+                Some(value) => product_text(value, 1, Some(&cos)),
+                None => product_text(amplitude.coefficient, amplitude.radicand, Some(&cos)),
+            };
+            let value = amplitude.value().0 * (angle - 2.0 * PI * j as f64 / 3.0).cos();
+
+            ExactRoot::Radical {
+                text: join_terms(Some(text), shift),
+                values: vec![(value + shift_value, 0.0)],
+            }
+        })
+        .collect())
+}
+
+// `x^4 + a*x^3 + b*x^2 + c*x + d` without rational roots, through the depressed
+// quartic `y^4 + p*y^2 + q*y + r` with `x = y - a/4`. Without the `y` term it
+// is a quadratic in `y^2`, otherwise Ferrari's method splits it into two
+// quadratics, which takes a rational root of the resolvent cubic.
+fn quartic(
+    a: Rational,
+    b: Rational,
+    c: Rational,
+    d: Rational,
+) -> Result<Vec<ExactRoot>, ReplError> {
+    let shift = a.neg().div(Rational::integer(4))?;
+    let a2 = a.mul(a)?;
+    let p = b.sub(Rational::new(3, 8).mul(a2)?)?;
+    let q = c
+        .sub(a.mul(b)?.div(Rational::integer(2))?)?
+        .add(a2.mul(a)?.div(Rational::integer(8))?)?;
+    let r = d
+        .sub(a.mul(c)?.div(Rational::integer(4))?)?
+        .add(a2.mul(b)?.div(Rational::integer(16))?)?
+        .sub(Rational::new(3, 256).mul(a2.mul(a2)?)?)?;
+
+    let mut roots = Vec::new();
+
+    if q.is_zero() {
+        // y^2 = -p/2 ± sqrt(p^2/4 - r)
+        let center = Surd::rational(p.neg().div(Rational::integer(2))?);
+        let root = Surd::sqrt(p.mul(p)?.div(Rational::integer(4))?.sub(r)?)?;
+        let squares = if root.is_zero() {
+            vec![center]
+        } else {
+            vec![center.plus(root)?, center.plus(root.neg())?]
+        };
+
+        for square in squares {
+            roots.extend(plus_minus_root(Surd::rational(shift), square, 1)?);
+        }
+        return Ok(roots);
+    }
+
+    let two = Rational::integer(2);
+    let resolvent = [
+        q.mul(q)?.neg(),
+        two.mul(p)?.mul(p)?.sub(Rational::integer(8).mul(r)?)?,
+        Rational::integer(8).mul(p)?,
+        Rational::integer(8),
+    ];
+    let m = rational_roots(&resolvent)?
+        .into_iter()
+        .find(|m| m.numerator > 0)
+        .ok_or_else(|| ReplError::Unsupported("exact roots of this quartic".to_string()))?;
+
+    // y^2 - σ*s*y + p/2 + m + σ*q/(2*s) with s = sqrt(2*m), whose roots are
+    // (σ*s ± sqrt(-2*p - 2*m - σ*q*s/m))/2.
+    let s = Surd::sqrt(two.mul(m)?)?;
+    for sign in [1, -1] {
+        let sign = Rational::integer(sign);
+        let center = Surd::rational(shift).plus(s.scale(sign.div(two)?)?)?;
+        let inner = Surd::rational(two.mul(p)?.add(two.mul(m)?)?.neg())
+            .plus(s.scale(sign.mul(q)?.div(m)?.neg())?)?;
+        roots.extend(plus_minus_root(center, inner, 2)?);
+    }
+
+    Ok(roots)
+}
+
+// The roots `center ± sqrt(inner)/divisor`, as fractions or single surds where
+// they simplify that far.
+fn plus_minus_root(center: Surd, inner: Surd, divisor: i128) -> Result<Vec<ExactRoot>, ReplError> {
+    let Some(inner) = inner.as_rational() else {
+        // The square root of a negative real is written with `i`.
+        let mut radius_text = if inner.radicand > 0 && inner.value().0 < 0.0 {
+            format!("i*sqrt({})", inner.neg().text())
+        } else {
+            format!("sqrt({})", inner.text())
+        };
+        if divisor != 1 {
+            radius_text = format!("{}/{}", radius_text, divisor);
+        }
+        let radius = complex_sqrt(inner.value());
+        let radius = (radius.0 / divisor as f64, radius.1 / divisor as f64);
+        let value = center.value();
+
+        return Ok(vec![ExactRoot::Radical {
+            text: plus_minus(nonzero_surd_text(&center), &radius_text),
+            values: vec![
+                (value.0 + radius.0, value.1 + radius.1),
+                (value.0 - radius.0, value.1 - radius.1),
+            ],
+        }]);
+    };
+
+    let radius = Surd::sqrt(inner.div(Rational::integer(divisor * divisor))?)?;
+    if radius.is_zero() {
+        return Ok(vec![ExactRoot::from_surd(center)]);
+    }
+
+    if center.coefficient.is_zero() && radius.as_rational().is_none() {
+        let value = center.value();
+        let offset = radius.value();
+        return Ok(vec![ExactRoot::Radical {
+            text: plus_minus(
+                nonzero_text(center.rational),
+                &product_text(radius.coefficient, radius.radicand, None),
+            ),
+            values: vec![
+                (value.0 + offset.0, value.1 + offset.1),
+                (value.0 - offset.0, value.1 - offset.1),
+            ],
+        }]);
+    }
+
+    if radius.as_rational().is_some() || center.radicand == radius.radicand {
+        return Ok(vec![
+            ExactRoot::from_surd(center.plus(radius)?),
+            ExactRoot::from_surd(center.plus(radius.neg())?),
+        ]);
+    }
+
+    let value = center.value();
+    let offset = radius.value();
+    Ok(vec![ExactRoot::Radical {
+        text: plus_minus(
+            nonzero_surd_text(&center),
+            &product_text(radius.coefficient, radius.radicand, None),
+        ),
+        values: vec![
+            (value.0 + offset.0, value.1 + offset.1),
+            (value.0 - offset.0, value.1 - offset.1),
+        ],
+    }])
+}
+
+impl ExactRoot {
+    fn from_surd(surd: Surd) -> ExactRoot {
+        match surd.as_rational() {
+            Some(value) => ExactRoot::Rational(value),
+            None => ExactRoot::Radical {
+                text: surd.text(),
+                values: vec![surd.value()],
+            },
+        }
+    }
+
+    fn text(&self) -> String {
+        match self {
+            ExactRoot::Rational(value) => value.to_string(),
+            ExactRoot::Radical { text, .. } => text.clone(),
+        }
+    }
+
+    fn real_values(&self) -> Vec<f64> {
+        match self {
+            ExactRoot::Rational(value) => vec![value.to_f64()],
+            ExactRoot::Radical { values, .. } => values
+                .iter()
+                .filter(|(re, im)| im.abs() <= EXACT_REAL_TOLERANCE * re.abs().max(1.0))
+                .map(|(re, _)| *re)
+                .collect(),
+        }
+    }
+}
+
+impl Rational {
+    const ZERO: Rational = Rational {
+        numerator: 0,
+        denominator: 1,
+    };
+
+    fn new(numerator: i128, denominator: i128) -> Rational {
+        let divisor = gcd(numerator, denominator).max(1) * denominator.signum();
+        Rational {
+            numerator: numerator / divisor,
+            denominator: denominator / divisor,
+        }
+    }
+
+    fn integer(value: i128) -> Rational {
+        Rational::new(value, 1)
+    }
+
+    fn from_f64(value: f64) -> Option<Rational> {
+        let (p, q) = rational(value)?;
+        Some(Rational::new(p, q))
+    }
+
+    fn to_f64(self) -> f64 {
+        self.numerator as f64 / self.denominator as f64
+    }
+
+    fn is_zero(&self) -> bool {
+        self.numerator == 0
+    }
+
+    fn neg(self) -> Rational {
+        Rational::new(-self.numerator, self.denominator)
+    }
+
+    fn abs(self) -> Rational {
+        Rational::new(self.numerator.abs(), self.denominator)
+    }
+
+    fn add(self, other: Rational) -> Result<Rational, ReplError> {
+        let numerator = self
+            .numerator
+            .checked_mul(other.denominator)
+            .zip(other.numerator.checked_mul(self.denominator))
+            .and_then(|(a, b)| a.checked_add(b));
+        let denominator = self.denominator.checked_mul(other.denominator);
+
+        match (numerator, denominator) {
+            (Some(numerator), Some(denominator)) => Ok(Rational::new(numerator, denominator)),
+            _ => Err(too_large()),
+        }
+    }
+
+    fn sub(self, other: Rational) -> Result<Rational, ReplError> {
+        self.add(other.neg())
+    }
+
+    fn mul(self, other: Rational) -> Result<Rational, ReplError> {
+        // Cancel across first, which keeps the products small.
+        let a = gcd(self.numerator, other.denominator).max(1);
+        let b = gcd(other.numerator, self.denominator).max(1);
+        let numerator = (self.numerator / a).checked_mul(other.numerator / b);
+        let denominator = (self.denominator / b).checked_mul(other.denominator / a);
+
+        match (numerator, denominator) {
+            (Some(numerator), Some(denominator)) => Ok(Rational::new(numerator, denominator)),
+            _ => Err(too_large()),
+        }
+    }
+
+    fn div(self, other: Rational) -> Result<Rational, ReplError> {
+        if other.is_zero() {
+            return Err(ReplError::InvalidArgument);
+        }
+        self.mul(Rational::new(other.denominator, other.numerator))
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.denominator == 1 {
+            write!(f, "{}", self.numerator)
+        } else {
+            write!(f, "{}/{}", self.numerator, self.denominator)
+        }
+    }
+}
+
+impl Surd {
+    fn rational(value: Rational) -> Surd {
+        Surd {
+            rational: value,
+            coefficient: Rational::ZERO,
+            radicand: 1,
+        }
+    }
+
+    fn sqrt(value: Rational) -> Result<Surd, ReplError> {
+        if value.is_zero() {
+            return Ok(Surd::rational(Rational::ZERO));
+        }
+
+        // sqrt(p/q) = sqrt(p*q)/q
+        let product = value
+            .numerator
+            .checked_mul(value.denominator)
+            .ok_or_else(too_large)?;
+        let (root, radicand) = square_free(product);
+        let coefficient = Rational::new(root, value.denominator);
+
+        Ok(if radicand == 1 {
+            Surd::rational(coefficient)
+        } else {
+            Surd {
+                rational: Rational::ZERO,
+                coefficient,
+                radicand,
+            }
+        })
+    }
+
+    fn as_rational(&self) -> Option<Rational> {
+        self.coefficient.is_zero().then_some(self.rational)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.rational.is_zero() && self.coefficient.is_zero()
+    }
+
+    fn neg(self) -> Surd {
+        Surd {
+            rational: self.rational.neg(),
+            coefficient: self.coefficient.neg(),
+            radicand: self.radicand,
+        }
+    }
+
+    fn scale(self, factor: Rational) -> Result<Surd, ReplError> {
+        Ok(Surd {
+            rational: self.rational.mul(factor)?,
+            coefficient: self.coefficient.mul(factor)?,
+            radicand: self.radicand,
+        })
+    }
+
+    // The sum of two surds, one of which is rational or both with the same radicand.
+    fn plus(self, other: Surd) -> Result<Surd, ReplError> {
+        let radicand = if self.coefficient.is_zero() {
+            other.radicand
+        } else {
+            self.radicand
+        };
+
+        Ok(Surd {
+            rational: self.rational.add(other.rational)?,
+            coefficient: self.coefficient.add(other.coefficient)?,
+            radicand,
+        })
+    }
+
+    fn value(&self) -> Complex {
+        let root = self.coefficient.to_f64() * (self.radicand.abs() as f64).sqrt();
+        if self.radicand < 0 {
+            (self.rational.to_f64(), root)
+        } else {
+            (self.rational.to_f64() + root, 0.0)
+        }
+    }
+
+    fn text(&self) -> String {
+        if self.coefficient.is_zero() {
+            return self.rational.to_string();
+        }
+
+        signed_text(
+            nonzero_text(self.rational),
+            self.coefficient.numerator < 0,
+            &product_text(self.coefficient.abs(), self.radicand, None),
+        )
+    }
+}
+
+fn too_large() -> ReplError {
+    ReplError::Unsupported("coefficients too large for exact roots".to_string())
+}
+
+// Split `n` into `root^2 * rest`, keeping the sign in `rest`.
+fn square_free(n: i128) -> (i128, i128) {
+    let (mut root, mut rest) = (1, n);
+    let mut d: i128 = 2;
+
+    while d <= SQUARE_FREE_MAX_DIVISOR && d * d <= rest.abs() {
+        while rest % (d * d) == 0 {
+            rest /= d * d;
+            root *= d;
+        }
+        d += 1;
+    }
+
+    (root, rest)
+}
+
+fn complex_sqrt((re, im): Complex) -> Complex {
+    let modulus = re.hypot(im);
+    let real = ((modulus + re) / 2.0).sqrt();
+    let imaginary = ((modulus - re) / 2.0).sqrt();
+    (real, if im < 0.0 { -imaginary } else { imaginary })
+}
+
+// A positive `coefficient * sqrt(radicand)` times an optional factor, e.g.
+// `3*sqrt(2)/2` or `i*sqrt(3)*(u - v)/2`, with `i` for a negative radicand.
+fn product_text(coefficient: Rational, radicand: i128, factor: Option<&str>) -> String {
+    let mut factors = Vec::new();
+    if radicand < 0 {
+        factors.push("i".to_string());
+    }
+    if radicand.abs() != 1 {
+        factors.push(format!("sqrt({})", radicand.abs()));
+    }
+    if let Some(factor) = factor {
+        factors.push(factor.to_string());
+    }
+    if coefficient.numerator != 1 || factors.is_empty() {
+        factors.insert(0, coefficient.numerator.to_string());
+    }
+
+    let text = factors.join("*");
+    if coefficient.denominator == 1 {
+        text
+    } else {
+        format!("{}/{}", text, coefficient.denominator)
+    }
+}
+
+fn nonzero_text(value: Rational) -> Option<String> {
+    (!value.is_zero()).then(|| value.to_string())
+}
+
+fn nonzero_surd_text(value: &Surd) -> Option<String> {
+    (!value.is_zero()).then(|| value.text())
+}
+
+// `first + term` or `first - term`, or the term alone.
+fn signed_text(first: Option<String>, is_negative: bool, term: &str) -> String {
+    match (first, is_negative) {
+        (Some(first), true) => format!("{} - {}", first, term),
+        (Some(first), false) => format!("{} + {}", first, term),
+        (None, true) => format!("-{}", term),
+        (None, false) => term.to_string(),
+    }
+}
+
+// A text followed by a rational, e.g. `cbrt(2) - 1/3`.
+fn join_terms(first: Option<String>, value: Rational) -> String {
+    match (first, value.is_zero()) {
+        (Some(first), true) => first,
+        (first, _) => signed_text(first, value.numerator < 0, &value.abs().to_string()),
+    }
+}
+
+fn plus_minus(center: Option<String>, radius: &str) -> String {
+    match center {
+        Some(center) => format!("{} ± {}", center, radius),
+        None => format!("±{}", radius),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repl::ReplResult;
+
+    fn solve(input: &str) -> Vec<(String, String)> {
+        match Repl::new().process_input(input).unwrap() {
+            ReplResult::ExactBindings(bindings) => bindings,
+            result => panic!("{} gave {:?}", input, result),
+        }
+    }
+
+    fn bindings(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn quadratic_roots_in_radicals() {
+        assert_eq!(
+            solve("solve_exact(x^2 - 2 = 0, x)"),
+            bindings(&[("x", "±sqrt(2)")])
+        );
+        assert_eq!(
+            solve("solve_exact(x^2 + x - 1 = 0, x)"),
+            bindings(&[("x", "-1/2 ± sqrt(5)/2")])
+        );
+        assert_eq!(
+            solve("solve_exact(x^2 + 1 = 0, x)"),
+            bindings(&[("x", "±i")])
+        );
+    }
+
+    #[test]
+    fn rational_roots_as_fractions() {
+        assert_eq!(
+            solve("solve_exact(2x + 1 = 0, x)"),
+            bindings(&[("x", "-1/2")])
+        );
+        assert_eq!(
+            solve("solve_exact(x^3 - 6x^2 + 11x - 6 = 0, x)"),
+            bindings(&[("x", "1"), ("x", "2"), ("x", "3")])
+        );
+    }
+
+    #[test]
+    fn linear_systems_in_fractions() {
+        assert_eq!(
+            solve("solve_exact((2x + 3y = 1, x - y = 2), (x, y))"),
+            bindings(&[("x", "7/5"), ("y", "-3/5")])
+        );
+    }
+
+    #[test]
+    fn real_values_are_kept_for_expressions() {
+        let mut repl = Repl::new();
+        let solution = repl
+            .eval_solve_exact(&["x^2 - 2 = 0".to_string(), "x".to_string()])
+            .unwrap();
+        let root = std::f64::consts::SQRT_2;
+        assert_eq!(solution.values.len(), 2);
+        assert!(solution
+            .values
+            .iter()
+            .all(|value| (value.abs() - root).abs() < 1e-12));
+    }
+}
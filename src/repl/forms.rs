@@ -0,0 +1,310 @@
+use super::functions::Scope;
+use super::symbolic::Expr;
+use super::Repl;
+use super::ReplError;
+use evalexpr::build_operator_tree;
+use evalexpr::eval_with_context_mut;
+use evalexpr::EvalexprError;
+use evalexpr::Node;
+use evalexpr::Value;
+use std::error::Error;
+
+/// Calls whose arguments are expressions rather than values, e.g. the body of
+/// `sum(k, 1, n, 1/k)`. They are evaluated by the REPL itself and replaced by
+/// their result before the rest of the input is handed to evalexpr.
+pub const SPECIAL_FORMS: &[&str] = &[
+    "sum",
+    "prod",
+    "seq",
+    "limit",
+    "limit_left",
+    "limit_right",
+    "taylor",
+    "ode",
+    "linsolve",
+    "nsolve",
+    "solve_exact",
+    "sample",
+    "minimize",
+    "maximize",
+    "simplify",
+    "expand",
+    "factor",
+    "partial",
+    "grad",
+    "hessian",
+    "jacobian",
+    "latex",
+    "mathml",
+];
+
+/// Special forms that produce an expression rather than a value. These are
+/// also expanded in function definitions and in `draw(...)`.
+pub const EXPRESSION_FORMS: &[&str] = &["taylor", "simplify", "expand", "factor", "partial"];
+
+/// The result of a special form.
+pub enum FormValue {
+    Value(Value),
+    Expression(Expr),
+}
+
+/// An expression that is evaluated many times with different bindings.
+pub enum Body {
+    Compiled(Node),
+    Source(String),
+}
+
+impl Repl {
+    /// Evaluate an input with some extra names bound on top of the context.
+    pub(super) fn evaluate(
+        &mut self,
+        input: &str,
+        bindings: &[(String, Value)],
+    ) -> Result<Value, Box<dyn Error>> {
+        let mut bindings = bindings.to_vec();
+        let expanded = self.expand_forms(input, &mut bindings, false)?;
+        let mut scope = Scope::new(&mut self.context, bindings);
+
+        Ok(eval_with_context_mut(&expanded, &mut scope)?)
+    }
+
+    /// Evaluate an input that has to produce a number.
+    pub(super) fn evaluate_number(
+        &mut self,
+        input: &str,
+        bindings: &[(String, Value)],
+    ) -> Result<f64, Box<dyn Error>> {
+        value_to_number(self.evaluate(input, bindings)?)
+    }
+
+    /// Prepare an expression for repeated evaluation. Bodies containing special
+    /// forms have to be expanded again every time, everything else is parsed once.
+    pub(super) fn compile_body(&self, body: &str) -> Result<Body, Box<dyn Error>> {
+        if self.regex_form_pattern.is_match(body) {
+            Ok(Body::Source(body.to_string()))
+        } else {
+            Ok(Body::Compiled(build_operator_tree(body)?))
+        }
+    }
+
+    pub(super) fn evaluate_body(
+        &mut self,
+        body: &Body,
+        bindings: &[(String, Value)],
+    ) -> Result<Value, Box<dyn Error>> {
+        self.budget.check()?;
+
+        match body {
+            Body::Compiled(node) => {
+                let scope = Scope::new(&self.context, bindings.to_vec());
+                Ok(node.eval_with_context(&scope)?)
+            }
+            Body::Source(source) => self.evaluate(source, bindings),
+        }
+    }
+
+    /// Replace the expression forms in an input by the expressions they produce,
+    /// leaving everything else untouched.
+    pub(super) fn expand_expression_forms(
+        &mut self,
+        input: &str,
+    ) -> Result<String, Box<dyn Error>> {
+        self.expand_forms(input, &mut Vec::new(), true)
+    }
+
+    /// If the whole input is a single expression form, the expression it produces.
+    pub(super) fn expression_form(&mut self, input: &str) -> Result<Option<Expr>, Box<dyn Error>> {
+        let Some((name, arguments)) = self.whole_form(input) else {
+            return Ok(None);
+        };
+
+        if !EXPRESSION_FORMS.contains(&name.as_str()) {
+            return Ok(None);
+        }
+
+        match self.eval_form(&name, &arguments, &[])? {
+            FormValue::Expression(expression) => Ok(Some(expression)),
+            FormValue::Value(_) => Ok(None),
+        }
+    }
+
+    /// If the whole input is a single special form, its name and arguments.
+    pub(super) fn whole_form(&self, input: &str) -> Option<(String, Vec<String>)> {
+        let captures = self.regex_form_pattern.captures(input)?;
+        let call = captures.get(0).unwrap();
+        let name = captures.get(1).unwrap().as_str().to_string();
+
+        if call.start() != 0 || find_closing_paren(input, call.end()) != Some(input.len() - 1) {
+            return None;
+        }
+
+        Some((name, split_arguments(&input[call.end()..input.len() - 1])))
+    }
+
+    // Replace every special form in the input, outermost first, by a temporary
+    // name bound to its value, or by the expression it produces.
+    fn expand_forms(
+        &mut self,
+        input: &str,
+        bindings: &mut Vec<(String, Value)>,
+        only_expressions: bool,
+    ) -> Result<String, Box<dyn Error>> {
+        let mut expanded = String::new();
+        let mut rest = input;
+
+        while let Some(captures) = self.regex_form_pattern.captures(rest) {
+            let call = captures.get(0).unwrap();
+            let name = captures.get(1).unwrap().as_str().to_string();
+            let arguments_end =
+                find_closing_paren(rest, call.end()).ok_or(ReplError::InvalidArgument)?;
+            let arguments = split_arguments(&rest[call.end()..arguments_end]);
+
+            expanded.push_str(&rest[..call.start()]);
+
+            if only_expressions && !EXPRESSION_FORMS.contains(&name.as_str()) {
+                expanded.push_str(&rest[call.start()..=arguments_end]);
+            } else {
+                match self.eval_form(&name, &arguments, bindings)? {
+                    // Nothing keeps temporaries while only expression forms are
+                    // expanded, so the value of `partial(f, x, P)` is written out.
+                    FormValue::Value(Value::Float(value)) if only_expressions => {
+                        expanded.push_str(&format!("({})", Expr::Number(value).to_source()));
+                    }
+                    FormValue::Value(value) => {
+                        let temporary = format!("__form{}", bindings.len());
+                        bindings.push((temporary.clone(), value));
+                        expanded.push_str(&temporary);
+                    }
+                    FormValue::Expression(expression) => {
+                        expanded.push_str(&format!("({})", expression.to_source()));
+                    }
+                }
+            }
+
+            rest = &rest[arguments_end + 1..];
+        }
+
+        expanded.push_str(rest);
+        Ok(expanded)
+    }
+
+    fn eval_form(
+        &mut self,
+        name: &str,
+        arguments: &[String],
+        bindings: &[(String, Value)],
+    ) -> Result<FormValue, Box<dyn Error>> {
+        let value = match name {
+            "sum" => self.eval_sum(arguments, bindings)?,
+            "prod" => self.eval_prod(arguments, bindings)?,
+            "seq" => self.eval_seq(arguments, bindings)?,
+            "limit" => self.eval_limit(arguments, bindings)?,
+            "limit_left" => self.eval_limit_left(arguments, bindings)?,
+            "limit_right" => self.eval_limit_right(arguments, bindings)?,
+            "ode" => self.eval_ode(arguments, bindings)?,
+            "linsolve" => self.eval_linsolve(arguments, bindings)?.to_value(),
+            "nsolve" => self.eval_nsolve(arguments, bindings)?.to_value(),
+            "solve_exact" => self.eval_solve_exact(arguments)?.to_value()?,
+            "sample" => self.eval_sample(arguments, bindings)?,
+            "minimize" => self.eval_optimum(arguments, bindings, false)?.to_value(),
+            "maximize" => self.eval_optimum(arguments, bindings, true)?.to_value(),
+            "taylor" => {
+                return Ok(FormValue::Expression(
+                    self.eval_taylor(arguments, bindings)?,
+                ))
+            }
+            "simplify" | "expand" | "factor" => {
+                return Ok(FormValue::Expression(self.eval_algebra(name, arguments)?))
+            }
+            "partial" => return self.eval_partial(arguments, bindings),
+            "grad" | "hessian" | "jacobian" => self.eval_derivatives(name, arguments, bindings)?,
+            "latex" | "mathml" => self.eval_typeset(name, arguments)?,
+            _ => {
+                return Err(Box::new(EvalexprError::FunctionIdentifierNotFound(
+                    name.to_string(),
+                )))
+            }
+        };
+
+        Ok(FormValue::Value(value))
+    }
+}
+
+pub fn value_to_number(value: Value) -> Result<f64, Box<dyn Error>> {
+    match value {
+        Value::Float(value) => Ok(value),
+        Value::Int(value) => Ok(value as f64),
+        actual => Err(Box::new(EvalexprError::ExpectedNumber { actual })),
+    }
+}
+
+/// Check the number of arguments passed to a special form.
+pub fn expect_arguments(arguments: &[String], expected: usize) -> Result<(), Box<dyn Error>> {
+    if arguments.len() == expected {
+        Ok(())
+    } else {
+        Err(Box::new(EvalexprError::wrong_function_argument_amount(
+            arguments.len(),
+            expected,
+        )))
+    }
+}
+
+/// The name of a variable bound by a special form, e.g. the `k` in `sum(k, 1, n, k^2)`.
+pub fn bound_variable(argument: &str) -> Result<String, Box<dyn Error>> {
+    let mut chars = argument.chars();
+    let is_identifier = chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_');
+
+    if is_identifier {
+        Ok(argument.to_string())
+    } else {
+        Err(Box::new(ReplError::InvalidArgument))
+    }
+}
+
+pub fn is_infinity(argument: &str) -> bool {
+    matches!(argument, "inf" | "infinity" | "∞")
+}
+
+pub fn find_closing_paren(input: &str, start: usize) -> Option<usize> {
+    let mut depth = 0;
+
+    for (index, ch) in input[start..].char_indices() {
+        match ch {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Some(start + index),
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Split a comma separated argument list, ignoring commas inside parentheses.
+pub fn split_arguments(arguments: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+
+    for ch in arguments.chars() {
+        match ch {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                result.push(current.trim().to_string());
+                current.clear();
+                continue;
+            }
+            _ => {}
+        }
+        current.push(ch);
+    }
+
+    if !current.trim().is_empty() || !result.is_empty() {
+        result.push(current.trim().to_string());
+    }
+
+    result
+}
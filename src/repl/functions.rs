@@ -0,0 +1,223 @@
+use super::budget::Budget;
+use super::interpolation::Interpolation;
+use evalexpr::build_operator_tree;
+use evalexpr::Context;
+use evalexpr::ContextWithMutableVariables;
+use evalexpr::EvalexprError;
+use evalexpr::EvalexprResult;
+use evalexpr::Function;
+use evalexpr::HashMapContext;
+use evalexpr::Node;
+use evalexpr::Value;
+use std::collections::BTreeMap;
+use std::ops::Deref;
+use std::ops::DerefMut;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::RwLock;
+
+/// A function defined from the REPL, e.g. `f(x, y) = x^2 + y`. Functions
+/// with initial values, e.g. `a(0) = 1`, are sequences defined by recurrence.
+/// Interpolants, e.g. `f = cubic_spline(points)`, keep the body that calls the
+/// interpolation function for display, and are evaluated from `interpolation`.
+#[derive(Debug, Clone, Default)]
+pub struct UserFunction {
+    pub params: Vec<String>,
+    pub body: String,
+    pub initial_values: BTreeMap<i64, f64>,
+    pub memo: Arc<Mutex<BTreeMap<i64, Value>>>,
+    pub interpolation: Option<Arc<Interpolation>>,
+}
+
+impl UserFunction {
+    /// Build the evalexpr function that evaluates the body against the shared
+    /// globals, with the parameters bound to the call arguments.
+    pub fn to_function(
+        &self,
+        name: &str,
+        globals: Arc<RwLock<HashMapContext>>,
+        budget: Budget,
+    ) -> EvalexprResult<Function> {
+        let body = if self.body.is_empty() {
+            None
+        } else {
+            Some(build_operator_tree(&self.body)?)
+        };
+
+        if !self.initial_values.is_empty() {
+            return Ok(self.to_sequence(name, body, globals, budget));
+        }
+
+        if let Some(interpolation) = self.interpolation.clone() {
+            return Ok(Function::new(move |argument| {
+                Ok(Value::Float(interpolation.at(argument.as_number()?)?))
+            }));
+        }
+
+        let params = self.params.clone();
+
+        Ok(Function::new(move |argument| {
+            let arguments = match argument {
+                _ if params.len() == 1 => vec![argument.clone()],
+                Value::Tuple(values) => values.clone(),
+                Value::Empty => Vec::new(),
+                value => vec![value.clone()],
+            };
+
+            if arguments.len() != params.len() {
+                return Err(EvalexprError::wrong_function_argument_amount(
+                    arguments.len(),
+                    params.len(),
+                ));
+            }
+
+            let Some(body) = &body else {
+                return Err(EvalexprError::CustomMessage(
+                    "function has no body".to_string(),
+                ));
+            };
+
+            let _call = budget.enter()?;
+            let globals = globals.read().unwrap();
+            let scope = Scope::new(&*globals, params.iter().cloned().zip(arguments).collect());
+
+            body.eval_with_context(&scope)
+        }))
+    }
+
+    // Terms of a recurrence are computed in order starting from the first initial
+    // value, so every call the body makes to earlier terms hits the memo instead
+    // of recursing.
+    fn to_sequence(
+        &self,
+        name: &str,
+        body: Option<Node>,
+        globals: Arc<RwLock<HashMapContext>>,
+        budget: Budget,
+    ) -> Function {
+        let name = name.to_string();
+        let param = self
+            .params
+            .first()
+            .cloned()
+            .unwrap_or_else(|| "n".to_string());
+        let initial_values = self.initial_values.clone();
+        let memo = self.memo.clone();
+
+        Function::new(move |argument| {
+            let n = match argument {
+                Value::Int(n) => *n,
+                Value::Float(n) if n.fract() == 0.0 => *n as i64,
+                actual => {
+                    return Err(EvalexprError::ExpectedInt {
+                        actual: actual.clone(),
+                    })
+                }
+            };
+
+            if let Some(value) = initial_values.get(&n) {
+                return Ok(Value::Float(*value));
+            }
+
+            if let Some(value) = memo.lock().unwrap().get(&n) {
+                return Ok(value.clone());
+            }
+
+            let first = *initial_values.keys().next().unwrap();
+            if n < first {
+                return Err(EvalexprError::CustomMessage(format!(
+                    "{}({}) comes before the first initial value {}({})",
+                    name, n, name, first
+                )));
+            }
+
+            let Some(body) = &body else {
+                return Err(EvalexprError::CustomMessage(format!(
+                    "{} has initial values but no recurrence",
+                    name
+                )));
+            };
+
+            let _call = budget.enter()?;
+            for m in first..=n {
+                if initial_values.contains_key(&m) || memo.lock().unwrap().contains_key(&m) {
+                    continue;
+                }
+
+                budget.allocate(std::mem::size_of::<(i64, Value)>())?;
+
+                let value = {
+                    let globals = globals.read().unwrap();
+                    let scope =
+                        Scope::new(&*globals, vec![(param.clone(), Value::Float(m as f64))]);
+                    body.eval_with_context(&scope)?
+                };
+                memo.lock().unwrap().insert(m, value);
+            }
+
+            Ok(memo.lock().unwrap()[&n].clone())
+        })
+    }
+
+    /// The definition as it would be typed into the REPL.
+    pub fn describe(&self, name: &str) -> String {
+        let mut parts = Vec::new();
+
+        if !self.body.is_empty() {
+            parts.push(format!(
+                "{}({}) = {}",
+                name,
+                self.params.join(", "),
+                self.body
+            ));
+        }
+
+        for (index, value) in &self.initial_values {
+            parts.push(format!("{}({}) = {}", name, index, value));
+        }
+
+        parts.join(", ")
+    }
+}
+
+/// A context that layers local bindings over a parent context. Writes go
+/// straight to the parent when it is mutable.
+pub struct Scope<P> {
+    parent: P,
+    bindings: Vec<(String, Value)>,
+}
+
+impl<P: Deref<Target = HashMapContext>> Scope<P> {
+    pub fn new(parent: P, bindings: Vec<(String, Value)>) -> Self {
+        Scope { parent, bindings }
+    }
+}
+
+impl<P: Deref<Target = HashMapContext>> Context for Scope<P> {
+    fn get_value(&self, identifier: &str) -> Option<&Value> {
+        self.bindings
+            .iter()
+            .rev()
+            .find(|(name, _)| name == identifier)
+            .map(|(_, value)| value)
+            .or_else(|| self.parent.get_value(identifier))
+    }
+
+    fn call_function(&self, identifier: &str, argument: &Value) -> EvalexprResult<Value> {
+        self.parent.call_function(identifier, argument)
+    }
+
+    fn are_builtin_functions_disabled(&self) -> bool {
+        self.parent.are_builtin_functions_disabled()
+    }
+
+    fn set_builtin_functions_disabled(&mut self, _disabled: bool) -> EvalexprResult<()> {
+        Err(EvalexprError::ContextNotMutable)
+    }
+}
+
+impl<P: DerefMut<Target = HashMapContext>> ContextWithMutableVariables for Scope<P> {
+    fn set_value(&mut self, identifier: String, value: Value) -> EvalexprResult<()> {
+        self.parent.set_value(identifier, value)
+    }
+}
@@ -0,0 +1,343 @@
+use super::parse_list;
+use super::solve::split_equation;
+use super::Point;
+use super::Repl;
+use super::ReplResult;
+use evalexpr::ContextWithMutableFunctions;
+use evalexpr::EvalexprError;
+use evalexpr::EvalexprResult;
+use evalexpr::Function;
+use evalexpr::Value;
+use std::error::Error;
+use std::sync::Arc;
+
+/// The interpolation functions, each called as `name(points, x)` with a list of
+/// points made with `List(...)`. Writing `f = name(points)` defines `f(x)`.
+pub const INTERPOLATION_FUNCTIONS: [&str; 3] = ["interp_linear", "lagrange", "cubic_spline"];
+
+/// An interpolant through data points, read and sorted, and for the cubic
+/// spline with its second derivatives at the points already solved.
+#[derive(Debug, Clone)]
+pub enum Interpolation {
+    Linear(Vec<Point>),
+    Lagrange(Vec<Point>),
+    CubicSpline {
+        points: Vec<Point>,
+        moments: Vec<f64>,
+    },
+}
+
+impl Interpolation {
+    /// The interpolant `name`, one of `INTERPOLATION_FUNCTIONS`, through the points.
+    pub fn new(name: &str, points: Vec<Point>) -> EvalexprResult<Self> {
+        let minimum_points = if name == "lagrange" { 1 } else { 2 };
+        if points.len() < minimum_points {
+            return Err(EvalexprError::CustomMessage(format!(
+                "{} needs at least {} points",
+                name, minimum_points
+            )));
+        }
+
+        match name {
+            "interp_linear" => Ok(Interpolation::Linear(points)),
+            "lagrange" => Ok(Interpolation::Lagrange(points)),
+            "cubic_spline" => Ok(Interpolation::CubicSpline {
+                moments: spline_moments(&points),
+                points,
+            }),
+            _ => Err(EvalexprError::FunctionIdentifierNotFound(name.to_string())),
+        }
+    }
+
+    pub fn at(&self, x: f64) -> EvalexprResult<f64> {
+        match self {
+            Interpolation::Linear(points) => interp_linear(points, x),
+            Interpolation::Lagrange(points) => Ok(lagrange(points, x)),
+            Interpolation::CubicSpline { points, moments } => cubic_spline(points, moments, x),
+        }
+    }
+}
+
+impl Repl {
+    pub(super) fn setup_interpolation_functions(&mut self) {
+        for name in INTERPOLATION_FUNCTIONS {
+            self.context
+                .set_function(
+                    name.to_string(),
+                    Function::new(move |args| {
+                        let tuple = args.as_fixed_len_tuple(2)?;
+                        let points = interpolation_points(&tuple[0])?;
+                        let x = tuple[1].as_number()?;
+
+                        Ok(Value::Float(Interpolation::new(name, points)?.at(x)?))
+                    }),
+                )
+                .unwrap();
+        }
+    }
+
+    /// `f = cubic_spline(points)`, which defines `f(x)` as the interpolant. It
+    /// is set up once, through the points the list holds at the time.
+    pub(super) fn define_interpolant(
+        &mut self,
+        input: &str,
+    ) -> Option<Result<ReplResult, Box<dyn Error>>> {
+        let (name, source) = split_equation(input)?;
+        let name = name.trim();
+
+        if !is_name(name) {
+            return None;
+        }
+
+        let (interpolant, list) = interpolant_call(source)?;
+        let body = expand_interpolant(source)?;
+        Some(self.define_interpolation(name, interpolant, list, &body))
+    }
+
+    fn define_interpolation(
+        &mut self,
+        name: &str,
+        interpolant: &str,
+        list: &str,
+        body: &str,
+    ) -> Result<ReplResult, Box<dyn Error>> {
+        self.sync_globals();
+        let points = interpolation_points(&self.evaluate(list, &[])?)?;
+        let interpolation = Interpolation::new(interpolant, points)?;
+
+        self.define_function(name, vec!["x".to_string()], body)?;
+        if let Some(function) = self.functions.get_mut(name) {
+            function.interpolation = Some(Arc::new(interpolation));
+        }
+        self.register_function(name)?;
+
+        Ok(ReplResult::Empty)
+    }
+
+    /// The data points behind `interp_linear(points)` and friends, so the graph
+    /// can mark them under the interpolant.
+    pub fn interpolation_nodes(&self, input: &str) -> Vec<Point> {
+        interpolant_call(input)
+            .and_then(|(_, list)| evalexpr::Context::get_value(&self.context, list))
+            .and_then(|value| interpolation_points(value).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// Rewrite `cubic_spline(points)` as `cubic_spline(points, x)`, a function of `x`.
+pub fn expand_interpolant(input: &str) -> Option<String> {
+    let (name, list) = interpolant_call(input)?;
+    Some(format!("{}({}, x)", name, list))
+}
+
+// Split `cubic_spline(points)` into the function and the name of the list.
+fn interpolant_call(input: &str) -> Option<(&str, &str)> {
+    let (name, rest) = input.trim().split_once('(')?;
+    let (name, list) = (name.trim(), rest.strip_suffix(')')?.trim());
+
+    if INTERPOLATION_FUNCTIONS.contains(&name) && is_name(list) {
+        Some((name, list))
+    } else {
+        None
+    }
+}
+
+fn is_name(source: &str) -> bool {
+    !source.is_empty() && source.chars().all(|ch| ch.is_alphanumeric() || ch == '_')
+}
+
+// Read the data points, either a list of points or a tuple of pairs, sorted
+// by `x`.
+fn interpolation_points(value: &Value) -> EvalexprResult<Vec<Point>> {
+    let not_points =
+        || EvalexprError::CustomMessage("interpolation needs a list of points".to_string());
+
+    let mut points = match value {
+        Value::String(value) => parse_list(value)
+            .ok_or_else(not_points)?
+            .items
+            .into_iter()
+            .map(|item| match item {
+                ReplResult::Point(point) => Ok(point),
+                _ => Err(not_points()),
+            })
+            .collect::<EvalexprResult<Vec<Point>>>()?,
+        Value::Tuple(values) => values
+            .iter()
+            .map(|value| {
+                let pair = value.as_fixed_len_tuple(2).map_err(|_| not_points())?;
+                Ok(Point {
+                    x: pair[0].as_number()?,
+                    y: pair[1].as_number()?,
+                })
+            })
+            .collect::<EvalexprResult<Vec<Point>>>()?,
+        _ => return Err(not_points()),
+    };
+
+    points.sort_by(|a, b| a.x.total_cmp(&b.x));
+
+    if points
+        .iter()
+        .any(|point| !point.x.is_finite() || !point.y.is_finite())
+    {
+        return Err(not_points());
+    }
+
+    if points.windows(2).any(|pair| pair[0].x == pair[1].x) {
+        return Err(EvalexprError::CustomMessage(
+            "interpolation points must have distinct x values".to_string(),
+        ));
+    }
+
+    Ok(points)
+}
+
+// The index of the interval `[x_i, x_i+1]` holding `x`, or an error outside the data.
+fn segment(points: &[Point], x: f64) -> EvalexprResult<usize> {
+    let first = points[0].x;
+    let last = points[points.len() - 1].x;
+
+    if !(first..=last).contains(&x) {
+        return Err(EvalexprError::CustomMessage(format!(
+            "{} is outside the interpolation range [{}, {}]",
+            x, first, last
+        )));
+    }
+
+    let index = points.partition_point(|point| point.x <= x);
+    Ok(index.clamp(1, points.len() - 1) - 1)
+}
+
+fn interp_linear(points: &[Point], x: f64) -> EvalexprResult<f64> {
+    let i = segment(points, x)?;
+    let (a, b) = (points[i], points[i + 1]);
+    let t = (x - a.x) / (b.x - a.x);
+
+    Ok(a.y + t * (b.y - a.y))
+}
+
+// The interpolating polynomial through every point, in barycentric form.
+fn lagrange(points: &[Point], x: f64) -> f64 {
+    let mut numerator = 0.0;
+    let mut denominator = 0.0;
+
+    for (j, point) in points.iter().enumerate() {
+        if x == point.x {
+            return point.y;
+        }
+
+        let weight = 1.0
+            / points
+                .iter()
+                .enumerate()
+                .filter(|(k, _)| *k != j)
+                .map(|(_, other)| point.x - other.x)
+                .product::<f64>();
+        let term = weight / (x - point.x);
+
+        numerator += term * point.y;
+        denominator += term;
+    }
+
+    numerator / denominator
+}
+
+// The natural cubic spline, whose second derivative vanishes at both ends,
+// given its second derivatives at the points.
+fn cubic_spline(points: &[Point], moments: &[f64], x: f64) -> EvalexprResult<f64> {
+    let i = segment(points, x)?;
+
+    let (a, b) = (points[i], points[i + 1]);
+    let h = b.x - a.x;
+    let (left, right) = (b.x - x, x - a.x);
+
+    Ok(moments[i] * left.powi(3) / (6.0 * h)
+        + moments[i + 1] * right.powi(3) / (6.0 * h)
+        + (a.y / h - moments[i] * h / 6.0) * left
+        + (b.y / h - moments[i + 1] * h / 6.0) * right)
+}
+
+// The second derivative of the spline at each point, from the tridiagonal
+// continuity equations solved with the Thomas algorithm.
+fn spline_moments(points: &[Point]) -> Vec<f64> {
+    let n = points.len();
+    let mut moments = vec![0.0; n];
+
+    if n < 3 {
+        return moments;
+    }
+
+    let h: Vec<f64> = points
+        .windows(2)
+        .map(|pair| pair[1].x - pair[0].x)
+        .collect();
+    let slope = |i: usize| (points[i + 1].y - points[i].y) / h[i];
+
+    // Forward sweep over the interior points 1..n-1.
+    let mut upper = vec![0.0; n];
+    let mut rhs = vec![0.0; n];
+    for i in 1..n - 1 {
+        let lower = h[i - 1];
+        let diagonal = 2.0 * (h[i - 1] + h[i]) - lower * upper[i - 1];
+        upper[i] = h[i] / diagonal;
+        rhs[i] = (6.0 * (slope(i) - slope(i - 1)) - lower * rhs[i - 1]) / diagonal;
+    }
+
+    for i in (1..n - 1).rev() {
+        moments[i] = rhs[i] - upper[i] * moments[i + 1];
+    }
+
+    moments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(coordinates: &[(f64, f64)]) -> Vec<Point> {
+        coordinates.iter().map(|&(x, y)| Point { x, y }).collect()
+    }
+
+    #[test]
+    fn interpolants_pass_through_known_values() {
+        let line = Interpolation::new("interp_linear", points(&[(0.0, 0.0), (2.0, 4.0)])).unwrap();
+        assert_eq!(line.at(0.5).unwrap(), 1.0);
+        assert!(line.at(3.0).is_err());
+
+        // Three points of y = x^2 give back the parabola.
+        let parabola =
+            Interpolation::new("lagrange", points(&[(0.0, 0.0), (1.0, 1.0), (3.0, 9.0)])).unwrap();
+        assert!((parabola.at(2.0).unwrap() - 4.0).abs() < 1e-12);
+
+        // The natural spline through three points on a line is that line.
+        let spline = Interpolation::new(
+            "cubic_spline",
+            points(&[(0.0, 1.0), (1.0, 3.0), (2.0, 5.0)]),
+        )
+        .unwrap();
+        assert!((spline.at(1.5).unwrap() - 4.0).abs() < 1e-12);
+
+        assert!(Interpolation::new("cubic_spline", points(&[(0.0, 0.0)])).is_err());
+    }
+
+    #[test]
+    fn defined_interpolant_keeps_its_points() {
+        let mut repl = Repl::new();
+        repl.process_input("p = List(Point(0, 0), Point(1, 1), Point(2, 0))")
+            .unwrap();
+        repl.process_input("f = cubic_spline(p)").unwrap();
+        let before = repl.process_input("f(0.5)").unwrap();
+
+        repl.process_input("p = List(Point(0, 5), Point(2, 5))")
+            .unwrap();
+        assert_eq!(
+            format!("{:?}", repl.process_input("f(0.5)").unwrap()),
+            format!("{:?}", before)
+        );
+        assert_eq!(
+            format!("{:?}", repl.process_input("cubic_spline(p, 0.5)").unwrap()),
+            format!("{:?}", ReplResult::Number(5.0))
+        );
+    }
+}
@@ -0,0 +1,412 @@
+use super::ReplError;
+use std::f64::consts::PI;
+
+/// LaTeX functions and the REPL functions they stand for.
+const LATEX_FUNCTIONS: &[(&str, &str)] = &[
+    ("sin", "sin"),
+    ("cos", "cos"),
+    ("tan", "tan"),
+    ("sec", "sec"),
+    ("csc", "cosec"),
+    ("cot", "cot"),
+    ("arcsin", "asin"),
+    ("arccos", "acos"),
+    ("arctan", "atan"),
+    ("sinh", "sinh"),
+    ("cosh", "cosh"),
+    ("tanh", "tanh"),
+    ("coth", "coth"),
+    ("exp", "exp"),
+    ("ln", "ln"),
+    ("log", "log"),
+    ("Gamma", "gamma"),
+    ("zeta", "zeta"),
+];
+
+/// LaTeX commands that are operators or spacing.
+const LATEX_SYMBOLS: &[(&str, &str)] = &[
+    ("cdot", "*"),
+    ("times", "*"),
+    ("div", "/"),
+    ("le", "<="),
+    ("leq", "<="),
+    ("ge", ">="),
+    ("geq", ">="),
+    ("ne", "!="),
+    ("neq", "!="),
+    ("lt", "<"),
+    ("gt", ">"),
+    ("vert", "|"),
+    ("lvert", "|"),
+    ("rvert", "|"),
+    ("mid", "|"),
+    (",", " "),
+    (";", " "),
+    (":", " "),
+    ("!", ""),
+    (" ", " "),
+    ("quad", " "),
+    ("qquad", " "),
+];
+
+/// Greek letters, which become variables of the same name.
+const GREEK_LETTERS: &[&str] = &[
+    "alpha",
+    "beta",
+    "gamma",
+    "delta",
+    "epsilon",
+    "varepsilon",
+    "eta",
+    "theta",
+    "vartheta",
+    "iota",
+    "kappa",
+    "lambda",
+    "mu",
+    "nu",
+    "xi",
+    "rho",
+    "sigma",
+    "tau",
+    "phi",
+    "varphi",
+    "chi",
+    "psi",
+    "omega",
+];
+
+/// Whether the input is written in LaTeX, i.e. has a command such as `\frac`
+/// or a group in braces outside of string literals.
+pub fn is_latex(input: &str) -> bool {
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for ch in input.chars() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '\\' | '{' if !in_string => return true,
+            _ => {}
+        }
+    }
+
+    false
+}
+
+/// Translate a LaTeX formula such as `\frac{1}{2}\sin(x)^2 + \sqrt{x}` into
+/// conventional notation for `normalize`. As in LaTeX, adjacent letters are
+/// separate variables, except for a name directly followed by parentheses,
+/// which is a function call.
+pub fn translate(input: &str) -> Result<String, ReplError> {
+    let mut reader = Reader {
+        chars: input.chars().collect(),
+        position: 0,
+    };
+    reader.sequence(None)
+}
+
+struct Reader {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl Reader {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.position += 1;
+        }
+    }
+
+    fn starts_with(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        self.chars[self.position..].starts_with(&text)
+    }
+
+    // Translate up to the closing brace or parenthesis, which is consumed, or to
+    // the end of the input.
+    fn sequence(&mut self, end: Option<char>) -> Result<String, ReplError> {
+        let mut output = String::new();
+        let mut depth = 0;
+
+        while let Some(ch) = self.peek() {
+            self.position += 1;
+
+            match ch {
+                '}' if end == Some('}') => return Ok(output),
+                '}' => return Err(latex("unmatched `}`")),
+                ')' | ']' if depth == 0 && end == Some(')') => return Ok(output),
+                ')' | ']' => {
+                    depth -= 1;
+                    output.push(')');
+                }
+                '(' | '[' => {
+                    depth += 1;
+                    output.push('(');
+                }
+                '{' => output.push_str(&format!("({})", self.sequence(Some('}'))?)),
+                '\\' => output.push_str(&self.command()?),
+                '^' => output.push_str(&format!("^({})", self.argument()?)),
+                '_' => output.push_str(&format!("_{}", self.subscript()?)),
+                'e' if self.peek() == Some('^') && !ends_with_letter(&output) => {
+                    self.position += 1;
+                    output.push_str(&format!("exp({})", self.argument()?));
+                }
+                _ if ch.is_alphabetic() => {
+                    output.push(ch);
+                    let is_call = self.chars[self.position..]
+                        .iter()
+                        .find(|ch| !ch.is_alphanumeric())
+                        == Some(&'(');
+                    if !is_call && self.peek().is_some_and(char::is_alphabetic) {
+                        output.push(' ');
+                    }
+                }
+                _ => output.push(ch),
+            }
+        }
+
+        match end {
+            Some('}') => Err(latex("unmatched `{`")),
+            Some(_) => Err(latex("unmatched `(`")),
+            None => Ok(output),
+        }
+    }
+
+    // The argument of a command or exponent: a group in braces, a command or a
+    // single character, as in `\frac12` or `x^2`.
+    fn argument(&mut self) -> Result<String, ReplError> {
+        self.skip_spaces();
+
+        match self.peek() {
+            Some('{') => {
+                self.position += 1;
+                self.sequence(Some('}'))
+            }
+            Some('\\') => {
+                self.position += 1;
+                self.command()
+            }
+            Some(ch) => {
+                self.position += 1;
+                Ok(ch.to_string())
+            }
+            None => Err(latex("missing argument at the end")),
+        }
+    }
+
+    // The letters and digits of a subscript, which become part of the name, so
+    // that `x_{12}` is `x_12`.
+    fn subscript(&mut self) -> Result<String, ReplError> {
+        let subscript = self.raw_argument()?;
+        if subscript
+            .chars()
+            .all(|ch| ch.is_alphanumeric() || ch == '_')
+        {
+            Ok(subscript)
+        } else {
+            Err(latex(&format!(
+                "subscript `{}` is not part of a name",
+                subscript
+            )))
+        }
+    }
+
+    // An argument taken as it is, e.g. the name in `\operatorname{sinc}`.
+    fn raw_argument(&mut self) -> Result<String, ReplError> {
+        self.skip_spaces();
+
+        if self.peek() != Some('{') {
+            return self.peek().map_or_else(
+                || Err(latex("missing argument at the end")),
+                |ch| {
+                    self.position += 1;
+                    Ok(ch.to_string())
+                },
+            );
+        }
+
+        let start = self.position + 1;
+        let Some(length) = self.chars[start..].iter().position(|&ch| ch == '}') else {
+            return Err(latex("unmatched `{`"));
+        };
+        self.position = start + length + 1;
+        Ok(self.chars[start..start + length]
+            .iter()
+            .collect::<String>()
+            .trim()
+            .to_string())
+    }
+
+    // A command after its backslash.
+    fn command(&mut self) -> Result<String, ReplError> {
+        let start = self.position;
+        while self.peek().is_some_and(|ch| ch.is_ascii_alphabetic()) {
+            self.position += 1;
+        }
+        if self.position == start && self.peek().is_some() {
+            self.position += 1;
+        }
+        let name: String = self.chars[start..self.position].iter().collect();
+
+        if let Some((_, function)) = LATEX_FUNCTIONS.iter().find(|(latex, _)| *latex == name) {
+            return self.function(function);
+        }
+        if let Some((_, symbol)) = LATEX_SYMBOLS.iter().find(|(latex, _)| *latex == name) {
+            return Ok(symbol.to_string());
+        }
+        if GREEK_LETTERS.contains(&name.as_str()) {
+            return Ok(format!(" {} ", name));
+        }
+
+        match name.as_str() {
+            "frac" | "dfrac" | "tfrac" => {
+                let numerator = self.argument()?;
+                let denominator = self.argument()?;
+                // A fraction is a real division, even of integers.
+                Ok(format!("(1.0*({})/({}))", numerator, denominator))
+            }
+            "sqrt" => {
+                self.skip_spaces();
+                if self.peek() == Some('[') {
+                    self.position += 1;
+                    let index = self.sequence(Some(')'))?;
+                    let radicand = self.argument()?;
+                    Ok(format!("(({})^(1.0/({})))", radicand, index))
+                } else {
+                    Ok(format!("sqrt({})", self.argument()?))
+                }
+            }
+            "pi" => Ok(format!("({})", PI)),
+            // `\left(` and `\right)` size the delimiter that follows, `.` is none.
+            "left" | "right" => {
+                self.skip_spaces();
+                if self.peek() == Some('.') {
+                    self.position += 1;
+                }
+                Ok(String::new())
+            }
+            "operatorname" | "mathrm" | "mathit" | "text" => self.raw_argument(),
+            "" => Err(latex("`\\` at the end")),
+            _ => Err(ReplError::Unsupported(format!("LaTeX command \\{}", name))),
+        }
+    }
+
+    // A number as a whole, e.g. the argument in `\log 100`.
+    fn number(&mut self) -> String {
+        let start = self.position;
+        while self
+            .peek()
+            .is_some_and(|ch| ch.is_ascii_digit() || ch == '.')
+        {
+            self.position += 1;
+        }
+        self.chars[start..self.position].iter().collect()
+    }
+
+    // A function applied to the parenthesized group or the single argument that
+    // follows, with an optional power as in `\sin^2 x`, or a base for `\log_2 x`.
+    fn function(&mut self, function: &str) -> Result<String, ReplError> {
+        let mut power = None;
+        let mut base = None;
+
+        loop {
+            self.skip_spaces();
+            match self.peek() {
+                Some('^') if power.is_none() => {
+                    self.position += 1;
+                    power = Some(self.argument()?);
+                }
+                Some('_') if function == "log" && base.is_none() => {
+                    self.position += 1;
+                    base = Some(self.argument()?);
+                }
+                _ => break,
+            }
+        }
+
+        if self.starts_with("\\left") {
+            self.position += "\\left".len();
+            self.skip_spaces();
+        }
+
+        let mut argument = match self.peek() {
+            Some('(' | '[') => {
+                self.position += 1;
+                self.sequence(Some(')'))?
+            }
+            Some(ch) if ch.is_ascii_digit() || ch == '.' => self.number(),
+            _ => self.argument()?,
+        };
+        if function == "log" {
+            argument = format!("{}, {}", argument, base.as_deref().unwrap_or("10"));
+        }
+
+        Ok(match power {
+            Some(power) => format!("{}({})^({})", function, argument, power),
+            None => format!("{}({})", function, argument),
+        })
+    }
+}
+
+fn ends_with_letter(output: &str) -> bool {
+    output
+        .chars()
+        .last()
+        .is_some_and(|ch| ch.is_alphanumeric() || ch == '_')
+}
+
+fn latex(message: &str) -> ReplError {
+    ReplError::Syntax(format!("LaTeX: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repl::Repl;
+    use crate::repl::ReplResult;
+
+    #[test]
+    fn recognises_latex() {
+        assert!(is_latex("\\frac{1}{2}"));
+        assert!(is_latex("x^{2}"));
+        assert!(!is_latex("x^2 + 1"));
+        assert!(!is_latex("\"\\frac\""));
+    }
+
+    #[test]
+    fn translates_formulas() {
+        for (latex, expected) in [
+            ("\\frac{1}{2}", "(1.0*(1)/(2))"),
+            ("\\sqrt{x}", "sqrt(x)"),
+            ("\\sqrt[3]{x}", "((x)^(1.0/(3)))"),
+            ("x^{2} \\cdot y", "x^(2) * y"),
+            ("\\sin(x)^2 + \\cos(x)^2", "sin(x)^(2) + cos(x)^(2)"),
+            ("\\pi r^2", "(3.141592653589793) r^(2)"),
+            ("\\left| x \\right|", "| x |"),
+            ("xy", "x y"),
+        ] {
+            assert_eq!(translate(latex).unwrap(), expected, "{}", latex);
+        }
+    }
+
+    #[test]
+    fn evaluates_translated_formulas() {
+        let mut repl = Repl::new();
+        match repl.process_input("\\frac{1}{2} + \\sqrt{16}").unwrap() {
+            ReplResult::Number(value) => assert_eq!(value, 4.5),
+            result => panic!("gave {:?}", result),
+        }
+    }
+
+    #[test]
+    fn unknown_commands_are_errors() {
+        assert!(translate("\\foo{x}").is_err());
+        assert!(translate("\\frac{1}").is_err());
+    }
+}
@@ -1,17 +1,78 @@
-use evalexpr::eval_with_context_mut;
+mod algebra;
+mod budget;
+mod calculus;
+mod closed_form;
+mod distributions;
+mod exact;
+mod forms;
+mod functions;
+mod interpolation;
+mod latex;
+mod multivariable;
+mod notation;
+mod number_format;
+mod ode;
+mod optimize;
+mod prelude;
+mod random;
+mod series;
+mod signal;
+mod solve;
+mod special;
+mod symbolic;
+mod typeset;
+
+use budget::Budget;
+use evalexpr::build_operator_tree;
 use evalexpr::ContextWithMutableFunctions;
 use evalexpr::ContextWithMutableVariables;
 use evalexpr::EvalexprError;
 use evalexpr::Function;
 use evalexpr::HashMapContext;
+use evalexpr::IterateVariablesContext;
+use evalexpr::Node;
 use evalexpr::Value;
+use forms::split_arguments;
+use forms::SPECIAL_FORMS;
+use functions::UserFunction;
+use interpolation::expand_interpolant;
+use random::Random;
 use regex::Regex;
+use solve::split_equation;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::RwLock;
+use std::time::Duration;
+use symbolic::Expr;
+
+pub use budget::spawn_evaluation;
+pub use budget::Limits;
+pub use functions::Scope;
+pub use number_format::Notation;
+pub use number_format::NumberFormat;
+pub use typeset::Markup;
 
 #[derive(Debug, Clone)]
 pub struct Repl {
     context: HashMapContext,
+    globals: Arc<RwLock<HashMapContext>>,
+    functions: BTreeMap<String, UserFunction>,
+    random: Arc<Mutex<Random>>,
     regex_list_pattern: Regex,
+    regex_function_pattern: Regex,
+    regex_initial_value_pattern: Regex,
+    regex_del_pattern: Regex,
+    regex_form_pattern: Regex,
+    exact_forms: bool,
+    number_format: NumberFormat,
+    budget: Budget,
+    prelude_files: Vec<PathBuf>,
+    prelude_errors: Vec<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -33,12 +94,89 @@ pub enum ReplResult {
     String(String),
     Point(Point),
     List(Array),
+    Expression(String),
+    Bindings(Vec<(String, f64)>),
+    ExactBindings(Vec<(String, String)>),
 }
 
 #[derive(Debug)]
 pub enum ReplError {
     InvalidType,
     InvalidArgument,
+    DidNotConverge,
+    Unbounded,
+    SingularMatrix,
+    Unsupported(String),
+    Syntax(String),
+    Timeout(Duration),
+    RecursionLimit(usize),
+    MemoryLimit(usize),
+    Cancelled,
+    Prelude(String),
+}
+
+impl fmt::Display for ReplError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplError::InvalidType => write!(f, "invalid type"),
+            ReplError::InvalidArgument => write!(f, "invalid argument"),
+            ReplError::DidNotConverge => write!(f, "did not converge"),
+            ReplError::Unbounded => write!(f, "unbounded"),
+            ReplError::SingularMatrix => write!(f, "singular matrix"),
+            ReplError::Unsupported(what) => write!(f, "unsupported: {}", what),
+            ReplError::Syntax(what) => write!(f, "syntax error: {}", what),
+            ReplError::Timeout(limit) => write!(f, "took longer than {:?}", limit),
+            ReplError::RecursionLimit(depth) => {
+                write!(f, "recursion deeper than {} calls", depth)
+            }
+            ReplError::MemoryLimit(bytes) => write!(f, "used more than {} MB", bytes >> 20),
+            ReplError::Cancelled => write!(f, "cancelled"),
+            ReplError::Prelude(errors) => write!(f, "prelude: {}", errors),
+        }
+    }
+}
+
+impl Error for ReplError {}
+
+/// Something computed by the REPL that can be drawn on the graph.
+#[derive(Debug, Clone)]
+pub enum Plot {
+    /// Lists of points joined into curves, e.g. the solution of `ode(...)`.
+    Curves(Vec<Vec<Point>>),
+    /// A single point, e.g. the solution of `nsolve(...)`.
+    Point(Point),
+}
+
+/// The kind of value a name is bound to in the REPL.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BindingKind {
+    Number,
+    Boolean,
+    String,
+    Point,
+    List,
+    Function,
+}
+
+impl fmt::Display for BindingKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BindingKind::Number => write!(f, "number"),
+            BindingKind::Boolean => write!(f, "boolean"),
+            BindingKind::String => write!(f, "string"),
+            BindingKind::Point => write!(f, "point"),
+            BindingKind::List => write!(f, "list"),
+            BindingKind::Function => write!(f, "function"),
+        }
+    }
+}
+
+/// A name defined in the REPL along with a printable form of its value.
+#[derive(Debug, Clone)]
+pub struct Binding {
+    pub name: String,
+    pub kind: BindingKind,
+    pub value: String,
 }
 
 impl Default for Repl {
@@ -48,33 +186,195 @@ impl Default for Repl {
 }
 
 impl Repl {
+    /// A REPL with the built-in functions. See `with_prelude` for one with the
+    /// definitions of the prelude files too.
     pub fn new() -> Self {
+        Repl::with_budget(Budget::default())
+    }
+
+    // The built-in functions that take long hold on to the budget, so a REPL
+    // that is reset keeps its own.
+    fn with_budget(budget: Budget) -> Self {
         let mut object = Repl {
             context: HashMapContext::new(),
+            globals: Arc::new(RwLock::new(HashMapContext::new())),
+            functions: BTreeMap::new(),
+            random: Arc::new(Mutex::new(Random::default())),
             regex_list_pattern: Regex::new(r"^(\w+)\s*=\s*List\s*\((.*)\)$").unwrap(),
+            regex_function_pattern: Regex::new(
+                r"^(\w+)\s*\(\s*(\w+(?:\s*,\s*\w+)*)?\s*\)\s*=\s*([^=].*)$",
+            )
+            .unwrap(),
+            regex_initial_value_pattern: Regex::new(r"^(\w+)\s*\(\s*(-?\d+)\s*\)\s*=\s*([^=].*)$")
+                .unwrap(),
+            regex_del_pattern: Regex::new(r"^del\s*\(\s*(\w+)\s*\)$").unwrap(),
+            regex_form_pattern: Regex::new(&format!(r"\b({})\s*\(", SPECIAL_FORMS.join("|")))
+                .unwrap(),
+            exact_forms: false,
+            number_format: NumberFormat::default(),
+            budget,
+            prelude_files: Vec::new(),
+            prelude_errors: Vec::new(),
         };
         object.setup_math_functions();
         object.setup_point_function();
+        object.setup_distribution_functions();
+        object.setup_random_functions();
+        object.setup_interpolation_functions();
+        object.setup_signal_functions();
         object
     }
 
     pub fn process_input(&mut self, input: &str) -> Result<ReplResult, Box<dyn Error>> {
-        if let Some(captures) = self.regex_list_pattern.captures(input.trim_end()) {
+        self.within_limits(|repl| repl.evaluate_input(input))
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.budget.limits()
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.budget.set_limits(limits);
+    }
+
+    /// Stop the evaluation running on another thread on a clone of this REPL,
+    /// and any evaluations after it until `resume()`.
+    pub fn cancel(&self) {
+        self.budget.cancel();
+    }
+
+    pub fn resume(&self) {
+        self.budget.resume();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.budget.is_cancelled()
+    }
+
+    // Run an evaluation within the limits, so that one that runs away stops
+    // with an error instead of freezing the caller.
+    fn within_limits<T>(
+        &mut self,
+        evaluate: impl FnOnce(&mut Self) -> Result<T, Box<dyn Error>>,
+    ) -> Result<T, Box<dyn Error>> {
+        let budget = self.budget.clone();
+        let _evaluation = budget.start();
+        budget.finish(evaluate(self))
+    }
+
+    fn evaluate_input(&mut self, input: &str) -> Result<ReplResult, Box<dyn Error>> {
+        let normalized = self.normalize(input)?;
+        let input = normalized.trim();
+
+        if input == "vars()" {
+            return Ok(ReplResult::String(self.describe_bindings()));
+        }
+
+        if let Some(arguments) = input
+            .strip_prefix("format(")
+            .and_then(|arguments| arguments.strip_suffix(')'))
+        {
+            if !arguments.trim().is_empty() {
+                self.number_format = NumberFormat::parse(arguments)?;
+            }
+            return Ok(ReplResult::String(self.number_format.to_string()));
+        }
+
+        if let Some(arguments) = input
+            .strip_prefix("limits(")
+            .and_then(|arguments| arguments.strip_suffix(')'))
+        {
+            if !arguments.trim().is_empty() {
+                let arguments = split_arguments(arguments)
+                    .iter()
+                    .map(|argument| self.evaluate_number(argument, &[]))
+                    .collect::<Result<Vec<f64>, Box<dyn Error>>>()?;
+                self.budget.set_limits(Limits::parse(&arguments)?);
+            }
+            return Ok(ReplResult::String(self.budget.limits().to_string()));
+        }
+
+        if input == "reload_prelude()" {
+            return Ok(ReplResult::String(self.reload_prelude()?));
+        }
+
+        if input == "reset()" {
+            self.reset();
+            return Ok(ReplResult::Empty);
+        }
+
+        if let Some(captures) = self.regex_del_pattern.captures(input) {
+            let name = captures.get(1).unwrap().as_str();
+            return self.delete_binding(name);
+        }
+
+        if let Some(captures) = self.regex_list_pattern.captures(input) {
             let name = captures.get(1).unwrap().as_str().trim();
             let values = captures.get(2).unwrap().as_str().trim();
             return self.create_list(name, values);
         }
 
-        let evaluation_result = eval_with_context_mut(input, &mut self.context)?;
-        match evaluation_result {
-            evalexpr::Value::Boolean(value) => Ok(ReplResult::Boolean(value)),
-            evalexpr::Value::Int(value) => Ok(ReplResult::Number(value as f64)),
-            evalexpr::Value::Float(value) => Ok(ReplResult::Number(value)),
-            evalexpr::Value::String(value) => Ok(ReplResult::String(value)),
-            evalexpr::Value::Tuple(value) => {
+        if let Some(captures) = self.regex_initial_value_pattern.captures(input) {
+            let name = captures.get(1).unwrap().as_str();
+            let index = captures.get(2).unwrap().as_str().parse()?;
+            let value = captures.get(3).unwrap().as_str().trim();
+            return self.define_initial_value(name, index, value);
+        }
+
+        if let Some(captures) = self.regex_function_pattern.captures(input) {
+            let name = captures.get(1).unwrap().as_str();
+            let params = captures
+                .get(2)
+                .map(|m| {
+                    m.as_str()
+                        .split(',')
+                        .map(|p| p.trim().to_string())
+                        .collect()
+                })
+                .unwrap_or_default();
+            let body = captures.get(3).unwrap().as_str().trim();
+            return self.define_function(name, params, body);
+        }
+
+        if let Some(result) = self.define_interpolant(input) {
+            return result;
+        }
+
+        self.sync_globals();
+
+        if let Some(solution) = self.solver_form(input)? {
+            return Ok(ReplResult::Bindings(solution.bindings()));
+        }
+
+        if let Some(solution) = self.exact_form(input)? {
+            return Ok(ReplResult::ExactBindings(solution.bindings));
+        }
+
+        if let Some(derivatives) = self.derivative_form(input)? {
+            return Ok(ReplResult::Expression(derivatives));
+        }
+
+        if let Some(expression) = self.expression_form(input)? {
+            return Ok(ReplResult::Expression(expression.to_string()));
+        }
+
+        let evaluation_result = self.evaluate(input, &[])?;
+        self.value_to_result(evaluation_result)
+    }
+
+    fn value_to_result(&self, value: Value) -> Result<ReplResult, Box<dyn Error>> {
+        match value {
+            Value::Boolean(value) => Ok(ReplResult::Boolean(value)),
+            Value::Int(value) => Ok(ReplResult::Number(value as f64)),
+            Value::Float(value) => Ok(ReplResult::Number(value)),
+            Value::String(value) => match parse_list(&value) {
+                Some(list) => Ok(ReplResult::List(list)),
+                None => Ok(ReplResult::String(value)),
+            },
+            Value::Tuple(value) if value.len() == 2 && value.iter().all(Value::is_number) => {
                 let x = match &value[0] {
-                    evalexpr::Value::Float(val) => *val,
-                    evalexpr::Value::Int(val) => *val as f64,
+                    Value::Float(val) => *val,
+                    Value::Int(val) => *val as f64,
                     _ => {
                         return Err(Box::new(EvalexprError::ExpectedNumber {
                             actual: Value::Empty,
@@ -83,8 +383,8 @@ impl Repl {
                 };
 
                 let y = match &value[1] {
-                    evalexpr::Value::Float(val) => *val,
-                    evalexpr::Value::Int(val) => *val as f64,
+                    Value::Float(val) => *val,
+                    Value::Int(val) => *val as f64,
                     _ => {
                         return Err(Box::new(EvalexprError::ExpectedNumber {
                             actual: Value::Empty,
@@ -96,10 +396,384 @@ impl Repl {
 
                 Ok(ReplResult::Point(point))
             }
+            Value::Tuple(values) => Ok(ReplResult::List(Array {
+                items: values
+                    .into_iter()
+                    .map(|value| self.value_to_result(value))
+                    .collect::<Result<Vec<ReplResult>, Box<dyn Error>>>()?,
+            })),
             _ => Ok(ReplResult::Empty),
         }
     }
 
+    /// Parse an equation for the graph, expanding forms such as `taylor(...)`
+    /// and interpolants such as `cubic_spline(points)`.
+    pub fn plot_expression(&mut self, input: &str) -> Result<Node, Box<dyn Error>> {
+        let input = self.normalize(input)?;
+        self.sync_globals();
+        let input = expand_interpolant(&input).unwrap_or(input);
+        let expanded = self.expand_expression_forms(&input)?;
+
+        Ok(build_operator_tree(&expanded)?)
+    }
+
+    /// Evaluate an input that produces something to mark on the graph: a list of
+    /// points such as the solution of `ode(...)`, a list of such lists, or a point
+    /// such as the solution of `nsolve(...)`. Equations are never evaluated here,
+    /// as that would assign to their left hand side.
+    pub fn plot_points(&mut self, input: &str) -> Result<Plot, Box<dyn Error>> {
+        self.within_limits(|repl| repl.evaluate_plot_points(input))
+    }
+
+    fn evaluate_plot_points(&mut self, input: &str) -> Result<Plot, Box<dyn Error>> {
+        let input = &self.normalize(input)?;
+        if split_equation(input).is_some() {
+            return Err(Box::new(ReplError::InvalidType));
+        }
+
+        self.sync_globals();
+        let value = self.evaluate(input, &[])?;
+
+        let list = match self.value_to_result(value)? {
+            ReplResult::Point(point) => return Ok(Plot::Point(point)),
+            ReplResult::List(list) => list,
+            _ => return Err(Box::new(ReplError::InvalidType)),
+        };
+
+        let points = |list: &Array| {
+            list.items
+                .iter()
+                .map(|item| match item {
+                    ReplResult::Point(point) => Ok(*point),
+                    _ => Err(Box::new(ReplError::InvalidType) as Box<dyn Error>),
+                })
+                .collect::<Result<Vec<Point>, Box<dyn Error>>>()
+        };
+
+        let curves = match list.items.first() {
+            Some(ReplResult::List(_)) => list
+                .items
+                .iter()
+                .map(|item| match item {
+                    ReplResult::List(list) => points(list),
+                    _ => Err(Box::new(ReplError::InvalidType) as Box<dyn Error>),
+                })
+                .collect::<Result<Vec<Vec<Point>>, Box<dyn Error>>>()?,
+            _ => vec![points(&list)?],
+        };
+
+        Ok(Plot::Curves(curves))
+    }
+
+    /// The variables and functions defined so far, for evaluating plots. The
+    /// graph draws while an evaluation may be running on a clone of this REPL,
+    /// so the user functions get a budget, globals and memos of their own,
+    /// which drawing can neither exhaust nor see midway.
+    pub fn graph_context(&self) -> HashMapContext {
+        let mut repl = Repl::with_budget(Budget::default());
+        for (name, value) in self.context.iter_variables() {
+            repl.context.set_value(name, value).unwrap();
+        }
+        for (name, function) in &self.functions {
+            repl.functions.insert(
+                name.clone(),
+                UserFunction {
+                    memo: Arc::default(),
+                    ..function.clone()
+                },
+            );
+            // The functions were built from these definitions before.
+            repl.register_function(name).unwrap();
+        }
+        repl.sync_globals();
+
+        repl.context
+    }
+
+    /// Every variable, list, point and user function currently defined, sorted by name.
+    pub fn bindings(&self) -> Vec<Binding> {
+        let mut bindings: Vec<Binding> = self
+            .context
+            .iter_variables()
+            .map(|(name, value)| {
+                let (kind, value) = match value {
+                    Value::Boolean(value) => (BindingKind::Boolean, value.to_string()),
+                    Value::Int(value) => (BindingKind::Number, value.to_string()),
+                    Value::Float(value) => (BindingKind::Number, value.to_string()),
+                    Value::String(value) if value.starts_with("List(") => {
+                        (BindingKind::List, value)
+                    }
+                    Value::String(value) => (BindingKind::String, format!("{:?}", value)),
+                    Value::Tuple(values) => (
+                        BindingKind::Point,
+                        format!(
+                            "({})",
+                            values
+                                .iter()
+                                .map(|value| value.to_string())
+                                .collect::<Vec<String>>()
+                                .join(", ")
+                        ),
+                    ),
+                    Value::Empty => (BindingKind::String, "()".to_string()),
+                };
+                Binding { name, kind, value }
+            })
+            .collect();
+
+        bindings.extend(self.functions.iter().map(|(name, function)| Binding {
+            name: name.clone(),
+            kind: BindingKind::Function,
+            value: function.describe(name),
+        }));
+
+        bindings.sort_by(|a, b| a.name.cmp(&b.name));
+        bindings
+    }
+
+    /// Forget every variable, list and user function. The prelude runs again
+    /// in a REPL made by `with_prelude`. Display settings stay.
+    pub fn reset(&mut self) {
+        let exact_forms = self.exact_forms;
+        let number_format = self.number_format;
+        let prelude_files = std::mem::take(&mut self.prelude_files);
+        // The budget is shared with whoever may cancel the evaluation, and it
+        // keeps the limits.
+        *self = Repl::with_budget(self.budget.clone());
+        self.exact_forms = exact_forms;
+        self.number_format = number_format;
+        if !prelude_files.is_empty() {
+            self.prelude_files = prelude_files;
+            self.load_prelude();
+        }
+    }
+
+    fn describe_bindings(&self) -> String {
+        self.bindings()
+            .iter()
+            .map(|binding| format!("{}: {} = {}", binding.name, binding.kind, binding.value))
+            .collect::<Vec<String>>()
+            .join("; ")
+    }
+
+    fn delete_binding(&mut self, name: &str) -> Result<ReplResult, Box<dyn Error>> {
+        if self.functions.remove(name).is_some() {
+            self.rebuild_context(None);
+            return Ok(ReplResult::Empty);
+        }
+
+        if evalexpr::Context::get_value(&self.context, name).is_some() {
+            self.rebuild_context(Some(name));
+            return Ok(ReplResult::Empty);
+        }
+
+        Err(Box::new(ReplError::InvalidArgument))
+    }
+
+    // The evalexpr context has no way to remove a single entry, so start from a
+    // fresh one and carry over everything that should survive.
+    fn rebuild_context(&mut self, removed_variable: Option<&str>) {
+        let variables: Vec<(String, Value)> = self
+            .context
+            .iter_variables()
+            .filter(|(name, _)| Some(name.as_str()) != removed_variable)
+            .collect();
+
+        self.context = HashMapContext::new();
+        self.setup_math_functions();
+        self.setup_point_function();
+        self.setup_distribution_functions();
+        self.setup_random_functions();
+        self.setup_interpolation_functions();
+        self.setup_signal_functions();
+
+        for (name, value) in variables {
+            self.context.set_value(name, value).unwrap();
+        }
+
+        for name in self.functions.keys().cloned().collect::<Vec<String>>() {
+            self.register_function(&name).unwrap();
+        }
+    }
+
+    /// Evaluate a user function or sequence at the integers `start..=end`, e.g.
+    /// for `draw_seq(a, 0, 30)`.
+    pub fn sequence_points(&mut self, arguments: &str) -> Result<Vec<Point>, Box<dyn Error>> {
+        let arguments = split_arguments(arguments);
+        if arguments.len() != 3 {
+            return Err(Box::new(EvalexprError::wrong_function_argument_amount(
+                arguments.len(),
+                3,
+            )));
+        }
+
+        self.sync_globals();
+
+        self.within_limits(|repl| {
+            let name = &arguments[0];
+            let start = repl.evaluate(&arguments[1], &[])?.as_int()?;
+            let end = repl.evaluate(&arguments[2], &[])?.as_int()?;
+
+            (start..=end)
+                .map(|n| {
+                    let value =
+                        evalexpr::Context::call_function(&repl.context, name, &Value::Int(n))?;
+                    repl.budget.allocate(std::mem::size_of::<Point>())?;
+                    Ok(Point {
+                        x: n as f64,
+                        y: value.as_number()?,
+                    })
+                })
+                .collect()
+        })
+    }
+
+    // A definition may list initial values after the body, as in
+    // `a(n) = a(n-1) + a(n-2), a(0) = 0, a(1) = 1`.
+    fn define_function(
+        &mut self,
+        name: &str,
+        params: Vec<String>,
+        body: &str,
+    ) -> Result<ReplResult, Box<dyn Error>> {
+        let parts = split_arguments(body);
+        let mut initial_values = Vec::new();
+
+        for part in parts.iter().skip(1) {
+            match self.regex_initial_value_pattern.captures(part) {
+                Some(captures) if captures.get(1).unwrap().as_str() == name => {
+                    initial_values.push((
+                        captures.get(2).unwrap().as_str().parse::<i64>()?,
+                        captures.get(3).unwrap().as_str().trim().to_string(),
+                    ));
+                }
+                _ => {
+                    initial_values.clear();
+                    break;
+                }
+            }
+        }
+
+        let body = if initial_values.is_empty() {
+            body
+        } else {
+            parts[0].as_str()
+        };
+
+        self.sync_globals();
+        let body = &self.expand_expression_forms(body)?;
+        build_operator_tree(body)?;
+
+        let mut function = self.functions.get(name).cloned().unwrap_or_default();
+        function.params = params;
+        function.body = body.to_string();
+        function.memo = Default::default();
+        function.interpolation = None;
+        self.functions.insert(name.to_string(), function);
+
+        for (index, value) in initial_values {
+            self.define_initial_value(name, index, &value)?;
+        }
+
+        self.register_function(name)?;
+
+        Ok(ReplResult::Empty)
+    }
+
+    fn define_initial_value(
+        &mut self,
+        name: &str,
+        index: i64,
+        value: &str,
+    ) -> Result<ReplResult, Box<dyn Error>> {
+        self.sync_globals();
+        let value = self.evaluate_number(value, &[])?;
+
+        let function = self
+            .functions
+            .entry(name.to_string())
+            .or_insert_with(|| UserFunction {
+                params: vec!["n".to_string()],
+                ..Default::default()
+            });
+        function.initial_values.insert(index, value);
+        function.memo = Default::default();
+
+        self.register_function(name)?;
+
+        Ok(ReplResult::Empty)
+    }
+
+    /// Replace calls to user functions by their bodies, so that they can be
+    /// rewritten symbolically.
+    fn inline_functions(&self, expression: &Expr) -> Result<Expr, Box<dyn Error>> {
+        self.inline_functions_to_depth(expression, 32)
+    }
+
+    fn inline_functions_to_depth(
+        &self,
+        expression: &Expr,
+        depth: usize,
+    ) -> Result<Expr, Box<dyn Error>> {
+        let inline = |e: &Expr| self.inline_functions_to_depth(e, depth);
+
+        Ok(match expression {
+            Expr::Call(name, arguments) => {
+                let arguments = arguments
+                    .iter()
+                    .map(inline)
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                match self.functions.get(name) {
+                    Some(function)
+                        if function.initial_values.is_empty()
+                            && function.params.len() == arguments.len() =>
+                    {
+                        if depth == 0 {
+                            return Err(Box::new(ReplError::Unsupported(format!(
+                                "recursive function {}",
+                                name
+                            ))));
+                        }
+
+                        let values: HashMap<String, Expr> =
+                            function.params.iter().cloned().zip(arguments).collect();
+                        let body = Expr::parse(&function.body)?.substitute(&values);
+                        self.inline_functions_to_depth(&body, depth - 1)?
+                    }
+                    _ => Expr::Call(name.clone(), arguments),
+                }
+            }
+            Expr::Number(_) | Expr::Variable(_) => expression.clone(),
+            Expr::Neg(u) => -inline(u)?,
+            Expr::Add(u, v) => inline(u)? + inline(v)?,
+            Expr::Sub(u, v) => inline(u)? - inline(v)?,
+            Expr::Mul(u, v) => inline(u)? * inline(v)?,
+            Expr::Div(u, v) => inline(u)? / inline(v)?,
+            Expr::Pow(u, v) => inline(u)?.pow(inline(v)?),
+        })
+    }
+
+    fn register_function(&mut self, name: &str) -> Result<(), Box<dyn Error>> {
+        let function =
+            self.functions[name].to_function(name, self.globals.clone(), self.budget.clone())?;
+        self.context.set_function(name.to_string(), function)?;
+
+        Ok(())
+    }
+
+    // User functions evaluate their bodies against a snapshot of the context, so
+    // refresh it before every evaluation.
+    // Sequences memoise their terms, which may depend on other variables, so the
+    // memos are only kept for the duration of one evaluation.
+    fn sync_globals(&mut self) {
+        *self.globals.write().unwrap() = self.context.clone();
+
+        for function in self.functions.values() {
+            function.memo.lock().unwrap().clear();
+        }
+    }
+
     fn setup_math_functions(&mut self) {
         self.context
             .set_function(
@@ -509,6 +1183,57 @@ impl Repl {
             )
             .unwrap();
 
+        self.context
+            .set_function(
+                "ln".to_string(),
+                Function::new(|args| {
+                    if let Value::Float(x) = &args {
+                        Ok(Value::Float(x.ln()))
+                    } else if let Value::Int(x) = &args {
+                        Ok(Value::Float((*x as f64).ln()))
+                    } else {
+                        Err(EvalexprError::ExpectedNumber {
+                            actual: Value::Empty,
+                        })
+                    }
+                }),
+            )
+            .unwrap();
+
+        self.context
+            .set_function(
+                "sqrt".to_string(),
+                Function::new(|args| {
+                    if let Value::Float(x) = &args {
+                        Ok(Value::Float(x.sqrt()))
+                    } else if let Value::Int(x) = &args {
+                        Ok(Value::Float((*x as f64).sqrt()))
+                    } else {
+                        Err(EvalexprError::ExpectedNumber {
+                            actual: Value::Empty,
+                        })
+                    }
+                }),
+            )
+            .unwrap();
+
+        self.context
+            .set_function(
+                "abs".to_string(),
+                Function::new(|args| {
+                    if let Value::Float(x) = &args {
+                        Ok(Value::Float(x.abs()))
+                    } else if let Value::Int(x) = &args {
+                        Ok(Value::Float((*x as f64).abs()))
+                    } else {
+                        Err(EvalexprError::ExpectedNumber {
+                            actual: Value::Empty,
+                        })
+                    }
+                }),
+            )
+            .unwrap();
+
         self.context
             .set_function(
                 "log".to_string(),
@@ -563,6 +1288,8 @@ impl Repl {
             .set_function(
                 "exp".to_string(),
                 Function::new(|args| match args {
+                    Value::Float(x) => Ok(Value::Float(x.exp())),
+                    Value::Int(x) => Ok(Value::Float((*x as f64).exp())),
                     Value::Tuple(values) if values.len() == 1 => {
                         if let Value::Float(x) = &values[0] {
                             Ok(Value::Float(x.exp()))
@@ -650,7 +1377,7 @@ impl Repl {
                 "binary_to_decimal".to_string(),
                 Function::new(|args| {
                     if let Value::String(x) = args {
-                        let decimal = i64::from_str_radix(&x, 2).map_err(|_| {
+                        let decimal = i64::from_str_radix(x, 2).map_err(|_| {
                             EvalexprError::ExpectedNumber {
                                 actual: Value::Empty,
                             }
@@ -690,7 +1417,7 @@ impl Repl {
                 "hex_to_decimal".to_string(),
                 Function::new(|args| {
                     if let Value::String(x) = args {
-                        let decimal = i64::from_str_radix(&x, 16).map_err(|_| {
+                        let decimal = i64::from_str_radix(x, 16).map_err(|_| {
                             EvalexprError::ExpectedNumber {
                                 actual: Value::Empty,
                             }
@@ -704,6 +1431,180 @@ impl Repl {
                 }),
             )
             .unwrap();
+
+        self.context
+            .set_function(
+                "gamma".to_string(),
+                Function::new(|args| {
+                    if let Value::Float(x) = &args {
+                        Ok(Value::Float(special::gamma(*x)))
+                    } else if let Value::Int(x) = &args {
+                        Ok(Value::Float(special::gamma(*x as f64)))
+                    } else {
+                        Err(EvalexprError::ExpectedNumber {
+                            actual: Value::Empty,
+                        })
+                    }
+                }),
+            )
+            .unwrap();
+
+        self.context
+            .set_function(
+                "factorial".to_string(),
+                Function::new(|args| match args {
+                    Value::Int(n) if *n < 0 => Err(EvalexprError::CustomMessage(
+                        "factorial of a negative integer".to_string(),
+                    )),
+                    // Past 170! the product is too large for a float anyway.
+                    Value::Int(n) if *n > 170 => Ok(Value::Float(f64::INFINITY)),
+                    Value::Int(n) => Ok(Value::Float((1..=*n).map(|k| k as f64).product())),
+                    Value::Float(x) => Ok(Value::Float(special::gamma(x + 1.0))),
+                    _ => Err(EvalexprError::ExpectedNumber {
+                        actual: Value::Empty,
+                    }),
+                }),
+            )
+            .unwrap();
+
+        self.context
+            .set_function(
+                "lgamma".to_string(),
+                Function::new(|args| {
+                    if let Value::Float(x) = &args {
+                        Ok(Value::Float(special::lgamma(*x)))
+                    } else if let Value::Int(x) = &args {
+                        Ok(Value::Float(special::lgamma(*x as f64)))
+                    } else {
+                        Err(EvalexprError::ExpectedNumber {
+                            actual: Value::Empty,
+                        })
+                    }
+                }),
+            )
+            .unwrap();
+
+        self.context
+            .set_function(
+                "erf".to_string(),
+                Function::new(|args| {
+                    if let Value::Float(x) = &args {
+                        Ok(Value::Float(special::erf(*x)))
+                    } else if let Value::Int(x) = &args {
+                        Ok(Value::Float(special::erf(*x as f64)))
+                    } else {
+                        Err(EvalexprError::ExpectedNumber {
+                            actual: Value::Empty,
+                        })
+                    }
+                }),
+            )
+            .unwrap();
+
+        self.context
+            .set_function(
+                "erfc".to_string(),
+                Function::new(|args| {
+                    if let Value::Float(x) = &args {
+                        Ok(Value::Float(special::erfc(*x)))
+                    } else if let Value::Int(x) = &args {
+                        Ok(Value::Float(special::erfc(*x as f64)))
+                    } else {
+                        Err(EvalexprError::ExpectedNumber {
+                            actual: Value::Empty,
+                        })
+                    }
+                }),
+            )
+            .unwrap();
+
+        self.context
+            .set_function(
+                "digamma".to_string(),
+                Function::new(|args| {
+                    if let Value::Float(x) = &args {
+                        Ok(Value::Float(special::digamma(*x)))
+                    } else if let Value::Int(x) = &args {
+                        Ok(Value::Float(special::digamma(*x as f64)))
+                    } else {
+                        Err(EvalexprError::ExpectedNumber {
+                            actual: Value::Empty,
+                        })
+                    }
+                }),
+            )
+            .unwrap();
+
+        self.context
+            .set_function(
+                "zeta".to_string(),
+                Function::new(|args| {
+                    if let Value::Float(x) = &args {
+                        Ok(Value::Float(special::zeta(*x)))
+                    } else if let Value::Int(x) = &args {
+                        Ok(Value::Float(special::zeta(*x as f64)))
+                    } else {
+                        Err(EvalexprError::ExpectedNumber {
+                            actual: Value::Empty,
+                        })
+                    }
+                }),
+            )
+            .unwrap();
+
+        self.context
+            .set_function(
+                "beta".to_string(),
+                Function::new(|args| {
+                    let tuple = args.as_fixed_len_tuple(2)?;
+                    let a = tuple[0].as_number()?;
+                    let b = tuple[1].as_number()?;
+
+                    Ok(Value::Float(special::beta(a, b)))
+                }),
+            )
+            .unwrap();
+
+        self.context
+            .set_function(
+                "besselj".to_string(),
+                Function::new(|args| {
+                    let tuple = args.as_fixed_len_tuple(2)?;
+                    let n = tuple[0].as_int()?;
+                    let x = tuple[1].as_number()?;
+
+                    Ok(Value::Float(special::bessel_j(n, x)))
+                }),
+            )
+            .unwrap();
+
+        self.context
+            .set_function(
+                "bessely".to_string(),
+                Function::new(|args| {
+                    let tuple = args.as_fixed_len_tuple(2)?;
+                    let n = tuple[0].as_int()?;
+                    let x = tuple[1].as_number()?;
+
+                    Ok(Value::Float(special::bessel_y(n, x)))
+                }),
+            )
+            .unwrap();
+
+        self.context
+            .set_function(
+                "lambertw".to_string(),
+                Function::new(|args| match args {
+                    Value::Tuple(tuple) if tuple.len() == 2 => {
+                        let x = tuple[0].as_number()?;
+                        let branch = tuple[1].as_int()?;
+
+                        Ok(Value::Float(special::lambert_w(x, branch)))
+                    }
+                    args => Ok(Value::Float(special::lambert_w(args.as_number()?, 0))),
+                }),
+            )
+            .unwrap();
     }
 
     fn setup_point_function(&mut self) {
@@ -747,80 +1648,255 @@ impl Repl {
             .unwrap();
     }
 
+    // Items are read as literals where possible and evaluated otherwise, so that
+    // `List(Point(1, f(1)), Point(2, f(2)))` works.
     fn create_list(&mut self, name: &str, values: &str) -> Result<ReplResult, Box<dyn Error>> {
-        let mut parsed_values = Vec::new();
-        let mut temp_str = String::new();
-        let mut parentheses_depth = 0;
-        println!("{:?}", values);
-
-        for ch in values.chars() {
-            if ch == '(' {
-                parentheses_depth += 1;
-            } else if ch == ')' {
-                parentheses_depth -= 1;
-                if parentheses_depth < 0 {
-                    parentheses_depth = 0;
+        self.sync_globals();
+
+        let mut items = Vec::new();
+        for item in split_arguments(values) {
+            match parse_value(&item) {
+                Some(value) => items.push(value),
+                None if item.is_empty() => {}
+                None => {
+                    let value = self.evaluate(&item, &[])?;
+                    items.push(self.value_to_result(value)?);
                 }
             }
+        }
+        let list = Array { items };
 
-            if parentheses_depth == 0 && ch == ',' {
-                if let Some(value) = self.parse_value(&temp_str.trim()) {
-                    parsed_values.push(value);
-                }
-                temp_str.clear();
-            } else {
-                temp_str.push(ch);
+        self.context
+            .set_value(name.to_string(), Value::String(serialize_list(&list)))?;
+
+        Ok(ReplResult::List(list))
+    }
+}
+
+/// Read back a list stored in the context by `create_list`.
+fn parse_list(value: &str) -> Option<Array> {
+    let values = value.strip_prefix("List(")?.strip_suffix(')')?;
+
+    Some(Array {
+        items: parse_list_items(values),
+    })
+}
+
+fn parse_list_items(values: &str) -> Vec<ReplResult> {
+    let mut parsed_values = Vec::new();
+    let mut temp_str = String::new();
+    let mut parentheses_depth = 0;
+
+    for ch in values.chars() {
+        if ch == '(' {
+            parentheses_depth += 1;
+        } else if ch == ')' {
+            parentheses_depth -= 1;
+            if parentheses_depth < 0 {
+                parentheses_depth = 0;
             }
         }
 
-        // Handle any remaining value in temp_str
-        if !temp_str.is_empty() {
-            if let Some(value) = self.parse_value(&temp_str.trim()) {
+        if parentheses_depth == 0 && ch == ',' {
+            if let Some(value) = parse_value(temp_str.trim()) {
                 parsed_values.push(value);
             }
+            temp_str.clear();
+        } else {
+            temp_str.push(ch);
         }
+    }
 
-        let list = Array {
-            items: parsed_values,
-        };
+    // Handle any remaining value in temp_str
+    if !temp_str.is_empty() {
+        if let Some(value) = parse_value(temp_str.trim()) {
+            parsed_values.push(value);
+        }
+    }
 
-        let serialized_list = format!("List({:?})", list.items);
+    parsed_values
+}
 
-        self.context
-            .set_value(name.to_string(), Value::String(serialized_list))
-            .unwrap();
+fn parse_value(value: &str) -> Option<ReplResult> {
+    if let Some(list) = parse_list(value) {
+        return Some(ReplResult::List(list));
+    }
 
-        Ok(ReplResult::List(list))
+    if let Some(point) = parse_point(value) {
+        return Some(ReplResult::Point(point));
+    }
+
+    if let Ok(num) = value.parse::<f64>() {
+        return Some(ReplResult::Number(num));
+    } else if let Ok(boolean) = value.parse::<bool>() {
+        return Some(ReplResult::Boolean(boolean));
+    }
+
+    None
+}
+
+fn parse_point(value: &str) -> Option<Point> {
+    let trimmed = value.trim();
+    if trimmed.starts_with("Point((") && trimmed.ends_with("))") {
+        let inside = &trimmed[7..trimmed.len() - 2];
+        let coords: Vec<&str> = inside.split(',').map(|s| s.trim()).collect();
+
+        if coords.len() == 2 {
+            if let (Ok(x), Ok(y)) = (coords[0].parse::<f64>(), coords[1].parse::<f64>()) {
+                return Some(Point { x, y });
+            }
+        }
     }
+    None
+}
 
-    fn parse_value(&self, value: &str) -> Option<ReplResult> {
-        if let Some(point) = self.parse_point(value) {
-            return Some(ReplResult::Point(point));
+/// Lists are kept in the context as strings, written so that `parse_list` can
+/// read them back.
+fn serialize_list(list: &Array) -> String {
+    let items: Vec<String> = list
+        .items
+        .iter()
+        .map(|item| match item {
+            ReplResult::Number(value) => format!("{:?}", value),
+            ReplResult::Boolean(value) => value.to_string(),
+            ReplResult::Point(point) => format!("Point(({:?}, {:?}))", point.x, point.y),
+            ReplResult::List(list) => serialize_list(list),
+            other => format!("{:?}", other),
+        })
+        .collect();
+
+    format!("List({})", items.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evalexpr::eval_with_context;
+
+    #[test]
+    fn graph_context_has_a_budget_of_its_own() {
+        let mut repl = Repl::new();
+        repl.process_input("f(x) = x^2").unwrap();
+        let context = repl.graph_context();
+
+        // Drawing while a cancelled evaluation is still running.
+        let _evaluation = repl.budget.start();
+        repl.cancel();
+        assert_eq!(eval_with_context("f(3)", &context), Ok(Value::Float(9.0)));
+        assert!(repl.budget.finish(Ok(())).is_ok());
+    }
+
+    #[test]
+    fn recurrences_with_initial_values() {
+        let mut repl = Repl::new();
+        repl.process_input("a(n) = a(n-1) + a(n-2), a(0) = 0, a(1) = 1")
+            .unwrap();
+        let points = repl.sequence_points("a, 0, 10").unwrap();
+        let terms: Vec<f64> = points.iter().map(|point| point.y).collect();
+        assert_eq!(
+            terms,
+            [0.0, 1.0, 1.0, 2.0, 3.0, 5.0, 8.0, 13.0, 21.0, 34.0, 55.0]
+        );
+        // Terms are memoised, so far terms do not recurse deeply.
+        match repl.process_input("a(50)").unwrap() {
+            ReplResult::Number(value) => assert_eq!(value, 12586269025.0),
+            result => panic!("a(50) gave {:?}", result),
         }
+    }
+
+    #[test]
+    fn initial_values_defined_on_their_own() {
+        let mut repl = Repl::new();
+        repl.process_input("b(0) = 2").unwrap();
+        repl.process_input("b(n) = 3*b(n-1)").unwrap();
+        let points = repl.sequence_points("b, 0, 3").unwrap();
+        let terms: Vec<f64> = points.iter().map(|point| point.y).collect();
+        assert_eq!(terms, [2.0, 6.0, 18.0, 54.0]);
+    }
 
-        println!("value is {:?}", value);
+    fn names(repl: &Repl) -> Vec<(String, BindingKind)> {
+        repl.bindings()
+            .into_iter()
+            .map(|binding| (binding.name, binding.kind))
+            .collect()
+    }
 
-        if let Ok(num) = value.parse::<f64>() {
-            return Some(ReplResult::Number(num));
-        } else if let Ok(boolean) = value.parse::<bool>() {
-            return Some(ReplResult::Boolean(boolean));
+    fn define_one_of_each(repl: &mut Repl) {
+        for input in [
+            "x = 2",
+            "b = true",
+            "l = List(1, 2)",
+            "p = Point(1, 2)",
+            "f(t) = t^2",
+        ] {
+            repl.process_input(input).unwrap();
         }
+    }
 
-        None
+    #[test]
+    fn bindings_are_listed_by_name() {
+        let mut repl = Repl::new();
+        define_one_of_each(&mut repl);
+
+        assert_eq!(
+            names(&repl),
+            [
+                ("b".to_string(), BindingKind::Boolean),
+                ("f".to_string(), BindingKind::Function),
+                ("l".to_string(), BindingKind::List),
+                ("p".to_string(), BindingKind::Point),
+                ("x".to_string(), BindingKind::Number),
+            ]
+        );
+        match repl.process_input("vars()").unwrap() {
+            ReplResult::String(description) => assert_eq!(
+                description,
+                "b: boolean = true; f: function = f(t) = t^2; l: list = List(1.0, 2.0); \
+                 p: point = (1, 2); x: number = 2"
+            ),
+            result => panic!("vars() gave {:?}", result),
+        }
     }
 
-    fn parse_point(&self, value: &str) -> Option<Point> {
-        let trimmed = value.trim();
-        if trimmed.starts_with("Point((") && trimmed.ends_with("))") {
-            let inside = &trimmed[7..trimmed.len() - 2];
-            let coords: Vec<&str> = inside.split(',').map(|s| s.trim()).collect();
+    #[test]
+    fn deleting_a_binding_keeps_the_others() {
+        let mut repl = Repl::new();
+        define_one_of_each(&mut repl);
+        repl.process_input("g(t) = f(t) + x").unwrap();
+
+        repl.process_input("del(x)").unwrap();
+        repl.process_input("del(f)").unwrap();
+        let remaining: Vec<String> = names(&repl).into_iter().map(|(name, _)| name).collect();
+        assert_eq!(remaining, ["b", "g", "l", "p"]);
+
+        assert!(repl.process_input("x").is_err());
+        assert!(repl.process_input("g(1)").is_err());
+        repl.process_input("x = 1").unwrap();
+        repl.process_input("f(t) = 3*t").unwrap();
+        match repl.process_input("g(2)").unwrap() {
+            ReplResult::Number(value) => assert_eq!(value, 7.0),
+            result => panic!("g(2) gave {:?}", result),
+        }
 
-            if coords.len() == 2 {
-                if let (Ok(x), Ok(y)) = (coords[0].parse::<f64>(), coords[1].parse::<f64>()) {
-                    return Some(Point { x, y });
-                }
-            }
+        let error = repl.process_input("del(undefined)").unwrap_err();
+        assert_eq!(error.to_string(), ReplError::InvalidArgument.to_string());
+    }
+
+    #[test]
+    fn reset_forgets_bindings_and_keeps_settings() {
+        let mut repl = Repl::new();
+        define_one_of_each(&mut repl);
+        repl.set_exact_forms(true);
+        repl.process_input("format(fixed, 2)").unwrap();
+
+        repl.process_input("reset()").unwrap();
+        assert!(repl.bindings().is_empty());
+        assert!(repl.process_input("f(2)").is_err());
+        assert!(repl.exact_forms());
+        assert_eq!(repl.number_format().to_string(), "fixed, 2");
+        match repl.process_input("sqrt(16)").unwrap() {
+            ReplResult::Number(value) => assert_eq!(value, 4.0),
+            result => panic!("sqrt(16) gave {:?}", result),
         }
-        None
     }
 }
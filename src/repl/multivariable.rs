@@ -0,0 +1,298 @@
+use super::forms::bound_variable;
+use super::forms::FormValue;
+use super::solve::tuple_numbers;
+use super::solve::tuple_sources;
+use super::symbolic::Expr;
+use super::Point;
+use super::Repl;
+use super::ReplError;
+use evalexpr::Context;
+use evalexpr::EvalexprError;
+use evalexpr::Value;
+use std::error::Error;
+
+/// The forms that produce a vector or matrix of partial derivatives. Without a
+/// point they are shown symbolically, with one they evaluate to numbers.
+const DERIVATIVE_FORMS: [&str; 3] = ["grad", "hessian", "jacobian"];
+
+/// Partial derivatives arranged as a vector or as a matrix, one row per function.
+enum Derivatives {
+    Vector(Vec<Expr>),
+    Matrix(Vec<Vec<Expr>>),
+}
+
+impl Repl {
+    /// `partial(f, x)`, the partial derivative of an expression, or of a user
+    /// function with respect to one of its parameters. `partial(f, x, P)` is its
+    /// value at the point `P`, whose coordinates are the function's parameters in
+    /// order, or for an expression its variables in order of appearance, leaving
+    /// out those that are defined, e.g. `partial(x^2*y, x, (1, 2))`.
+    pub(super) fn eval_partial(
+        &mut self,
+        arguments: &[String],
+        bindings: &[(String, Value)],
+    ) -> Result<FormValue, Box<dyn Error>> {
+        if arguments.len() != 2 && arguments.len() != 3 {
+            return Err(Box::new(EvalexprError::wrong_function_argument_amount(
+                arguments.len(),
+                2,
+            )));
+        }
+
+        let variable = bound_variable(&arguments[1])?;
+        let parameters = self.function_parameters(&arguments[0]);
+        let expression = self.differentiable(
+            &arguments[0],
+            parameters
+                .as_deref()
+                .unwrap_or(std::slice::from_ref(&variable)),
+        )?;
+        let derivative = expression.derivative(&variable)?;
+
+        let Some(point) = arguments.get(2) else {
+            return Ok(FormValue::Expression(derivative));
+        };
+
+        let variables = parameters.unwrap_or_else(|| {
+            expression
+                .variables()
+                .into_iter()
+                .filter(|name| {
+                    *name == variable
+                        || (Context::get_value(&self.context, name).is_none()
+                            && !bindings.iter().any(|(bound, _)| bound == name))
+                })
+                .collect()
+        });
+        let scope = self.point_bindings(point, &variables, bindings)?;
+        Ok(FormValue::Value(Value::Float(
+            self.evaluate_number(&derivative.to_source(), &scope)?,
+        )))
+    }
+
+    /// `grad(f, (x, y))`, `hessian(f, (x, y))` and `jacobian((f, g), (x, y))`,
+    /// evaluated at the point given as a third argument, otherwise at the values
+    /// the variables have. Vectors are tuples, matrices tuples of rows.
+    pub(super) fn eval_derivatives(
+        &mut self,
+        name: &str,
+        arguments: &[String],
+        bindings: &[(String, Value)],
+    ) -> Result<Value, Box<dyn Error>> {
+        if arguments.len() != 2 && arguments.len() != 3 {
+            return Err(Box::new(EvalexprError::wrong_function_argument_amount(
+                arguments.len(),
+                3,
+            )));
+        }
+
+        let (derivatives, variables) = self.derivatives(name, arguments)?;
+        let scope = match arguments.get(2) {
+            Some(point) => self.point_bindings(point, &variables, bindings)?,
+            None => bindings.to_vec(),
+        };
+
+        let mut evaluate_row = |row: &[Expr]| -> Result<Value, Box<dyn Error>> {
+            let mut values = Vec::with_capacity(row.len());
+            for expression in row {
+                values.push(Value::Float(
+                    self.evaluate_number(&expression.to_source(), &scope)?,
+                ));
+            }
+            Ok(Value::Tuple(values))
+        };
+
+        match derivatives {
+            Derivatives::Vector(vector) => evaluate_row(&vector),
+            Derivatives::Matrix(matrix) => Ok(Value::Tuple(
+                matrix
+                    .iter()
+                    .map(|row| evaluate_row(row))
+                    .collect::<Result<Vec<Value>, Box<dyn Error>>>()?,
+            )),
+        }
+    }
+
+    /// If the whole input is `grad`, `hessian` or `jacobian` without a point,
+    /// the derivatives written out, e.g. `(2*x*y, x^2)`.
+    pub(super) fn derivative_form(
+        &mut self,
+        input: &str,
+    ) -> Result<Option<String>, Box<dyn Error>> {
+        let Some((name, arguments)) = self.whole_form(input) else {
+            return Ok(None);
+        };
+
+        if !DERIVATIVE_FORMS.contains(&name.as_str()) || arguments.len() != 2 {
+            return Ok(None);
+        }
+
+        let tuple = |row: &[Expr]| {
+            let items: Vec<String> = row.iter().map(Expr::to_string).collect();
+            format!("({})", items.join(", "))
+        };
+
+        Ok(Some(match self.derivatives(&name, &arguments)?.0 {
+            Derivatives::Vector(vector) => tuple(&vector),
+            Derivatives::Matrix(matrix) => {
+                let rows: Vec<String> = matrix.iter().map(|row| tuple(row)).collect();
+                format!("({})", rows.join(", "))
+            }
+        }))
+    }
+
+    /// The gradient of a function of `x` and `y` at a point, for the graph to
+    /// draw as an arrow. The function is a user function of two parameters or an
+    /// expression in `x` and `y`.
+    pub fn gradient_at(&mut self, function: &str, point: Point) -> Result<Point, Box<dyn Error>> {
+        self.sync_globals();
+
+        let variables = self
+            .function_parameters(function)
+            .unwrap_or_else(|| vec!["x".to_string(), "y".to_string()]);
+        if variables.len() != 2 {
+            return Err(Box::new(ReplError::InvalidArgument));
+        }
+
+        let expression = self.differentiable(function, &variables)?;
+        let scope = vec![
+            (variables[0].clone(), Value::Float(point.x)),
+            (variables[1].clone(), Value::Float(point.y)),
+        ];
+
+        Ok(Point {
+            x: self.evaluate_number(&expression.derivative(&variables[0])?.to_source(), &scope)?,
+            y: self.evaluate_number(&expression.derivative(&variables[1])?.to_source(), &scope)?,
+        })
+    }
+
+    // The symbolic derivatives for `grad`, `hessian` or `jacobian`, and the
+    // variables they are taken with respect to.
+    fn derivatives(
+        &mut self,
+        name: &str,
+        arguments: &[String],
+    ) -> Result<(Derivatives, Vec<String>), Box<dyn Error>> {
+        let variables = tuple_sources(&arguments[1])
+            .iter()
+            .map(|variable| bound_variable(variable))
+            .collect::<Result<Vec<String>, Box<dyn Error>>>()?;
+
+        let gradient = |expression: &Expr| -> Result<Vec<Expr>, Box<dyn Error>> {
+            variables
+                .iter()
+                .map(|variable| expression.derivative(variable))
+                .collect()
+        };
+
+        let derivatives = match name {
+            "grad" => {
+                Derivatives::Vector(gradient(&self.differentiable(&arguments[0], &variables)?)?)
+            }
+            "hessian" => Derivatives::Matrix(
+                gradient(&self.differentiable(&arguments[0], &variables)?)?
+                    .iter()
+                    .map(gradient)
+                    .collect::<Result<Vec<Vec<Expr>>, Box<dyn Error>>>()?,
+            ),
+            _ => {
+                let mut rows = Vec::new();
+                for function in tuple_sources(&arguments[0]) {
+                    rows.push(gradient(&self.differentiable(&function, &variables)?)?);
+                }
+                Derivatives::Matrix(rows)
+            }
+        };
+
+        Ok((derivatives, variables))
+    }
+
+    // The expression to differentiate. The name of a user function stands for the
+    // function applied to the variables, otherwise the source is an expression.
+    fn differentiable(
+        &mut self,
+        source: &str,
+        variables: &[String],
+    ) -> Result<Expr, Box<dyn Error>> {
+        let expression = match self.function_parameters(source) {
+            Some(parameters) if parameters.len() != variables.len() => {
+                return Err(Box::new(EvalexprError::wrong_function_argument_amount(
+                    variables.len(),
+                    parameters.len(),
+                )))
+            }
+            Some(_) => Expr::call(
+                source.trim(),
+                variables
+                    .iter()
+                    .map(|variable| Expr::Variable(variable.clone()))
+                    .collect(),
+            ),
+            None => Expr::parse(&self.expand_expression_forms(source)?)?,
+        };
+
+        self.inline_functions(&expression)
+    }
+
+    fn function_parameters(&self, source: &str) -> Option<Vec<String>> {
+        self.functions
+            .get(source.trim())
+            .map(|function| function.params.clone())
+    }
+
+    // Bind the coordinates of a point, such as `Point(1, 2)` or `(1, 2, 3)`, to
+    // the variables in order.
+    fn point_bindings(
+        &mut self,
+        point: &str,
+        variables: &[String],
+        bindings: &[(String, Value)],
+    ) -> Result<Vec<(String, Value)>, Box<dyn Error>> {
+        let coordinates = tuple_numbers(self.evaluate(point, bindings)?)?;
+        if coordinates.len() != variables.len() {
+            return Err(Box::new(ReplError::InvalidArgument));
+        }
+
+        let mut scope = bindings.to_vec();
+        for (variable, value) in variables.iter().zip(coordinates) {
+            scope.push((variable.clone(), Value::Float(value)));
+        }
+        Ok(scope)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repl::ReplResult;
+
+    fn number(repl: &mut Repl, input: &str) -> f64 {
+        match repl.process_input(input).unwrap() {
+            ReplResult::Number(value) => value,
+            result => panic!("{} gave {:?}", input, result),
+        }
+    }
+
+    #[test]
+    fn partial_derivatives_of_expressions() {
+        let mut repl = Repl::new();
+        assert_eq!(number(&mut repl, "partial(x^2*y, x, (1, 2))"), 4.0);
+        assert_eq!(number(&mut repl, "partial(x^2*y, y, Point(3, 1))"), 9.0);
+        assert_eq!(number(&mut repl, "partial(x^3, x, 2)"), 12.0);
+        assert!(repl.process_input("partial(x^2*y, x, 1)").is_err());
+
+        repl.process_input("a = 5").unwrap();
+        assert_eq!(number(&mut repl, "partial(a*x^2, x, 2)"), 20.0);
+    }
+
+    #[test]
+    fn partial_derivatives_of_user_functions() {
+        let mut repl = Repl::new();
+        repl.process_input("f(x, y) = x^2*y").unwrap();
+        assert_eq!(number(&mut repl, "partial(f, x, Point(1, 2))"), 4.0);
+        match repl.process_input("partial(f, x)").unwrap() {
+            ReplResult::Expression(expression) => assert_eq!(expression, "2*x*y"),
+            result => panic!("partial gave {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,426 @@
+use super::latex;
+use super::Repl;
+use super::ReplError;
+use evalexpr::IterateVariablesContext;
+use std::collections::BTreeSet;
+
+/// Functions conventionally written without parentheses, as in `sin x`, which
+/// would otherwise be read as the product `sin*x`.
+const PREFIX_FUNCTIONS: &[&str] = &[
+    "sin", "cos", "tan", "sec", "cosec", "cot", "asin", "acos", "atan", "sinh", "cosh", "tanh",
+    "coth", "exp", "ln", "log", "sqrt",
+];
+
+/// A piece of input as far as conventional notation is concerned. Operators,
+/// commas and string literals pass through untouched as text.
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(String),
+    Name(String),
+    Open,
+    Close,
+    Bar,
+    Root,
+    Bang,
+    Space(String),
+    Text(String),
+}
+
+/// What the names in an input stand for, which tells a call such as `f(x)`
+/// from a product such as `a(x + 1)`.
+#[derive(Debug, Clone, Default)]
+pub struct Names {
+    pub variables: BTreeSet<String>,
+    pub functions: BTreeSet<String>,
+}
+
+/// What ends the group being read.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Closing {
+    End,
+    Paren,
+    Bar,
+}
+
+/// Rewrite conventional math notation as evalexpr syntax: implicit
+/// multiplication such as `2x`, `3sin(x)` or `(x + 1)(x - 1)`, `|x - 1|` for
+/// `abs(x - 1)`, `√2` for `sqrt(2)`, `5!` for `factorial(5)`, and `×`, `·` and
+/// `÷` for `*` and `/`. Input that can be read more than one way is an error,
+/// such as a variable followed by parentheses or a function without them.
+/// LaTeX input is translated first.
+pub fn normalize(input: &str, names: &Names) -> Result<String, ReplError> {
+    let translated;
+    let input = if latex::is_latex(input) {
+        translated = latex::translate(input)?;
+        &translated
+    } else {
+        input
+    };
+
+    let mut parser = Parser {
+        tokens: tokenize(input),
+        position: 0,
+        names,
+    };
+    parser.sequence(Closing::End)
+}
+
+impl Repl {
+    /// Normalize an input with the variables and user functions defined so
+    /// far. A function being defined is not a variable, even if it was one,
+    /// and its parameters are.
+    pub(super) fn normalize(&self, input: &str) -> Result<String, ReplError> {
+        let definition = self.regex_function_pattern.captures(input.trim());
+        let defined = definition
+            .as_ref()
+            .or(self
+                .regex_initial_value_pattern
+                .captures(input.trim())
+                .as_ref())
+            .map(|captures| captures[1].to_string());
+        let parameters = definition
+            .as_ref()
+            .and_then(|captures| captures.get(2))
+            .map(|parameters| {
+                parameters
+                    .as_str()
+                    .split(',')
+                    .map(|name| name.trim().to_string())
+            });
+
+        let names = Names {
+            variables: self
+                .context
+                .iter_variable_names()
+                .filter(|name| Some(name) != defined.as_ref())
+                .filter(|name| !self.functions.contains_key(name))
+                .chain(parameters.into_iter().flatten())
+                .collect(),
+            functions: self.functions.keys().cloned().chain(defined).collect(),
+        };
+        normalize(input, &names)
+    }
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    names: &'a Names,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    // The next token that is not a space.
+    fn peek_past_spaces(&self) -> Option<&Token> {
+        self.tokens[self.position..]
+            .iter()
+            .find(|token| !matches!(token, Token::Space(_)))
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    // Operands and the operators between them, up to the end of the group.
+    // Two operands in a row are multiplied.
+    fn sequence(&mut self, closing: Closing) -> Result<String, ReplError> {
+        let mut output = String::new();
+        let mut operand_start = None;
+        let mut last_number: Option<String> = None;
+
+        loop {
+            let is_after_operand = operand_start.is_some();
+
+            match self.peek() {
+                None => {
+                    return match closing {
+                        Closing::End => Ok(output),
+                        Closing::Paren => Err(syntax("unmatched `(`")),
+                        Closing::Bar => Err(syntax("unmatched `|`")),
+                    }
+                }
+                Some(Token::Close) => {
+                    self.next();
+                    return match closing {
+                        Closing::Paren => Ok(output),
+                        _ => Err(syntax("unmatched `)`")),
+                    };
+                }
+                Some(Token::Bar) if is_after_operand && closing == Closing::Bar => {
+                    self.next();
+                    if self.is_ambiguous_bar() {
+                        return Err(syntax(
+                            "`|` could close this absolute value or open another, write abs(...)",
+                        ));
+                    }
+                    return Ok(output);
+                }
+                Some(Token::Bar)
+                    if is_after_operand
+                        && self.tokens.get(self.position + 1) == Some(&Token::Bar) =>
+                {
+                    self.position += 2;
+                    output.push_str("||");
+                    operand_start = None;
+                    last_number = None;
+                }
+                Some(Token::Space(space)) => {
+                    output.push_str(space);
+                    self.next();
+                }
+                Some(Token::Text(text)) => {
+                    output.push_str(text);
+                    self.next();
+                    operand_start = None;
+                    last_number = None;
+                }
+                Some(Token::Bang) if is_after_operand => {
+                    self.next();
+                    let operand = output.split_off(operand_start.unwrap());
+                    output.push_str(&format!("factorial({})", operand.trim_end()));
+                    last_number = None;
+                }
+                Some(Token::Bang) => {
+                    self.next();
+                    output.push('!');
+                }
+                Some(token) => {
+                    let number = match token {
+                        Token::Number(number) => Some(number.clone()),
+                        _ => None,
+                    };
+
+                    if is_after_operand {
+                        if let (Some(first), Some(second)) = (&last_number, &number) {
+                            return Err(syntax(&format!(
+                                "`{} {}` is ambiguous, write `{}*{}` or `{}{}`",
+                                first, second, first, second, first, second
+                            )));
+                        }
+                        // Keep the space, if any, after the multiplication sign.
+                        let trimmed = output.trim_end().len();
+                        output.insert(trimmed, '*');
+                    }
+
+                    operand_start = Some(output.len());
+                    output.push_str(&self.operand()?);
+                    last_number = number;
+                }
+            }
+        }
+    }
+
+    // A number, a name or call, a group in parentheses or bars, or a root.
+    fn operand(&mut self) -> Result<String, ReplError> {
+        match self.next() {
+            Some(Token::Number(number)) => Ok(number),
+            Some(Token::Name(name)) => {
+                let is_function = self.names.functions.contains(&name)
+                    || PREFIX_FUNCTIONS.contains(&name.as_str());
+
+                if self.peek() == Some(&Token::Open) {
+                    if self.names.variables.contains(&name) && !is_function {
+                        return Err(syntax(&format!(
+                            "`{}(…)` is ambiguous, write `{}*(…)`",
+                            name, name
+                        )));
+                    }
+                    self.next();
+                    Ok(format!("{}({})", name, self.sequence(Closing::Paren)?))
+                } else if is_function
+                    && matches!(
+                        self.peek_past_spaces(),
+                        Some(Token::Number(_) | Token::Name(_) | Token::Bar | Token::Root)
+                    )
+                {
+                    Err(syntax(&format!("missing parentheses, write `{}(…)`", name)))
+                } else {
+                    Ok(name)
+                }
+            }
+            Some(Token::Open) => Ok(format!("({})", self.sequence(Closing::Paren)?)),
+            Some(Token::Bar) => Ok(format!("abs({})", self.sequence(Closing::Bar)?)),
+            Some(Token::Root) => {
+                while matches!(self.peek(), Some(Token::Space(_))) {
+                    self.next();
+                }
+                if !matches!(
+                    self.peek(),
+                    Some(
+                        Token::Number(_) | Token::Name(_) | Token::Open | Token::Bar | Token::Root
+                    )
+                ) {
+                    return Err(syntax("`√` needs something to take the root of"));
+                }
+
+                let radicand = self.operand()?;
+                if radicand.starts_with('(') {
+                    Ok(format!("sqrt{}", radicand))
+                } else {
+                    Ok(format!("sqrt({})", radicand))
+                }
+            }
+            _ => Err(syntax("expected an operand")),
+        }
+    }
+
+    // A closing bar followed by an operand, with more bars to come, as in
+    // `|a|b|c|`, which reads as `abs(a)*b*abs(c)` or `abs(a*abs(b)*c)`.
+    fn is_ambiguous_bar(&self) -> bool {
+        matches!(
+            self.peek_past_spaces(),
+            Some(Token::Number(_) | Token::Name(_) | Token::Open | Token::Root)
+        ) && self.tokens[self.position..].contains(&Token::Bar)
+    }
+}
+
+fn syntax(message: &str) -> ReplError {
+    ReplError::Syntax(message.to_string())
+}
+
+fn tokenize(input: &str) -> Vec<Token> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    let is_digit = |i: usize| chars.get(i).is_some_and(char::is_ascii_digit);
+
+    while i < chars.len() {
+        let start = i;
+        let ch = chars[i];
+
+        let token = if ch.is_whitespace() {
+            while i < chars.len() && chars[i].is_whitespace() {
+                i += 1;
+            }
+            Token::Space(chars[start..i].iter().collect())
+        } else if ch.is_ascii_digit() || (ch == '.' && is_digit(i + 1)) {
+            while is_digit(i) || (i < chars.len() && chars[i] == '.') {
+                i += 1;
+            }
+            // An exponent only if digits follow, so that `2e` is `2*e`.
+            if i < chars.len() && matches!(chars[i], 'e' | 'E') {
+                let sign = usize::from(matches!(chars.get(i + 1), Some('+' | '-')));
+                if is_digit(i + 1 + sign) {
+                    i += 1 + sign;
+                    while is_digit(i) {
+                        i += 1;
+                    }
+                }
+            }
+            Token::Number(chars[start..i].iter().collect())
+        } else if ch.is_alphabetic() || ch == '_' {
+            while i < chars.len()
+                && (chars[i].is_alphanumeric()
+                    || chars[i] == '_'
+                    || (chars[i] == ':' && chars.get(i + 1) == Some(&':')))
+            {
+                i += if chars[i] == ':' { 2 } else { 1 };
+            }
+            Token::Name(chars[start..i].iter().collect())
+        } else if ch == '"' {
+            i += 1;
+            while i < chars.len() && chars[i] != '"' {
+                i += if chars[i] == '\\' { 2 } else { 1 };
+            }
+            i = (i + 1).min(chars.len());
+            Token::Text(chars[start..i].iter().collect())
+        } else {
+            i += 1;
+            match ch {
+                '(' => Token::Open,
+                ')' => Token::Close,
+                '|' => Token::Bar,
+                '√' => Token::Root,
+                '×' | '·' => Token::Text("*".to_string()),
+                '÷' => Token::Text("/".to_string()),
+                '!' if chars.get(i) == Some(&'=') => {
+                    i += 1;
+                    Token::Text("!=".to_string())
+                }
+                '!' => Token::Bang,
+                _ => Token::Text(ch.to_string()),
+            }
+        };
+
+        tokens.push(token);
+    }
+
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(variables: &[&str], functions: &[&str]) -> Names {
+        Names {
+            variables: variables.iter().map(|name| name.to_string()).collect(),
+            functions: functions.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn implicit_multiplication_and_notation() {
+        let names = Names::default();
+        assert_eq!(normalize("2x", &names).unwrap(), "2*x");
+        assert_eq!(normalize("3sin(x)", &names).unwrap(), "3*sin(x)");
+        assert_eq!(
+            normalize("(x + 1)(x - 1)", &names).unwrap(),
+            "(x + 1)*(x - 1)"
+        );
+        assert_eq!(normalize("|x - 1|", &names).unwrap(), "abs(x - 1)");
+        assert_eq!(normalize("√2", &names).unwrap(), "sqrt(2)");
+        assert_eq!(normalize("5!", &names).unwrap(), "factorial(5)");
+        assert_eq!(normalize("6 ÷ 2 × 3", &names).unwrap(), "6 / 2 * 3");
+    }
+
+    #[test]
+    fn ambiguous_input_is_an_error() {
+        let names = Names::default();
+        assert!(normalize("2 3", &names).is_err());
+        assert!(normalize("|a|b|c|", &names).is_err());
+        assert!(normalize("(x + 1", &names).is_err());
+    }
+
+    #[test]
+    fn variable_followed_by_parentheses_is_ambiguous() {
+        let error = normalize("x(x + 1)", &names(&["x"], &[])).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            ReplError::Syntax("`x(…)` is ambiguous, write `x*(…)`".to_string()).to_string()
+        );
+        assert_eq!(
+            normalize("f(x + 1)", &names(&["x"], &["f"])).unwrap(),
+            "f(x + 1)"
+        );
+    }
+
+    #[test]
+    fn function_without_parentheses_is_an_error() {
+        let names = names(&["x"], &["f"]);
+        for input in ["sin x", "f 2", "ln |x|"] {
+            let error = normalize(input, &names).unwrap_err().to_string();
+            assert!(
+                error.contains("missing parentheses"),
+                "{}: {}",
+                input,
+                error
+            );
+        }
+        assert_eq!(normalize("del(f)", &names).unwrap(), "del(f)");
+    }
+
+    #[test]
+    fn definitions_may_reuse_a_variable_name() {
+        let mut repl = Repl::new();
+        repl.process_input("a = 2").unwrap();
+        repl.process_input("a(n) = n + 1").unwrap();
+        assert!(repl.process_input("g(t) = t(t + 1)").is_err());
+    }
+}
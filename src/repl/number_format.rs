@@ -0,0 +1,305 @@
+use super::forms::split_arguments;
+use super::Repl;
+use super::ReplError;
+use super::ReplResult;
+use std::error::Error;
+use std::fmt;
+
+/// How the digits of a number are written out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Notation {
+    /// As many digits as it takes to tell the number apart, e.g. `0.1`.
+    Auto,
+    /// A fixed number of decimals, e.g. `3.142` with 3.
+    Fixed(usize),
+    /// A number of significant figures, e.g. `3.14` with 3.
+    Significant(usize),
+    /// A mantissa with a number of decimals and a power of ten, e.g. `3.14e2`.
+    Scientific(usize),
+    /// As scientific, with the power of ten a multiple of three, e.g. `314.16e0`.
+    Engineering(usize),
+}
+
+/// The display settings for numbers in the REPL output, the coordinate
+/// readout and the axis labels, set with `format(...)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NumberFormat {
+    pub notation: Notation,
+    /// Whether the digits before the decimal point are grouped in threes.
+    pub grouped: bool,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        NumberFormat {
+            notation: Notation::Auto,
+            grouped: false,
+        }
+    }
+}
+
+impl fmt::Display for NumberFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.notation {
+            Notation::Auto => write!(f, "auto")?,
+            Notation::Fixed(digits) => write!(f, "fixed, {}", digits)?,
+            Notation::Significant(digits) => write!(f, "sig, {}", digits)?,
+            Notation::Scientific(digits) => write!(f, "sci, {}", digits)?,
+            Notation::Engineering(digits) => write!(f, "eng, {}", digits)?,
+        }
+        if self.grouped {
+            write!(f, ", group")?;
+        }
+        Ok(())
+    }
+}
+
+/// The most digits any notation is asked for, beyond which `f64` has none.
+const MAX_DIGITS: usize = 17;
+
+impl NumberFormat {
+    pub fn is_default(&self) -> bool {
+        *self == NumberFormat::default()
+    }
+
+    /// Read the arguments of `format(...)`: a notation out of `auto`, `fixed`,
+    /// `sig`, `sci` and `eng`, the number of digits it takes, and `group` for
+    /// digit grouping, e.g. `format(fixed, 2, group)`.
+    pub fn parse(arguments: &str) -> Result<Self, ReplError> {
+        let arguments = split_arguments(arguments);
+        let mut arguments = arguments.iter().map(|argument| argument.trim());
+
+        let notation = arguments.next().unwrap_or_default();
+        let mut digits = None;
+        let mut grouped = false;
+        for argument in arguments {
+            match argument {
+                "group" if !grouped => grouped = true,
+                _ if digits.is_none() && !grouped => {
+                    digits = Some(
+                        argument
+                            .parse::<usize>()
+                            .ok()
+                            .filter(|&digits| digits <= MAX_DIGITS)
+                            .ok_or(ReplError::InvalidArgument)?,
+                    )
+                }
+                _ => return Err(ReplError::InvalidArgument),
+            }
+        }
+
+        let notation = match (notation, digits) {
+            ("auto", None) => Notation::Auto,
+            ("fixed", Some(digits)) => Notation::Fixed(digits),
+            ("sig", Some(digits)) if digits > 0 => Notation::Significant(digits),
+            ("sci", Some(digits)) => Notation::Scientific(digits),
+            ("eng", Some(digits)) => Notation::Engineering(digits),
+            ("auto" | "fixed" | "sig" | "sci" | "eng", _) => {
+                return Err(ReplError::InvalidArgument)
+            }
+            _ => {
+                return Err(ReplError::Unsupported(format!(
+                    "number format `{}`",
+                    notation
+                )))
+            }
+        };
+
+        Ok(NumberFormat { notation, grouped })
+    }
+
+    pub fn format(&self, value: f64) -> String {
+        if !value.is_finite() {
+            return format!("{:?}", value);
+        }
+
+        let formatted = match self.notation {
+            Notation::Auto => format!("{:?}", value),
+            Notation::Fixed(digits) => format!("{:.*}", digits, value),
+            Notation::Significant(digits) => {
+                // The exponent after rounding, so that 9.96 to two figures is 10.
+                let exponent = exponent(&format!("{:.*e}", digits - 1, value));
+                let decimals = digits as i32 - 1 - exponent;
+                if decimals >= 0 {
+                    format!("{:.*}", decimals as usize, value)
+                } else {
+                    let scale = 10f64.powi(-decimals);
+                    format!("{:.0}", (value / scale).round() * scale)
+                }
+            }
+            Notation::Scientific(digits) => format!("{:.*e}", digits, value),
+            Notation::Engineering(digits) => {
+                let mut exponent = exponent(&format!("{:e}", value)).div_euclid(3) * 3;
+                let mut mantissa = format!("{:.*}", digits, value / 10f64.powi(exponent));
+                // Rounding can carry into a fourth digit, as in 999.96e0.
+                if mantissa.parse::<f64>().unwrap_or_default().abs() >= 1000.0 {
+                    exponent += 3;
+                    mantissa = format!("{:.*}", digits, value / 10f64.powi(exponent));
+                }
+                format!("{}e{}", mantissa, exponent)
+            }
+        };
+
+        // Values that round to zero lose their sign, e.g. -0.001 to two decimals.
+        let formatted = match formatted.strip_prefix('-') {
+            Some(unsigned) if self.notation != Notation::Auto && is_zero(unsigned) => {
+                unsigned.to_string()
+            }
+            _ => formatted,
+        };
+
+        if self.grouped {
+            group_digits(&formatted)
+        } else {
+            formatted
+        }
+    }
+
+    /// A number as it is written in the REPL output, where results are shown
+    /// in the shape of their `Debug` form, e.g. `Number(0.5)`.
+    fn result(&self, result: &ReplResult) -> String {
+        match result {
+            ReplResult::Number(value) => format!("Number({})", self.format(*value)),
+            ReplResult::Point(point) => format!(
+                "Point(Point {{ x: {}, y: {} }})",
+                self.format(point.x),
+                self.format(point.y)
+            ),
+            ReplResult::List(array) => format!(
+                "List(Array {{ items: [{}] }})",
+                array
+                    .items
+                    .iter()
+                    .map(|item| self.result(item))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            ReplResult::Bindings(bindings) => format!(
+                "Bindings([{}])",
+                bindings
+                    .iter()
+                    .map(|(name, value)| format!("({:?}, {})", name, self.format(*value)))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            _ => format!("{:?}", result),
+        }
+    }
+}
+
+fn exponent(scientific: &str) -> i32 {
+    scientific
+        .split('e')
+        .nth(1)
+        .and_then(|exponent| exponent.parse().ok())
+        .unwrap_or(0)
+}
+
+// Whether the digits of a formatted number, before any exponent, are all zero.
+fn is_zero(formatted: &str) -> bool {
+    formatted
+        .split('e')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .all(|ch| ch == '0' || ch == '.')
+}
+
+// Put a space between each group of three digits before the decimal point,
+// e.g. `1234567.5` as `1 234 567.5`.
+fn group_digits(formatted: &str) -> String {
+    let (sign, unsigned) = match formatted.strip_prefix('-') {
+        Some(unsigned) => ("-", unsigned),
+        None => ("", formatted),
+    };
+    let end = unsigned
+        .find(|ch: char| !ch.is_ascii_digit())
+        .unwrap_or(unsigned.len());
+    let (integer, rest) = unsigned.split_at(end);
+
+    let mut grouped = String::new();
+    for (index, digit) in integer.chars().enumerate() {
+        if index > 0 && (integer.len() - index) % 3 == 0 {
+            grouped.push(' ');
+        }
+        grouped.push(digit);
+    }
+
+    format!("{}{}{}", sign, grouped, rest)
+}
+
+impl Repl {
+    pub fn number_format(&self) -> NumberFormat {
+        self.number_format
+    }
+
+    pub fn set_number_format(&mut self, number_format: NumberFormat) {
+        self.number_format = number_format;
+    }
+
+    /// A result as it is shown in the REPL output, with its numbers written
+    /// out in the current number format.
+    pub fn format_result(&self, result: &Result<ReplResult, Box<dyn Error>>) -> String {
+        match result {
+            Ok(result) => format!("Ok({})", self.number_format.result(result)),
+            Err(_) => format!("{:?}", result),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(arguments: &str, value: f64) -> String {
+        NumberFormat::parse(arguments).unwrap().format(value)
+    }
+
+    #[test]
+    fn formats_in_each_notation() {
+        assert_eq!(format("fixed, 2, group", 1234567.891), "1 234 567.89");
+        assert_eq!(format("fixed, 3", -0.5), "-0.500");
+        assert_eq!(format("sig, 3", 1.23456), "1.23");
+        assert_eq!(format("sig, 2", 9.96), "10");
+        assert_eq!(format("sig, 2", 12345.0), "12000");
+        assert_eq!(format("sci, 2", 123.456), "1.23e2");
+        assert_eq!(format("eng, 2", 31415.9), "31.42e3");
+        assert_eq!(format("eng, 1", 999.96), "1.0e3");
+        assert_eq!(format("auto", 0.1), "0.1");
+        assert_eq!(format("fixed, 2, group", -1234.5), "-1 234.50");
+    }
+
+    #[test]
+    fn negative_values_that_round_to_zero_lose_their_sign() {
+        assert_eq!(format("fixed, 2", -0.001), "0.00");
+        assert_eq!(format("fixed, 2", -0.0), "0.00");
+        assert_eq!(format("sci, 2", -0.0), "0.00e0");
+        assert_eq!(format("eng, 1", -0.0), "0.0e0");
+        assert_eq!(format("sig, 2", -0.0), "0.0");
+        assert_eq!(format("fixed, 2", -0.005001), "-0.01");
+    }
+
+    #[test]
+    fn settings_read_back() {
+        for arguments in ["auto", "fixed, 2, group", "sig, 3", "sci, 4", "eng, 1"] {
+            assert_eq!(
+                NumberFormat::parse(arguments).unwrap().to_string(),
+                arguments
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_settings_are_errors() {
+        for arguments in [
+            "fixed",
+            "sig, 0",
+            "sci, 18",
+            "auto, 2",
+            "fixed, group, 2",
+            "hex, 2",
+        ] {
+            assert!(NumberFormat::parse(arguments).is_err(), "{}", arguments);
+        }
+    }
+}
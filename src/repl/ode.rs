@@ -0,0 +1,306 @@
+use super::forms::bound_variable;
+use super::forms::expect_arguments;
+use super::forms::split_arguments;
+use super::forms::value_to_number;
+use super::forms::Body;
+use super::serialize_list;
+use super::Array;
+use super::Point;
+use super::Repl;
+use super::ReplError;
+use super::ReplResult;
+use evalexpr::Value;
+use std::error::Error;
+
+/// Relative and absolute tolerance for the local error of one step.
+const ODE_RELATIVE_TOLERANCE: f64 = 1e-8;
+const ODE_ABSOLUTE_TOLERANCE: f64 = 1e-10;
+
+/// Steps are at most this fraction of the interval, so plotted solutions stay smooth.
+const ODE_MAX_STEP_FRACTION: f64 = 0.01;
+
+/// The solver gives up after this many steps, accepted or not.
+const ODE_MAX_STEPS: usize = 100_000;
+
+// Dormand–Prince coefficients: the nodes, the Runge–Kutta matrix, and the
+// weights of the fifth and fourth order solutions.
+const C: [f64; 7] = [0.0, 1.0 / 5.0, 3.0 / 10.0, 4.0 / 5.0, 8.0 / 9.0, 1.0, 1.0];
+const A: [[f64; 6]; 7] = [
+    [0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [1.0 / 5.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [3.0 / 40.0, 9.0 / 40.0, 0.0, 0.0, 0.0, 0.0],
+    [44.0 / 45.0, -56.0 / 15.0, 32.0 / 9.0, 0.0, 0.0, 0.0],
+    [
+        19372.0 / 6561.0,
+        -25360.0 / 2187.0,
+        64448.0 / 6561.0,
+        -212.0 / 729.0,
+        0.0,
+        0.0,
+    ],
+    [
+        9017.0 / 3168.0,
+        -355.0 / 33.0,
+        46732.0 / 5247.0,
+        49.0 / 176.0,
+        -5103.0 / 18656.0,
+        0.0,
+    ],
+    [
+        35.0 / 384.0,
+        0.0,
+        500.0 / 1113.0,
+        125.0 / 192.0,
+        -2187.0 / 6784.0,
+        11.0 / 84.0,
+    ],
+];
+const B5: [f64; 7] = [
+    35.0 / 384.0,
+    0.0,
+    500.0 / 1113.0,
+    125.0 / 192.0,
+    -2187.0 / 6784.0,
+    11.0 / 84.0,
+    0.0,
+];
+const B4: [f64; 7] = [
+    5179.0 / 57600.0,
+    0.0,
+    7571.0 / 16695.0,
+    393.0 / 640.0,
+    -92097.0 / 339200.0,
+    187.0 / 2100.0,
+    1.0 / 40.0,
+];
+
+/// The solution at each accepted step, as `x` and the value of every unknown.
+type Trajectory = Vec<(f64, Vec<f64>)>;
+
+/// One equation `dy/dx = f(x, y)` of an initial value problem.
+struct Equation {
+    dependent: String,
+    independent: String,
+    body: Body,
+}
+
+impl Repl {
+    /// `ode(dy/dx = f(x, y), x0, y0, x_end)`, the solution as a list of points
+    /// `(x, y)`. Systems are written `ode((dx/dt = -y, dy/dt = x), t0, (x0, y0), t_end)`
+    /// and produce one such list per unknown.
+    pub(super) fn eval_ode(
+        &mut self,
+        arguments: &[String],
+        bindings: &[(String, Value)],
+    ) -> Result<Value, Box<dyn Error>> {
+        expect_arguments(arguments, 4)?;
+
+        let is_system = arguments[0].starts_with('(') && arguments[0].ends_with(')');
+        let sources = if is_system {
+            split_arguments(&arguments[0][1..arguments[0].len() - 1])
+        } else {
+            vec![arguments[0].clone()]
+        };
+
+        let equations = sources
+            .iter()
+            .map(|source| self.parse_equation(source))
+            .collect::<Result<Vec<Equation>, Box<dyn Error>>>()?;
+
+        if equations
+            .iter()
+            .any(|equation| equation.independent != equations[0].independent)
+        {
+            return Err(Box::new(ReplError::Unsupported(
+                "equations with different independent variables".to_string(),
+            )));
+        }
+
+        let start = self.evaluate_number(&arguments[1], bindings)?;
+        let end = self.evaluate_number(&arguments[3], bindings)?;
+        let initial = match self.evaluate(&arguments[2], bindings)? {
+            Value::Tuple(values) => values
+                .into_iter()
+                .map(value_to_number)
+                .collect::<Result<Vec<f64>, Box<dyn Error>>>()?,
+            value => vec![value_to_number(value)?],
+        };
+
+        if initial.len() != equations.len() || !start.is_finite() || !end.is_finite() {
+            return Err(Box::new(ReplError::InvalidArgument));
+        }
+
+        let trajectory = self.solve_ode(&equations, bindings, start, &initial, end)?;
+        let component = |index: usize| Array {
+            items: trajectory
+                .iter()
+                .map(|(x, y)| ReplResult::Point(Point { x: *x, y: y[index] }))
+                .collect(),
+        };
+
+        let list = if is_system {
+            Array {
+                items: (0..equations.len())
+                    .map(|index| ReplResult::List(component(index)))
+                    .collect(),
+            }
+        } else {
+            component(0)
+        };
+
+        Ok(Value::String(serialize_list(&list)))
+    }
+
+    // Parse `dy/dx = f(x, y)`.
+    fn parse_equation(&self, source: &str) -> Result<Equation, Box<dyn Error>> {
+        let (derivative, body) = source.split_once('=').ok_or(ReplError::InvalidArgument)?;
+        let (dependent, independent) = derivative
+            .split_once('/')
+            .ok_or(ReplError::InvalidArgument)?;
+
+        let variable = |differential: &str| match differential.trim().strip_prefix('d') {
+            Some(name) => bound_variable(name.trim()),
+            None => Err(Box::new(ReplError::InvalidArgument) as Box<dyn Error>),
+        };
+
+        Ok(Equation {
+            dependent: variable(dependent)?,
+            independent: variable(independent)?,
+            body: self.compile_body(body.trim())?,
+        })
+    }
+
+    // Integrate with the embedded Runge–Kutta 5(4) pair of Dormand and Prince,
+    // adapting the step to keep the local error within tolerance.
+    fn solve_ode(
+        &mut self,
+        equations: &[Equation],
+        bindings: &[(String, Value)],
+        start: f64,
+        initial: &[f64],
+        end: f64,
+    ) -> Result<Trajectory, Box<dyn Error>> {
+        let direction = if end >= start { 1.0 } else { -1.0 };
+        let max_step = ((end - start).abs() * ODE_MAX_STEP_FRACTION).max(f64::MIN_POSITIVE);
+        let mut step = max_step;
+        let mut x = start;
+        let mut y = initial.to_vec();
+        let mut trajectory = vec![(x, y.clone())];
+
+        for _ in 0..ODE_MAX_STEPS {
+            if (end - x) * direction <= 0.0 {
+                return Ok(trajectory);
+            }
+
+            // Finish exactly at the end rather than leaving a sliver of rounding error.
+            let is_last_step = (end - x).abs() <= step * (1.0 + 1e-9);
+            if is_last_step {
+                step = (end - x).abs();
+            }
+            let h = step * direction;
+
+            let mut k: Vec<Vec<f64>> = Vec::with_capacity(7);
+            for stage in 0..7 {
+                let y_stage: Vec<f64> = (0..y.len())
+                    .map(|i| y[i] + h * (0..stage).map(|j| A[stage][j] * k[j][i]).sum::<f64>())
+                    .collect();
+                k.push(self.ode_slope(equations, bindings, x + C[stage] * h, &y_stage)?);
+            }
+
+            let y_next: Vec<f64> = (0..y.len())
+                .map(|i| y[i] + h * (0..7).map(|j| B5[j] * k[j][i]).sum::<f64>())
+                .collect();
+            let error = (0..y.len())
+                .map(|i| {
+                    let difference = h * (0..7).map(|j| (B5[j] - B4[j]) * k[j][i]).sum::<f64>();
+                    let scale = ODE_ABSOLUTE_TOLERANCE
+                        + ODE_RELATIVE_TOLERANCE * y[i].abs().max(y_next[i].abs());
+                    (difference / scale).powi(2)
+                })
+                .sum::<f64>();
+            let error = (error / y.len() as f64).sqrt();
+
+            if !error.is_finite() || y_next.iter().any(|value| !value.is_finite()) {
+                return Err(Box::new(ReplError::DidNotConverge));
+            }
+
+            if error <= 1.0 {
+                x = if is_last_step { end } else { x + h };
+                y = y_next;
+                trajectory.push((x, y.clone()));
+            }
+
+            let factor = if error == 0.0 {
+                5.0
+            } else {
+                (0.9 * error.powf(-0.2)).clamp(0.2, 5.0)
+            };
+            step = (step * factor).min(max_step);
+
+            if step <= f64::EPSILON * x.abs().max(1.0) {
+                return Err(Box::new(ReplError::DidNotConverge));
+            }
+        }
+
+        Err(Box::new(ReplError::DidNotConverge))
+    }
+
+    fn ode_slope(
+        &mut self,
+        equations: &[Equation],
+        bindings: &[(String, Value)],
+        x: f64,
+        y: &[f64],
+    ) -> Result<Vec<f64>, Box<dyn Error>> {
+        let mut scope = bindings.to_vec();
+        scope.push((equations[0].independent.clone(), Value::Float(x)));
+        for (equation, value) in equations.iter().zip(y) {
+            scope.push((equation.dependent.clone(), Value::Float(*value)));
+        }
+
+        equations
+            .iter()
+            .map(|equation| value_to_number(self.evaluate_body(&equation.body, &scope)?))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The last point of a solution.
+    fn end(result: &ReplResult) -> Point {
+        match result {
+            ReplResult::List(list) => match list.items.last() {
+                Some(ReplResult::Point(point)) => *point,
+                item => panic!("solution ends with {:?}", item),
+            },
+            result => panic!("solution is {:?}", result),
+        }
+    }
+
+    #[test]
+    fn exponential_growth() {
+        let mut repl = Repl::new();
+        let solution = repl.process_input("ode(dy/dx = y, 0, 1, 1)").unwrap();
+        let point = end(&solution);
+        assert_eq!(point.x, 1.0);
+        assert!((point.y - std::f64::consts::E).abs() < 1e-6);
+    }
+
+    #[test]
+    fn harmonic_oscillator_turns_half_way_round() {
+        let mut repl = Repl::new();
+        let solution = repl
+            .process_input("ode((dx/dt = -y, dy/dt = x), 0, (1, 0), 3.141592653589793)")
+            .unwrap();
+        let ReplResult::List(unknowns) = solution else {
+            panic!("solution is {:?}", solution);
+        };
+
+        // x = cos(t) and y = sin(t).
+        assert!((end(&unknowns.items[0]).y + 1.0).abs() < 1e-6);
+        assert!(end(&unknowns.items[1]).y.abs() < 1e-6);
+    }
+}
@@ -0,0 +1,416 @@
+use super::forms::bound_variable;
+use super::forms::value_to_number;
+use super::forms::Body;
+use super::solve::tuple_numbers;
+use super::solve::tuple_sources;
+use super::solve::Solution;
+use super::Repl;
+use super::ReplError;
+use evalexpr::EvalexprError;
+use evalexpr::Value;
+use std::error::Error;
+
+/// The golden ratio's conjugate squared, the fraction of an interval taken by a
+/// golden-section step.
+const GOLDEN_SECTION: f64 = 0.381_966_011_250_105_1;
+
+/// Brent's search stops once the bracket around the minimum is this small
+/// relative to its position.
+const BRENT_TOLERANCE: f64 = 1.5e-8;
+const BRENT_MAX_ITERATIONS: usize = 500;
+
+/// Nelder–Mead stops once the simplex has collapsed both in position and in value.
+const NELDER_MEAD_TOLERANCE: f64 = 1e-10;
+const NELDER_MEAD_MAX_ITERATIONS: usize = 20_000;
+
+/// An extremum is taken to be where the objective runs off to infinity, as
+/// `1/x` does next to zero, if it is this many times larger than the objective
+/// a relative step this long away.
+const UNBOUNDED_RATIO: f64 = 1e3;
+const UNBOUNDED_STEP: f64 = 1e-6;
+
+/// The point where `minimize` or `maximize` found the extremum, and its value.
+#[derive(Debug, Clone)]
+pub struct Extremum {
+    pub names: Vec<String>,
+    pub argument: Vec<f64>,
+    pub value: f64,
+    pub is_maximum: bool,
+}
+
+impl Extremum {
+    /// For one variable the point `(x, f(x))` on the curve, for several the
+    /// argument alone, so that in two variables it is a point in the plane.
+    pub fn to_value(&self) -> Value {
+        match self.argument.as_slice() {
+            [x] => Value::Tuple(vec![Value::Float(*x), Value::Float(self.value)]),
+            argument => Value::Tuple(argument.iter().map(|value| Value::Float(*value)).collect()),
+        }
+    }
+}
+
+impl From<Extremum> for Solution {
+    fn from(extremum: Extremum) -> Self {
+        let label = if extremum.is_maximum { "max" } else { "min" };
+        let mut names = extremum.names;
+        let mut values = extremum.argument;
+        names.push(label.to_string());
+        values.push(extremum.value);
+
+        Solution { names, values }
+    }
+}
+
+/// What to optimize: the expression, negated when maximizing, and its variables.
+struct Objective {
+    body: Body,
+    names: Vec<String>,
+    sign: f64,
+}
+
+impl Repl {
+    /// `minimize(f, x, a, b)` searches `[a, b]` by Brent's method, and
+    /// `minimize(f, (x, y), (x0, y0))` searches from a starting point by
+    /// Nelder–Mead. `maximize` takes the same arguments.
+    pub(super) fn eval_optimum(
+        &mut self,
+        arguments: &[String],
+        bindings: &[(String, Value)],
+        is_maximum: bool,
+    ) -> Result<Extremum, Box<dyn Error>> {
+        if arguments.len() != 3 && arguments.len() != 4 {
+            return Err(Box::new(EvalexprError::wrong_function_argument_amount(
+                arguments.len(),
+                4,
+            )));
+        }
+
+        let objective = Objective {
+            body: self.compile_body(&arguments[0])?,
+            names: tuple_sources(&arguments[1])
+                .iter()
+                .map(|name| bound_variable(name))
+                .collect::<Result<Vec<String>, Box<dyn Error>>>()?,
+            sign: if is_maximum { -1.0 } else { 1.0 },
+        };
+
+        let argument = if arguments.len() == 4 {
+            if objective.names.len() != 1 {
+                return Err(Box::new(ReplError::InvalidArgument));
+            }
+
+            let a = self.evaluate_number(&arguments[2], bindings)?;
+            let b = self.evaluate_number(&arguments[3], bindings)?;
+            if !a.is_finite() || !b.is_finite() || a >= b {
+                return Err(Box::new(ReplError::InvalidArgument));
+            }
+
+            vec![self.brent(&objective, bindings, a, b)?]
+        } else {
+            let start = tuple_numbers(self.evaluate(&arguments[2], bindings)?)?;
+            if start.len() != objective.names.len() {
+                return Err(Box::new(ReplError::InvalidArgument));
+            }
+
+            self.nelder_mead(&objective, bindings, start)?
+        };
+
+        let value = self.objective(&objective, bindings, &argument)?;
+        if self.is_unbounded_near(&objective, bindings, &argument, value)? {
+            return Err(Box::new(ReplError::Unbounded));
+        }
+        let value = objective.sign * value;
+
+        Ok(Extremum {
+            names: objective.names,
+            argument,
+            value,
+            is_maximum,
+        })
+    }
+
+    // The value to minimize at a point. Undefined values count as infinitely
+    // bad, so the searches simply avoid them.
+    fn objective(
+        &mut self,
+        objective: &Objective,
+        bindings: &[(String, Value)],
+        argument: &[f64],
+    ) -> Result<f64, Box<dyn Error>> {
+        let mut scope = bindings.to_vec();
+        for (name, value) in objective.names.iter().zip(argument) {
+            scope.push((name.clone(), Value::Float(*value)));
+        }
+
+        let value = objective.sign * value_to_number(self.evaluate_body(&objective.body, &scope)?)?;
+        Ok(if value.is_nan() { f64::INFINITY } else { value })
+    }
+
+    // Whether the objective is far larger, in magnitude, at the point a search
+    // settled on than a small step away in any direction. Searches close in on
+    // a pole such as that of `1/x` at zero, and the value they end with only
+    // depends on how close they got.
+    fn is_unbounded_near(
+        &mut self,
+        objective: &Objective,
+        bindings: &[(String, Value)],
+        argument: &[f64],
+        value: f64,
+    ) -> Result<bool, Box<dyn Error>> {
+        for i in 0..argument.len() {
+            for direction in [-1.0, 1.0] {
+                let mut nearby = argument.to_vec();
+                nearby[i] += direction * UNBOUNDED_STEP * argument[i].abs().max(1.0);
+                let nearby_value = self.objective(objective, bindings, &nearby)?;
+
+                if nearby_value.is_finite()
+                    && value.abs() > UNBOUNDED_RATIO * nearby_value.abs().max(1.0)
+                {
+                    return Ok(true);
+                }
+            }
+        }
+
+        Ok(false)
+    }
+
+    // Brent's method: parabolic interpolation through the three best points so
+    // far, falling back to golden-section steps when the parabola is not trusted.
+    // The ends of the interval are checked too, as the minimum over `[a, b]` may
+    // lie there.
+    fn brent(
+        &mut self,
+        objective: &Objective,
+        bindings: &[(String, Value)],
+        a: f64,
+        b: f64,
+    ) -> Result<f64, Box<dyn Error>> {
+        let (mut low, mut high) = (a, b);
+        let mut x = low + GOLDEN_SECTION * (high - low);
+        let (mut w, mut v) = (x, x);
+        let mut fx = self.objective(objective, bindings, &[x])?;
+        let (mut fw, mut fv) = (fx, fx);
+        let (mut d, mut e) = (0.0_f64, 0.0_f64);
+        let mut has_converged = false;
+
+        for _ in 0..BRENT_MAX_ITERATIONS {
+            let middle = 0.5 * (low + high);
+            let tolerance = BRENT_TOLERANCE * x.abs() + 1e-12;
+            if (x - middle).abs() <= 2.0 * tolerance - 0.5 * (high - low) {
+                has_converged = true;
+                break;
+            }
+
+            let mut is_golden = true;
+            if e.abs() > tolerance {
+                let r = (x - w) * (fx - fv);
+                let q = (x - v) * (fx - fw);
+                let p = (x - v) * q - (x - w) * r;
+                let q = 2.0 * (q - r);
+                let (p, q) = if q > 0.0 { (-p, q) } else { (p, -q) };
+
+                if p.abs() < (0.5 * q * e).abs() && p > q * (low - x) && p < q * (high - x) {
+                    e = d;
+                    d = p / q;
+                    let u = x + d;
+                    if u - low < 2.0 * tolerance || high - u < 2.0 * tolerance {
+                        d = tolerance.copysign(middle - x);
+                    }
+                    is_golden = false;
+                }
+            }
+
+            if is_golden {
+                e = if x >= middle { low - x } else { high - x };
+                d = GOLDEN_SECTION * e;
+            }
+
+            let u = if d.abs() >= tolerance {
+                x + d
+            } else {
+                x + tolerance.copysign(d)
+            };
+            let fu = self.objective(objective, bindings, &[u])?;
+
+            if fu <= fx {
+                if u >= x {
+                    low = x;
+                } else {
+                    high = x;
+                }
+                (v, fv) = (w, fw);
+                (w, fw) = (x, fx);
+                (x, fx) = (u, fu);
+            } else {
+                if u < x {
+                    low = u;
+                } else {
+                    high = u;
+                }
+                if fu <= fw || w == x {
+                    (v, fv) = (w, fw);
+                    (w, fw) = (u, fu);
+                } else if fu <= fv || v == x || v == w {
+                    (v, fv) = (u, fu);
+                }
+            }
+        }
+
+        if !has_converged {
+            return Err(Box::new(ReplError::DidNotConverge));
+        }
+
+        let fa = self.objective(objective, bindings, &[a])?;
+        let fb = self.objective(objective, bindings, &[b])?;
+        let best =
+            [(a, fa), (b, fb)]
+                .into_iter()
+                .fold((x, fx), |best, end| if end.1 < best.1 { end } else { best });
+
+        if best.1.is_finite() {
+            Ok(best.0)
+        } else {
+            Err(Box::new(ReplError::DidNotConverge))
+        }
+    }
+
+    // The Nelder–Mead simplex search with the standard reflection, expansion,
+    // contraction and shrink coefficients.
+    fn nelder_mead(
+        &mut self,
+        objective: &Objective,
+        bindings: &[(String, Value)],
+        start: Vec<f64>,
+    ) -> Result<Vec<f64>, Box<dyn Error>> {
+        let n = start.len();
+        let mut simplex = vec![start.clone()];
+        for i in 0..n {
+            let mut vertex = start.clone();
+            vertex[i] += if vertex[i] == 0.0 {
+                0.1
+            } else {
+                0.1 * vertex[i].abs()
+            };
+            simplex.push(vertex);
+        }
+
+        let mut values = Vec::with_capacity(n + 1);
+        for vertex in &simplex {
+            values.push(self.objective(objective, bindings, vertex)?);
+        }
+
+        for _ in 0..NELDER_MEAD_MAX_ITERATIONS {
+            let mut order: Vec<usize> = (0..=n).collect();
+            order.sort_by(|&i, &j| values[i].total_cmp(&values[j]));
+            simplex = order.iter().map(|&i| simplex[i].clone()).collect();
+            values = order.iter().map(|&i| values[i]).collect();
+
+            let (best, worst) = (values[0], values[n]);
+            let size = simplex[1..]
+                .iter()
+                .flat_map(|vertex| vertex.iter().zip(&simplex[0]).map(|(a, b)| (a - b).abs()))
+                .fold(0.0, f64::max);
+            let scale = simplex[0]
+                .iter()
+                .fold(1.0_f64, |scale, x| scale.max(x.abs()));
+
+            if best.is_finite()
+                && size <= NELDER_MEAD_TOLERANCE * scale
+                && worst - best <= NELDER_MEAD_TOLERANCE * best.abs().max(1.0)
+            {
+                return Ok(simplex.swap_remove(0));
+            }
+
+            let centroid: Vec<f64> = (0..n)
+                .map(|i| simplex[..n].iter().map(|vertex| vertex[i]).sum::<f64>() / n as f64)
+                .collect();
+            let towards = |t: f64| -> Vec<f64> {
+                centroid
+                    .iter()
+                    .zip(&simplex[n])
+                    .map(|(c, w)| c + t * (w - c))
+                    .collect()
+            };
+
+            let reflected = towards(-1.0);
+            let f_reflected = self.objective(objective, bindings, &reflected)?;
+
+            if f_reflected < best {
+                let expanded = towards(-2.0);
+                let f_expanded = self.objective(objective, bindings, &expanded)?;
+                (simplex[n], values[n]) = if f_expanded < f_reflected {
+                    (expanded, f_expanded)
+                } else {
+                    (reflected, f_reflected)
+                };
+                continue;
+            }
+
+            if f_reflected < values[n - 1] {
+                (simplex[n], values[n]) = (reflected, f_reflected);
+                continue;
+            }
+
+            let (contracted, bound) = if f_reflected < worst {
+                (towards(-0.5), f_reflected)
+            } else {
+                (towards(0.5), worst)
+            };
+            let f_contracted = self.objective(objective, bindings, &contracted)?;
+            if f_contracted < bound {
+                (simplex[n], values[n]) = (contracted, f_contracted);
+                continue;
+            }
+
+            // Shrink every vertex halfway towards the best one.
+            for i in 1..=n {
+                simplex[i] = simplex[i]
+                    .iter()
+                    .zip(&simplex[0])
+                    .map(|(x, best)| best + 0.5 * (x - best))
+                    .collect();
+                values[i] = self.objective(objective, bindings, &simplex[i])?;
+            }
+        }
+
+        Err(Box::new(ReplError::DidNotConverge))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repl::ReplResult;
+
+    fn bindings(repl: &mut Repl, input: &str) -> Vec<(String, f64)> {
+        match repl.process_input(input).unwrap() {
+            ReplResult::Bindings(bindings) => bindings,
+            result => panic!("{} gave {:?}", input, result),
+        }
+    }
+
+    #[test]
+    fn finds_known_extrema() {
+        let mut repl = Repl::new();
+
+        let minimum = bindings(&mut repl, "minimize(x^2 - 2x, x, -5, 5)");
+        assert!((minimum[0].1 - 1.0).abs() < 1e-6);
+        assert!((minimum[1].1 + 1.0).abs() < 1e-12);
+
+        let maximum = bindings(&mut repl, "maximize(sin(x), x, 0, 3)");
+        assert!((maximum[0].1 - std::f64::consts::FRAC_PI_2).abs() < 1e-6);
+
+        let minimum = bindings(&mut repl, "minimize((x - 1)^2 + (y + 2)^2, (x, y), (0, 0))");
+        assert!((minimum[0].1 - 1.0).abs() < 1e-6);
+        assert!((minimum[1].1 + 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn pole_is_not_an_extremum() {
+        let mut repl = Repl::new();
+        for input in ["minimize(1/x, x, -1, 1)", "maximize(1/x, x, -1, 1)"] {
+            let error = repl.process_input(input).unwrap_err();
+            assert_eq!(error.to_string(), ReplError::Unbounded.to_string());
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use super::Repl;
+use super::ReplError;
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+/// The prelude in the user config directory, e.g. `~/.config/mymathboard/prelude.txt`.
+const USER_PRELUDE: &str = "mymathboard/prelude.txt";
+
+/// The prelude of a project, in the directory the app is started from.
+const PROJECT_PRELUDE: &str = "mymathboard-prelude.txt";
+
+/// Inputs that would run the prelude again from inside it.
+const PRELUDE_FORBIDDEN: &[&str] = &["reset()", "reload_prelude()"];
+
+impl Repl {
+    /// A REPL with the definitions of the prelude files on top of the built-in
+    /// functions. They run again when it is reset.
+    pub fn with_prelude() -> Self {
+        let files = dirs::config_dir()
+            .map(|dir| dir.join(USER_PRELUDE))
+            .into_iter()
+            .chain(Some(PathBuf::from(PROJECT_PRELUDE)))
+            .collect();
+        Repl::with_prelude_files(files)
+    }
+
+    /// A REPL with a prelude read from the given files, in order, those that
+    /// do not exist skipped.
+    pub fn with_prelude_files(files: Vec<PathBuf>) -> Self {
+        let mut repl = Repl::new();
+        repl.prelude_files = files;
+        repl.load_prelude();
+        repl
+    }
+
+    /// The prelude files that exist, the user one first so that a project can
+    /// override its definitions.
+    pub fn prelude_paths(&self) -> Vec<PathBuf> {
+        self.prelude_files
+            .iter()
+            .filter(|path| path.is_file())
+            .cloned()
+            .collect()
+    }
+
+    /// What went wrong loading the prelude, one message per failed line.
+    pub fn prelude_errors(&self) -> &[String] {
+        &self.prelude_errors
+    }
+
+    /// Run every line of the prelude files as REPL input, skipping blank lines
+    /// and `#` comments. A line that fails is recorded and the rest still run.
+    pub(super) fn load_prelude(&mut self) {
+        self.prelude_errors.clear();
+
+        for path in self.prelude_paths() {
+            let source = match fs::read_to_string(&path) {
+                Ok(source) => source,
+                Err(error) => {
+                    self.prelude_errors
+                        .push(format!("{}: {}", path.display(), error));
+                    continue;
+                }
+            };
+
+            for (index, line) in source.lines().enumerate() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+
+                let compact: String = line.split_whitespace().collect();
+                let result = if PRELUDE_FORBIDDEN.contains(&compact.as_str()) {
+                    let error = ReplError::Unsupported(format!("{} in the prelude", line));
+                    Err(Box::new(error) as Box<dyn Error>)
+                } else {
+                    self.process_input(line)
+                };
+
+                if let Err(error) = result {
+                    self.prelude_errors.push(format!(
+                        "{}:{}: {}: {}",
+                        path.display(),
+                        index + 1,
+                        line,
+                        error
+                    ));
+                }
+            }
+        }
+    }
+
+    /// `reload_prelude()`: run the prelude again on top of what is defined.
+    pub(super) fn reload_prelude(&mut self) -> Result<String, ReplError> {
+        self.load_prelude();
+
+        if !self.prelude_errors.is_empty() {
+            return Err(ReplError::Prelude(self.prelude_errors.join("; ")));
+        }
+
+        let paths = self.prelude_paths();
+        if paths.is_empty() {
+            Ok("no prelude found".to_string())
+        } else {
+            Ok(format!(
+                "loaded {}",
+                paths
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repl::ReplResult;
+
+    // A prelude file of its own for each test, in the temporary directory.
+    fn prelude_file(name: &str, source: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mymathboard-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(PROJECT_PRELUDE);
+        fs::write(&path, source).unwrap();
+        path
+    }
+
+    fn number(repl: &mut Repl, input: &str) -> f64 {
+        match repl.process_input(input).unwrap() {
+            ReplResult::Number(value) => value,
+            result => panic!("{} gave {:?}", input, result),
+        }
+    }
+
+    #[test]
+    fn failing_lines_are_recorded_and_the_rest_run() {
+        let path = prelude_file(
+            "failing",
+            "# constants\n\na = 2\nb = undefined_name\nreset()\nf(x) = a*x\n",
+        );
+        let mut repl = Repl::with_prelude_files(vec![path.clone()]);
+
+        assert_eq!(number(&mut repl, "f(3)"), 6.0);
+        let errors = repl.prelude_errors();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].starts_with(&format!("{}:4: b = undefined_name: ", path.display())));
+        assert_eq!(
+            errors[1],
+            format!(
+                "{}:5: reset(): {}",
+                path.display(),
+                ReplError::Unsupported("reset() in the prelude".to_string())
+            )
+        );
+
+        let error = repl.process_input("reload_prelude()").unwrap_err();
+        assert!(error.to_string().contains("undefined_name"));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn reset_runs_the_prelude_again() {
+        let path = prelude_file("reset", "a = 2\n");
+        let mut repl = Repl::with_prelude_files(vec![path.clone()]);
+        repl.process_input("a = 5").unwrap();
+        repl.process_input("c = 1").unwrap();
+
+        repl.reset();
+        assert_eq!(number(&mut repl, "a"), 2.0);
+        assert!(repl.process_input("c").is_err());
+        assert!(repl.prelude_errors().is_empty());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn missing_files_are_skipped() {
+        let missing = std::env::temp_dir().join("mymathboard-missing-prelude.txt");
+        let mut repl = Repl::with_prelude_files(vec![missing]);
+        assert!(repl.prelude_paths().is_empty());
+        assert!(repl.prelude_errors().is_empty());
+        match repl.process_input("reload_prelude()").unwrap() {
+            ReplResult::String(message) => assert_eq!(message, "no prelude found"),
+            result => panic!("reload_prelude() gave {:?}", result),
+        }
+        // A REPL without a prelude does not read the default files either.
+        assert!(Repl::new().prelude_paths().is_empty());
+    }
+}
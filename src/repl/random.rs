@@ -0,0 +1,292 @@
+use super::distributions::distribution_quantile;
+use super::forms::expect_arguments;
+use super::forms::split_arguments;
+use super::serialize_list;
+use super::Array;
+use super::Repl;
+use super::ReplError;
+use super::ReplResult;
+use evalexpr::ContextWithMutableFunctions;
+use evalexpr::EvalexprError;
+use evalexpr::Function;
+use evalexpr::Value;
+use std::error::Error;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+/// The random number generator behind `rand()` and friends, xoshiro256**.
+/// It is seeded from the clock until `seed(n)` is called, after which the
+/// numbers drawn are the same every time a board is replayed.
+#[derive(Debug, Clone)]
+pub struct Random {
+    state: [u64; 4],
+}
+
+impl Default for Random {
+    fn default() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_nanos() as u64)
+            .unwrap_or_default();
+        Random::new(nanos)
+    }
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        // Expand the seed with SplitMix64, as recommended for xoshiro.
+        let mut x = seed;
+        let mut next = || {
+            x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = x;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        };
+
+        Random {
+            state: [next(), next(), next(), next()],
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let result = self.state[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = self.state[1] << 17;
+
+        self.state[2] ^= self.state[0];
+        self.state[3] ^= self.state[1];
+        self.state[1] ^= self.state[2];
+        self.state[0] ^= self.state[3];
+        self.state[2] ^= t;
+        self.state[3] = self.state[3].rotate_left(45);
+
+        result
+    }
+
+    /// A uniform number in [0, 1).
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 * 2f64.powi(-53)
+    }
+
+    /// A uniform number in (0, 1), safe to pass to a quantile function.
+    pub fn next_open_f64(&mut self) -> f64 {
+        ((self.next_u64() >> 11) as f64 + 0.5) * 2f64.powi(-53)
+    }
+
+    /// A uniform integer in `low..=high`, without modulo bias.
+    pub fn next_int(&mut self, low: i64, high: i64) -> i64 {
+        let range = high.wrapping_sub(low) as u64 as u128 + 1;
+        let zone = (u64::MAX as u128 + 1) / range * range;
+
+        loop {
+            let value = self.next_u64() as u128;
+            if value < zone {
+                return low.wrapping_add((value % range) as i64);
+            }
+        }
+    }
+
+    /// A standard normal number, by the polar method.
+    pub fn next_normal(&mut self) -> f64 {
+        loop {
+            let u = 2.0 * self.next_f64() - 1.0;
+            let v = 2.0 * self.next_f64() - 1.0;
+            let s = u * u + v * v;
+
+            if s > 0.0 && s < 1.0 {
+                return u * (-2.0 * s.ln() / s).sqrt();
+            }
+        }
+    }
+}
+
+impl Repl {
+    pub(super) fn setup_random_functions(&mut self) {
+        let random = self.random.clone();
+        self.context
+            .set_function(
+                "seed".to_string(),
+                Function::new(move |args| {
+                    let seed = args.as_int()?;
+                    *random.lock().unwrap() = Random::new(seed as u64);
+                    Ok(Value::Empty)
+                }),
+            )
+            .unwrap();
+
+        let random = self.random.clone();
+        self.context
+            .set_function(
+                "rand".to_string(),
+                Function::new(move |args| {
+                    let value = random.lock().unwrap().next_f64();
+                    match args {
+                        Value::Empty => Ok(Value::Float(value)),
+                        Value::Tuple(tuple) if tuple.len() == 2 => {
+                            let low = tuple[0].as_number()?;
+                            let high = tuple[1].as_number()?;
+                            Ok(Value::Float(low + value * (high - low)))
+                        }
+                        args => Err(EvalexprError::wrong_function_argument_amount(
+                            argument_count(args),
+                            0,
+                        )),
+                    }
+                }),
+            )
+            .unwrap();
+
+        let random = self.random.clone();
+        self.context
+            .set_function(
+                "randint".to_string(),
+                Function::new(move |args| {
+                    let tuple = args.as_fixed_len_tuple(2)?;
+                    let low = tuple[0].as_int()?;
+                    let high = tuple[1].as_int()?;
+
+                    if low > high {
+                        return Err(EvalexprError::CustomMessage(format!(
+                            "randint({}, {}) has an empty range",
+                            low, high
+                        )));
+                    }
+
+                    Ok(Value::Int(random.lock().unwrap().next_int(low, high)))
+                }),
+            )
+            .unwrap();
+
+        let random = self.random.clone();
+        self.context
+            .set_function(
+                "randn".to_string(),
+                Function::new(move |args| {
+                    let value = random.lock().unwrap().next_normal();
+                    match args {
+                        Value::Empty => Ok(Value::Float(value)),
+                        Value::Tuple(tuple) if tuple.len() == 2 => {
+                            let mu = tuple[0].as_number()?;
+                            let sigma = tuple[1].as_number()?;
+                            Ok(Value::Float(mu + sigma * value))
+                        }
+                        args => Err(EvalexprError::wrong_function_argument_amount(
+                            argument_count(args),
+                            0,
+                        )),
+                    }
+                }),
+            )
+            .unwrap();
+    }
+
+    /// `sample(dist, n)`, a list of `n` numbers drawn from a distribution such as
+    /// `normal(0, 1)`, `binomial(10, 0.5)` or `poisson(3)`.
+    pub(super) fn eval_sample(
+        &mut self,
+        arguments: &[String],
+        bindings: &[(String, Value)],
+    ) -> Result<Value, Box<dyn Error>> {
+        expect_arguments(arguments, 2)?;
+
+        let distribution = arguments[0].trim();
+        let (name, parameters) = match distribution.split_once('(') {
+            Some((name, rest)) => (
+                name.trim(),
+                split_arguments(rest.strip_suffix(')').ok_or(ReplError::InvalidArgument)?),
+            ),
+            None => (distribution, Vec::new()),
+        };
+
+        let quantile = distribution_quantile(name).ok_or_else(|| {
+            ReplError::Unsupported(format!("sampling from the {} distribution", name))
+        })?;
+        let parameters = parameters
+            .iter()
+            .map(|parameter| self.evaluate_number(parameter, bindings))
+            .collect::<Result<Vec<f64>, Box<dyn Error>>>()?;
+        let count = self.evaluate(&arguments[1], bindings)?.as_int()?;
+
+        if count < 0 {
+            return Err(Box::new(ReplError::InvalidArgument));
+        }
+
+        // Inverse transform sampling: the quantile of a uniform number.
+        let mut items = Vec::new();
+        let mut arguments = vec![0.0];
+        arguments.extend(parameters);
+
+        for _ in 0..count {
+            self.budget.allocate(std::mem::size_of::<ReplResult>())?;
+            arguments[0] = self.random.lock().unwrap().next_open_f64();
+            let value = quantile(&arguments).ok_or_else(|| {
+                EvalexprError::CustomMessage(format!(
+                    "{} is not defined for these parameters",
+                    distribution
+                ))
+            })?;
+            items.push(ReplResult::Number(value));
+        }
+
+        Ok(Value::String(serialize_list(&Array { items })))
+    }
+}
+
+fn argument_count(args: &Value) -> usize {
+    match args {
+        Value::Empty => 0,
+        Value::Tuple(tuple) => tuple.len(),
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DRAWS: [&str; 5] = [
+        "rand()",
+        "rand(2, 3)",
+        "randint(1, 6)",
+        "randn()",
+        "sample(normal(0, 1), 3)",
+    ];
+
+    fn draws(repl: &mut Repl, seed: u64) -> Vec<String> {
+        repl.process_input(&format!("seed({})", seed)).unwrap();
+        DRAWS
+            .iter()
+            .map(|input| format!("{:?}", repl.process_input(input).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn seeded_draws_repeat() {
+        let first = draws(&mut Repl::new(), 42);
+        assert_eq!(draws(&mut Repl::new(), 42), first);
+        assert_ne!(draws(&mut Repl::new(), 43), first);
+
+        let mut repl = Repl::new();
+        assert_eq!(draws(&mut repl, 42), first);
+        assert_eq!(draws(&mut repl, 42), first);
+    }
+
+    #[test]
+    fn seeded_draws_repeat_after_reset() {
+        let mut repl = Repl::new();
+        let first = draws(&mut repl, 7);
+        repl.reset();
+        assert_eq!(draws(&mut repl, 7), first);
+    }
+
+    #[test]
+    fn draws_stay_in_range() {
+        let mut random = Random::new(1);
+        for _ in 0..1000 {
+            assert!((0.0..1.0).contains(&random.next_f64()));
+            assert!((-2..=2).contains(&random.next_int(-2, 2)));
+            let open = random.next_open_f64();
+            assert!(open > 0.0 && open < 1.0);
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use super::forms::bound_variable;
+use super::forms::expect_arguments;
+use super::forms::is_infinity;
+use super::forms::value_to_number;
+use super::forms::Body;
+use super::serialize_list;
+use super::Array;
+use super::Repl;
+use super::ReplError;
+use super::ReplResult;
+use evalexpr::Value;
+use std::error::Error;
+
+/// Infinite series give up after 2^20 terms.
+const MAX_SERIES_TERMS: u64 = 1 << 20;
+
+/// Partial sums are extrapolated with at most this many Richardson steps.
+const MAX_RICHARDSON_STEPS: usize = 8;
+
+impl Repl {
+    /// `sum(k, a, b, expr)`, where `b` may be `inf`.
+    pub(super) fn eval_sum(
+        &mut self,
+        arguments: &[String],
+        bindings: &[(String, Value)],
+    ) -> Result<Value, Box<dyn Error>> {
+        self.eval_series(arguments, bindings, 0.0, |total, term| total + term)
+    }
+
+    /// `prod(k, a, b, expr)`, where `b` may be `inf`.
+    pub(super) fn eval_prod(
+        &mut self,
+        arguments: &[String],
+        bindings: &[(String, Value)],
+    ) -> Result<Value, Box<dyn Error>> {
+        self.eval_series(arguments, bindings, 1.0, |total, term| total * term)
+    }
+
+    /// `seq(expr, k, a, b)` or `seq(expr, k, a, b, step)`, producing a list.
+    pub(super) fn eval_seq(
+        &mut self,
+        arguments: &[String],
+        bindings: &[(String, Value)],
+    ) -> Result<Value, Box<dyn Error>> {
+        if arguments.len() != 5 {
+            expect_arguments(arguments, 4)?;
+        }
+
+        let variable = bound_variable(&arguments[1])?;
+        let start = self.evaluate_number(&arguments[2], bindings)?;
+        let end = self.evaluate_number(&arguments[3], bindings)?;
+        let step = match arguments.get(4) {
+            Some(step) => self.evaluate_number(step, bindings)?,
+            None => 1.0,
+        };
+
+        if step == 0.0 || !step.is_finite() {
+            return Err(Box::new(ReplError::InvalidArgument));
+        }
+
+        let body = self.compile_body(&arguments[0])?;
+        let mut items = Vec::new();
+        let mut k = start;
+
+        while (step > 0.0 && k <= end) || (step < 0.0 && k >= end) {
+            let value = self.evaluate_term(&body, bindings, &variable, k)?;
+            self.budget.allocate(std::mem::size_of::<ReplResult>())?;
+            items.push(self.value_to_result(value)?);
+            k += step;
+        }
+
+        Ok(Value::String(serialize_list(&Array { items })))
+    }
+
+    fn eval_series(
+        &mut self,
+        arguments: &[String],
+        bindings: &[(String, Value)],
+        identity: f64,
+        combine: fn(f64, f64) -> f64,
+    ) -> Result<Value, Box<dyn Error>> {
+        expect_arguments(arguments, 4)?;
+
+        let variable = bound_variable(&arguments[0])?;
+        let start = self.evaluate_number(&arguments[1], bindings)?;
+        let body = self.compile_body(&arguments[3])?;
+
+        if is_infinity(&arguments[2]) {
+            return self
+                .eval_infinite_series(&body, bindings, &variable, start, identity, combine)
+                .map(Value::Float);
+        }
+
+        let end = self.evaluate_number(&arguments[2], bindings)?;
+        let mut total = identity;
+        let mut k = start;
+
+        while k <= end {
+            let term = value_to_number(self.evaluate_term(&body, bindings, &variable, k)?)?;
+            total = combine(total, term);
+            k += 1.0;
+        }
+
+        Ok(Value::Float(total))
+    }
+
+    // Terms are accumulated until they stop changing the total. Slowly
+    // converging series are instead extrapolated from the partial results after
+    // 16, 32, 64, ... terms, which behave like a power series in 1/n. The
+    // partial results one term earlier are extrapolated alike, and the two must
+    // agree, so that the partial sums of an oscillating series such as
+    // `(-1)^k`, which alternate between two values, are not taken for a limit.
+    fn eval_infinite_series(
+        &mut self,
+        body: &Body,
+        bindings: &[(String, Value)],
+        variable: &str,
+        start: f64,
+        identity: f64,
+        combine: fn(f64, f64) -> f64,
+    ) -> Result<f64, Box<dyn Error>> {
+        let mut total = identity;
+        let mut terms = 0;
+        let mut unchanged_terms = 0;
+        let mut next_checkpoint = 16;
+        let mut even = Extrapolation::default();
+        let mut odd = Extrapolation::default();
+        let mut previous_change = f64::INFINITY;
+
+        while terms < MAX_SERIES_TERMS {
+            let k = start + terms as f64;
+            let term = value_to_number(self.evaluate_term(body, bindings, variable, k)?)?;
+            let next_total = combine(total, term);
+
+            if !next_total.is_finite() {
+                return Err(Box::new(ReplError::DidNotConverge));
+            }
+
+            let change = (next_total - total).abs();
+            if change <= f64::EPSILON * next_total.abs() {
+                unchanged_terms += 1;
+            } else {
+                unchanged_terms = 0;
+            }
+
+            terms += 1;
+
+            if unchanged_terms >= 20 {
+                return Ok(next_total);
+            }
+
+            if terms == next_checkpoint {
+                let odd_estimate = odd.push((terms - 1) as f64, total);
+                let even_estimate = even.push(terms as f64, next_total);
+
+                // The terms must tend to zero, shrinking from one checkpoint to
+                // the next, and both extrapolations must settle on one value.
+                if let (Some(even_estimate), Some(odd_estimate)) = (even_estimate, odd_estimate) {
+                    let agree = (even_estimate - odd_estimate).abs()
+                        <= 1e-10 * even_estimate.abs().max(1.0);
+                    if change < previous_change && agree {
+                        return Ok(even_estimate);
+                    }
+                }
+
+                previous_change = change;
+                next_checkpoint *= 2;
+            }
+
+            total = next_total;
+        }
+
+        Err(Box::new(ReplError::DidNotConverge))
+    }
+
+    fn evaluate_term(
+        &mut self,
+        body: &Body,
+        bindings: &[(String, Value)],
+        variable: &str,
+        k: f64,
+    ) -> Result<Value, Box<dyn Error>> {
+        let mut bindings = bindings.to_vec();
+        bindings.push((variable.to_string(), Value::Float(k)));
+        self.evaluate_body(body, &bindings)
+    }
+}
+
+/// Richardson extrapolation of partial results to infinitely many terms.
+#[derive(Default)]
+struct Extrapolation {
+    counts: Vec<f64>,
+    row: Vec<f64>,
+}
+
+impl Extrapolation {
+    // Add the partial result after `count` terms. Returns the extrapolated
+    // limit once it has taken enough steps and stopped changing.
+    fn push(&mut self, count: f64, total: f64) -> Option<f64> {
+        let mut row = vec![total];
+        for step in 1..=self.row.len().min(MAX_RICHARDSON_STEPS) {
+            let ratio = count / self.counts[self.counts.len() - step];
+            row.push(row[step - 1] + (row[step - 1] - self.row[step - 1]) / (ratio - 1.0));
+        }
+
+        let estimate = *row.last().unwrap();
+        let previous_estimate = self.row.last().copied().unwrap_or(f64::NAN);
+        self.counts.push(count);
+        self.row = row;
+
+        let is_settled = self.row.len() > 4
+            && (estimate - previous_estimate).abs() <= 1e-10 * estimate.abs().max(1.0);
+        is_settled.then_some(estimate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::LN_2;
+    use std::f64::consts::PI;
+
+    fn number(repl: &mut Repl, input: &str) -> f64 {
+        match repl.process_input(input).unwrap() {
+            ReplResult::Number(value) => value,
+            result => panic!("{} gave {:?}", input, result),
+        }
+    }
+
+    #[test]
+    fn finite_sums_and_products() {
+        let mut repl = Repl::new();
+        assert_eq!(number(&mut repl, "sum(k, 1, 100, k)"), 5050.0);
+        assert_eq!(number(&mut repl, "prod(k, 1, 5, k)"), 120.0);
+    }
+
+    #[test]
+    fn infinite_series_converge_to_known_limits() {
+        let mut repl = Repl::new();
+        let basel = number(&mut repl, "sum(k, 1, inf, 1/k^2)");
+        assert!((basel - PI * PI / 6.0).abs() < 1e-9);
+
+        let alternating = number(&mut repl, "sum(k, 1, inf, (-1)^(k + 1)/k)");
+        assert!((alternating - LN_2).abs() < 1e-9);
+
+        let wallis = number(&mut repl, "prod(k, 2, inf, 1 - 1/k^2)");
+        assert!((wallis - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn divergent_series_is_an_error() {
+        let mut repl = Repl::new();
+        assert!(repl.process_input("sum(k, 1, inf, 1/k)").is_err());
+        assert!(repl.process_input("sum(k, 0, inf, (-1)^k)").is_err());
+    }
+
+    #[test]
+    fn seq_lists_the_terms() {
+        let mut repl = Repl::new();
+        match repl.process_input("seq(k^2, k, 1, 4)").unwrap() {
+            ReplResult::List(list) => {
+                let terms: Vec<String> = list
+                    .items
+                    .iter()
+                    .map(|item| format!("{:?}", item))
+                    .collect();
+                assert_eq!(
+                    terms,
+                    ["Number(1.0)", "Number(4.0)", "Number(9.0)", "Number(16.0)"]
+                );
+            }
+            result => panic!("seq gave {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,346 @@
+use super::budget::Budget;
+use super::parse_list;
+use super::serialize_list;
+use super::Array;
+use super::Point;
+use super::Repl;
+use super::ReplResult;
+use evalexpr::ContextWithMutableFunctions;
+use evalexpr::EvalexprError;
+use evalexpr::EvalexprResult;
+use evalexpr::Function;
+use evalexpr::Value;
+use std::error::Error;
+use std::f64::consts::PI;
+
+/// A complex sample as its real and imaginary parts. Lists of them are written as
+/// lists of points, so `fft(List(1, 0, 0, 0))` reads back as `Point((1, 0)), ...`.
+type Complex = (f64, f64);
+
+/// Imaginary parts below this fraction of the largest sample are rounding error,
+/// and results made of real numbers only are given as plain numbers.
+const SIGNAL_REAL_TOLERANCE: f64 = 1e-12;
+
+/// A window of `n` samples, given the index and the length.
+type Window = fn(f64, f64) -> f64;
+
+impl Repl {
+    pub(super) fn setup_signal_functions(&mut self) {
+        let budget = self.budget.clone();
+        self.context
+            .set_function(
+                "fft".to_string(),
+                Function::new(move |args| Ok(complex_list(&dft(&signal(args)?, false, &budget)?))),
+            )
+            .unwrap();
+
+        let budget = self.budget.clone();
+        self.context
+            .set_function(
+                "ifft".to_string(),
+                Function::new(move |args| Ok(complex_list(&dft(&signal(args)?, true, &budget)?))),
+            )
+            .unwrap();
+
+        self.context
+            .set_function(
+                "magnitude".to_string(),
+                Function::new(|args| {
+                    let values = signal(args)?;
+                    Ok(number_list(values.iter().map(|(re, im)| re.hypot(*im))))
+                }),
+            )
+            .unwrap();
+
+        self.context
+            .set_function(
+                "phase".to_string(),
+                Function::new(|args| {
+                    let values = signal(args)?;
+                    Ok(number_list(values.iter().map(|(re, im)| im.atan2(*re))))
+                }),
+            )
+            .unwrap();
+
+        let budget = self.budget.clone();
+        self.context
+            .set_function(
+                "convolve".to_string(),
+                Function::new(move |args| {
+                    let tuple = args.as_fixed_len_tuple(2)?;
+                    let a = signal(&tuple[0])?;
+                    let b = signal(&tuple[1])?;
+
+                    if a.is_empty() || b.is_empty() {
+                        return Ok(number_list(std::iter::empty()));
+                    }
+
+                    let length = a.len() + b.len() - 1;
+                    budget.allocate(length * std::mem::size_of::<Complex>())?;
+                    let mut result = vec![(0.0, 0.0); length];
+                    for (i, x) in a.iter().enumerate() {
+                        budget.check()?;
+                        for (j, y) in b.iter().enumerate() {
+                            let (re, im) = multiply(*x, *y);
+                            result[i + j].0 += re;
+                            result[i + j].1 += im;
+                        }
+                    }
+
+                    Ok(complex_list(&result))
+                }),
+            )
+            .unwrap();
+
+        let windows: [(&str, Window); 3] = [
+            ("hann", |k, n| 0.5 - 0.5 * (2.0 * PI * k / n).cos()),
+            ("hamming", |k, n| 0.54 - 0.46 * (2.0 * PI * k / n).cos()),
+            ("blackman", |k, n| {
+                0.42 - 0.5 * (2.0 * PI * k / n).cos() + 0.08 * (4.0 * PI * k / n).cos()
+            }),
+        ];
+
+        for (name, window) in windows {
+            self.context
+                .set_function(
+                    name.to_string(),
+                    Function::new(move |args| {
+                        let values = signal(args)?;
+                        // The symmetric window, which is zero at both ends.
+                        let n = (values.len().max(2) - 1) as f64;
+                        let windowed: Vec<Complex> = values
+                            .iter()
+                            .enumerate()
+                            .map(|(k, (re, im))| {
+                                let weight = if values.len() == 1 {
+                                    1.0
+                                } else {
+                                    window(k as f64, n)
+                                };
+                                (re * weight, im * weight)
+                            })
+                            .collect();
+
+                        Ok(complex_list(&windowed))
+                    }),
+                )
+                .unwrap();
+        }
+    }
+
+    /// `draw_spectrum(list)`, the magnitude of the discrete Fourier transform of a
+    /// signal as points `(k, |X_k|)`, for the graph to draw as stems.
+    pub fn spectrum_points(&mut self, input: &str) -> Result<Vec<Point>, Box<dyn Error>> {
+        self.sync_globals();
+
+        self.within_limits(|repl| {
+            let value = repl.evaluate(input, &[])?;
+
+            Ok(dft(&signal(&value)?, false, &repl.budget)?
+                .iter()
+                .enumerate()
+                .map(|(k, (re, im))| Point {
+                    x: k as f64,
+                    y: re.hypot(*im),
+                })
+                .collect())
+        })
+    }
+}
+
+// Read a signal, a list of numbers or of points standing for complex numbers.
+fn signal(value: &Value) -> EvalexprResult<Vec<Complex>> {
+    let not_a_signal =
+        || EvalexprError::CustomMessage("expected a list of numbers or points".to_string());
+
+    match value {
+        Value::String(value) => parse_list(value)
+            .ok_or_else(not_a_signal)?
+            .items
+            .into_iter()
+            .map(|item| match item {
+                ReplResult::Number(value) => Ok((value, 0.0)),
+                ReplResult::Point(point) => Ok((point.x, point.y)),
+                _ => Err(not_a_signal()),
+            })
+            .collect(),
+        Value::Tuple(values) => values
+            .iter()
+            .map(|value| match value {
+                Value::Tuple(pair) if pair.len() == 2 => {
+                    Ok((pair[0].as_number()?, pair[1].as_number()?))
+                }
+                value => Ok((value.as_number()?, 0.0)),
+            })
+            .collect(),
+        _ => Err(not_a_signal()),
+    }
+}
+
+// A list of numbers when every imaginary part is negligible, of points otherwise.
+fn complex_list(values: &[Complex]) -> Value {
+    let scale = values
+        .iter()
+        .map(|(re, im)| re.hypot(*im))
+        .fold(0.0, f64::max);
+    let is_real = values
+        .iter()
+        .all(|(_, im)| im.abs() <= SIGNAL_REAL_TOLERANCE * scale);
+
+    if is_real {
+        return number_list(values.iter().map(|(re, _)| *re));
+    }
+
+    Value::String(serialize_list(&Array {
+        items: values
+            .iter()
+            .map(|(x, y)| ReplResult::Point(Point { x: *x, y: *y }))
+            .collect(),
+    }))
+}
+
+fn number_list(values: impl Iterator<Item = f64>) -> Value {
+    Value::String(serialize_list(&Array {
+        items: values.map(ReplResult::Number).collect(),
+    }))
+}
+
+fn multiply(a: Complex, b: Complex) -> Complex {
+    (a.0 * b.0 - a.1 * b.1, a.0 * b.1 + a.1 * b.0)
+}
+
+// The discrete Fourier transform, or its inverse scaled by `1/n`. Lengths that
+// are powers of two use the radix-2 FFT, others the direct sum, which takes
+// long enough on long signals to check the budget as it goes.
+fn dft(values: &[Complex], inverse: bool, budget: &Budget) -> EvalexprResult<Vec<Complex>> {
+    let n = values.len();
+    let sign = if inverse { 1.0 } else { -1.0 };
+    budget.allocate(std::mem::size_of_val(values))?;
+
+    let mut result = if n.is_power_of_two() {
+        fft_radix2(values, sign)
+    } else {
+        (0..n)
+            .map(|k| {
+                budget.check()?;
+                Ok(values.iter().enumerate().fold((0.0, 0.0), |sum, (j, x)| {
+                    let angle = sign * 2.0 * PI * ((j * k) % n) as f64 / n as f64;
+                    let (re, im) = multiply(*x, (angle.cos(), angle.sin()));
+                    (sum.0 + re, sum.1 + im)
+                }))
+            })
+            .collect::<EvalexprResult<Vec<Complex>>>()?
+    };
+
+    if inverse {
+        for value in &mut result {
+            value.0 /= n as f64;
+            value.1 /= n as f64;
+        }
+    }
+
+    Ok(result)
+}
+
+// Iterative Cooley–Tukey: reorder by bit reversal, then combine butterflies.
+fn fft_radix2(values: &[Complex], sign: f64) -> Vec<Complex> {
+    let n = values.len();
+    let bits = n.trailing_zeros();
+    let mut result = vec![(0.0, 0.0); n];
+
+    for (index, value) in values.iter().enumerate() {
+        let reversed = if bits == 0 {
+            0
+        } else {
+            index.reverse_bits() >> (usize::BITS - bits)
+        };
+        result[reversed] = *value;
+    }
+
+    let mut length = 2;
+    while length <= n {
+        let angle = sign * 2.0 * PI / length as f64;
+        for chunk in result.chunks_mut(length) {
+            let (even, odd) = chunk.split_at_mut(length / 2);
+            for (k, (a, b)) in even.iter_mut().zip(odd.iter_mut()).enumerate() {
+                let twiddle = ((angle * k as f64).cos(), (angle * k as f64).sin());
+                let t = multiply(twiddle, *b);
+                *b = (a.0 - t.0, a.1 - t.1);
+                *a = (a.0 + t.0, a.1 + t.1);
+            }
+        }
+        length *= 2;
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: &[Complex], expected: &[Complex]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!(
+                (a.0 - e.0).abs() < 1e-12 && (a.1 - e.1).abs() < 1e-12,
+                "{:?} is not {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn transform_of_an_impulse_is_flat() {
+        let budget = Budget::default();
+        let impulse = [(1.0, 0.0), (0.0, 0.0), (0.0, 0.0), (0.0, 0.0)];
+        assert_close(&dft(&impulse, false, &budget).unwrap(), &[(1.0, 0.0); 4]);
+        assert_close(
+            &dft(&impulse[..3], false, &budget).unwrap(),
+            &[(1.0, 0.0); 3],
+        );
+    }
+
+    #[test]
+    fn transform_of_a_cosine_has_two_peaks() {
+        let cosine = [(1.0, 0.0), (0.0, 0.0), (-1.0, 0.0), (0.0, 0.0)];
+        assert_close(
+            &dft(&cosine, false, &Budget::default()).unwrap(),
+            &[(0.0, 0.0), (2.0, 0.0), (0.0, 0.0), (2.0, 0.0)],
+        );
+    }
+
+    #[test]
+    fn inverse_transform_round_trips() {
+        let budget = Budget::default();
+        for length in [5, 8] {
+            let values: Vec<Complex> = (0..length)
+                .map(|k| (k as f64, (k * k) as f64 / 3.0))
+                .collect();
+            let spectrum = dft(&values, false, &budget).unwrap();
+            assert_close(&dft(&spectrum, true, &budget).unwrap(), &values);
+        }
+    }
+
+    #[test]
+    fn convolution_multiplies_polynomials() {
+        let mut repl = Repl::new();
+        // (1 + x)(1 + 2x + x^2) = 1 + 3x + 3x^2 + x^3
+        match repl.process_input("convolve((1, 1), (1, 2, 1))").unwrap() {
+            ReplResult::List(list) => assert_eq!(
+                format!("{:?}", list.items),
+                "[Number(1.0), Number(3.0), Number(3.0), Number(1.0)]"
+            ),
+            result => panic!("convolve gave {:?}", result),
+        }
+    }
+
+    #[test]
+    fn spectrum_points_are_magnitudes() {
+        let mut repl = Repl::new();
+        let points = repl.spectrum_points("(1, 0, -1, 0)").unwrap();
+        let magnitudes: Vec<f64> = points.iter().map(|point| point.y.round()).collect();
+        assert_eq!(magnitudes, [0.0, 2.0, 0.0, 2.0]);
+    }
+}
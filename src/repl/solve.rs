@@ -0,0 +1,485 @@
+use super::forms::bound_variable;
+use super::forms::find_closing_paren;
+use super::forms::split_arguments;
+use super::forms::value_to_number;
+use super::forms::Body;
+use super::Repl;
+use super::ReplError;
+use evalexpr::build_operator_tree;
+use evalexpr::ContextWithMutableVariables;
+use evalexpr::EvalexprError;
+use evalexpr::Node;
+use evalexpr::Value;
+use std::error::Error;
+
+/// Newton's method gives up after this many iterations.
+const NSOLVE_MAX_ITERATIONS: usize = 100;
+
+/// A solution is accepted once the residuals are this small.
+const NSOLVE_TOLERANCE: f64 = 1e-10;
+
+/// The solution of a system of equations, one value per unknown.
+pub struct Solution {
+    pub names: Vec<String>,
+    pub values: Vec<f64>,
+}
+
+impl Solution {
+    /// A single unknown as a number, several as a tuple, which makes the
+    /// solution of a system in two unknowns a point.
+    pub fn to_value(&self) -> Value {
+        match self.values.as_slice() {
+            [value] => Value::Float(*value),
+            values => Value::Tuple(values.iter().map(|value| Value::Float(*value)).collect()),
+        }
+    }
+
+    pub fn bindings(&self) -> Vec<(String, f64)> {
+        self.names
+            .iter()
+            .cloned()
+            .zip(self.values.clone())
+            .collect()
+    }
+}
+
+impl Repl {
+    /// `linsolve(A, b)` with `A` written as a tuple of rows, or
+    /// `linsolve((eq1, eq2), (x, y))` for linear equations in named unknowns.
+    /// A trailing `assign` argument also assigns the solution in the context.
+    pub(super) fn eval_linsolve(
+        &mut self,
+        arguments: &[String],
+        bindings: &[(String, Value)],
+    ) -> Result<Solution, Box<dyn Error>> {
+        let (arguments, assign) = take_assign_flag(arguments);
+        if arguments.len() != 2 {
+            return Err(Box::new(EvalexprError::wrong_function_argument_amount(
+                arguments.len(),
+                2,
+            )));
+        }
+
+        let sources = tuple_sources(&arguments[0]);
+        let solution = if sources
+            .iter()
+            .any(|source| split_equation(source).is_some())
+        {
+            self.solve_linear_equations(&sources, &arguments[1], bindings)?
+        } else {
+            let matrix = match self.evaluate(&arguments[0], bindings)? {
+                Value::Tuple(rows) => rows.into_iter().map(tuple_numbers).collect(),
+                value => tuple_numbers(value).map(|row| vec![row]),
+            }?;
+            let rhs = tuple_numbers(self.evaluate(&arguments[1], bindings)?)?;
+
+            Solution {
+                names: (1..=rhs.len()).map(|index| format!("x{}", index)).collect(),
+                values: solve_linear(matrix, rhs)?,
+            }
+        };
+
+        if assign {
+            self.assign_solution(&solution)?;
+        }
+
+        Ok(solution)
+    }
+
+    /// `nsolve((eq1, eq2), (x, y), (x0, y0))`, solving nonlinear equations with
+    /// Newton's method from a starting point. A trailing `assign` argument also
+    /// assigns the solution in the context.
+    pub(super) fn eval_nsolve(
+        &mut self,
+        arguments: &[String],
+        bindings: &[(String, Value)],
+    ) -> Result<Solution, Box<dyn Error>> {
+        let (arguments, assign) = take_assign_flag(arguments);
+        if arguments.len() != 3 {
+            return Err(Box::new(EvalexprError::wrong_function_argument_amount(
+                arguments.len(),
+                3,
+            )));
+        }
+
+        let residuals = self.compile_residuals(&tuple_sources(&arguments[0]))?;
+        let names = tuple_sources(&arguments[1])
+            .iter()
+            .map(|name| bound_variable(name))
+            .collect::<Result<Vec<String>, Box<dyn Error>>>()?;
+        let mut x = tuple_numbers(self.evaluate(&arguments[2], bindings)?)?;
+
+        if names.len() != residuals.len() || x.len() != names.len() {
+            return Err(Box::new(ReplError::InvalidArgument));
+        }
+
+        let mut f = self.evaluate_residuals(&residuals, bindings, &names, &x)?;
+
+        for _ in 0..NSOLVE_MAX_ITERATIONS {
+            // The Jacobian by central differences.
+            let mut jacobian = vec![vec![0.0; x.len()]; x.len()];
+            for column in 0..x.len() {
+                let h = 1e-7 * x[column].abs().max(1.0);
+                let mut forward = x.clone();
+                let mut backward = x.clone();
+                forward[column] += h;
+                backward[column] -= h;
+
+                let f_forward = self.evaluate_residuals(&residuals, bindings, &names, &forward)?;
+                let f_backward =
+                    self.evaluate_residuals(&residuals, bindings, &names, &backward)?;
+                for row in 0..x.len() {
+                    jacobian[row][column] = (f_forward[row] - f_backward[row]) / (2.0 * h);
+                }
+            }
+
+            let step = solve_linear(jacobian, f.iter().map(|value| -value).collect())?;
+
+            // Halve the step until it reduces the residuals, so that starting
+            // points far from the solution do not send the iteration astray.
+            let mut scale = 1.0;
+            loop {
+                let candidate: Vec<f64> = x.iter().zip(&step).map(|(x, s)| x + scale * s).collect();
+                let f_candidate =
+                    self.evaluate_residuals(&residuals, bindings, &names, &candidate)?;
+
+                if norm(&f_candidate) < norm(&f) || scale < 1e-4 {
+                    x = candidate;
+                    f = f_candidate;
+                    break;
+                }
+
+                scale /= 2.0;
+            }
+
+            if x.iter().chain(&f).any(|value| !value.is_finite()) {
+                break;
+            }
+
+            // Stop once the residuals vanish and Newton's method has settled,
+            // which leaves the last digits accurate as well.
+            if norm(&f) <= NSOLVE_TOLERANCE && scale * norm(&step) <= 1e-10 * norm(&x).max(1.0) {
+                let solution = Solution { names, values: x };
+                if assign {
+                    self.assign_solution(&solution)?;
+                }
+                return Ok(solution);
+            }
+        }
+
+        Err(Box::new(ReplError::DidNotConverge))
+    }
+
+    /// If the whole input is a call to `linsolve`, `nsolve`, `minimize` or
+    /// `maximize`, its solution.
+    pub(super) fn solver_form(&mut self, input: &str) -> Result<Option<Solution>, Box<dyn Error>> {
+        match self.whole_form(input) {
+            Some((name, arguments)) if name == "linsolve" => {
+                Ok(Some(self.eval_linsolve(&arguments, &[])?))
+            }
+            Some((name, arguments)) if name == "nsolve" => {
+                Ok(Some(self.eval_nsolve(&arguments, &[])?))
+            }
+            Some((name, arguments)) if name == "minimize" || name == "maximize" => Ok(Some(
+                self.eval_optimum(&arguments, &[], name == "maximize")?
+                    .into(),
+            )),
+            _ => Ok(None),
+        }
+    }
+
+    /// The equations to draw for an input: the input itself if it is an
+    /// equation such as `x^2 + y^2 = 4`, or the equations passed to a solver.
+    /// Each is drawn as the curve where its residual is zero.
+    pub fn plot_equations(&mut self, input: &str) -> Result<Vec<Node>, Box<dyn Error>> {
+        let normalized = self.normalize(input)?;
+        let input = normalized.trim();
+        self.sync_globals();
+
+        let sources = if split_equation(input).is_some() {
+            vec![input.to_string()]
+        } else {
+            match self.whole_form(input) {
+                Some((name, arguments))
+                    if ["linsolve", "nsolve", "solve_exact"].contains(&name.as_str())
+                        && !arguments.is_empty() =>
+                {
+                    tuple_sources(&arguments[0])
+                        .into_iter()
+                        .filter(|source| split_equation(source).is_some())
+                        .collect()
+                }
+                _ => Vec::new(),
+            }
+        };
+
+        sources
+            .iter()
+            .map(|source| {
+                let expanded = self.expand_expression_forms(&residual(source))?;
+                Ok(build_operator_tree(&expanded)?)
+            })
+            .collect()
+    }
+
+    // Linear equations are solved by reading off their coefficients: the
+    // residuals at the origin give the constants, and the change when one
+    // unknown is set to 1 gives its column.
+    fn solve_linear_equations(
+        &mut self,
+        sources: &[String],
+        unknowns: &str,
+        bindings: &[(String, Value)],
+    ) -> Result<Solution, Box<dyn Error>> {
+        let residuals = self.compile_residuals(sources)?;
+        let names = tuple_sources(unknowns)
+            .iter()
+            .map(|name| bound_variable(name))
+            .collect::<Result<Vec<String>, Box<dyn Error>>>()?;
+
+        if names.len() != residuals.len() {
+            return Err(Box::new(ReplError::InvalidArgument));
+        }
+
+        let origin = vec![0.0; names.len()];
+        let constants = self.evaluate_residuals(&residuals, bindings, &names, &origin)?;
+        let mut matrix = vec![vec![0.0; names.len()]; names.len()];
+
+        for column in 0..names.len() {
+            let mut unit = origin.clone();
+            unit[column] = 1.0;
+
+            let values = self.evaluate_residuals(&residuals, bindings, &names, &unit)?;
+            for row in 0..names.len() {
+                matrix[row][column] = values[row] - constants[row];
+            }
+        }
+
+        let values = solve_linear(matrix, constants.iter().map(|value| -value).collect())?;
+        let check = self.evaluate_residuals(&residuals, bindings, &names, &values)?;
+        let scale = values
+            .iter()
+            .fold(1.0, |scale: f64, value| scale.max(value.abs()));
+
+        if norm(&check) > 1e-9 * scale {
+            return Err(Box::new(ReplError::Unsupported(
+                "linsolve of nonlinear equations, use nsolve".to_string(),
+            )));
+        }
+
+        Ok(Solution { names, values })
+    }
+
+    fn compile_residuals(&self, sources: &[String]) -> Result<Vec<Body>, Box<dyn Error>> {
+        sources
+            .iter()
+            .map(|source| self.compile_body(&residual(source)))
+            .collect()
+    }
+
+    fn evaluate_residuals(
+        &mut self,
+        residuals: &[Body],
+        bindings: &[(String, Value)],
+        names: &[String],
+        values: &[f64],
+    ) -> Result<Vec<f64>, Box<dyn Error>> {
+        let mut scope = bindings.to_vec();
+        for (name, value) in names.iter().zip(values) {
+            scope.push((name.clone(), Value::Float(*value)));
+        }
+
+        residuals
+            .iter()
+            .map(|residual| value_to_number(self.evaluate_body(residual, &scope)?))
+            .collect()
+    }
+
+    fn assign_solution(&mut self, solution: &Solution) -> Result<(), Box<dyn Error>> {
+        for (name, value) in solution.bindings() {
+            self.context.set_value(name, Value::Float(value))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Split an equation `lhs = rhs` at its single `=`, which must not be part of
+/// a comparison such as `==` or `<=`.
+pub fn split_equation(source: &str) -> Option<(&str, &str)> {
+    let bytes = source.as_bytes();
+    let mut depth = 0;
+    let mut position = None;
+
+    for (index, byte) in bytes.iter().enumerate() {
+        match byte {
+            b'(' => depth += 1,
+            b')' => depth -= 1,
+            b'=' if depth == 0 => {
+                let before = index.checked_sub(1).map(|i| bytes[i]);
+                let after = bytes.get(index + 1).copied();
+                let is_comparison =
+                    matches!(before, Some(b'=' | b'<' | b'>' | b'!')) || after == Some(b'=');
+
+                if !is_comparison {
+                    if position.is_some() {
+                        return None;
+                    }
+                    position = Some(index);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    position.map(|index| (source[..index].trim(), source[index + 1..].trim()))
+}
+
+// An equation `lhs = rhs` becomes `(lhs) - (rhs)`, anything else is taken to
+// be equal to zero already.
+pub fn residual(source: &str) -> String {
+    match split_equation(source) {
+        Some((lhs, rhs)) => format!("({}) - ({})", lhs, rhs),
+        None => source.to_string(),
+    }
+}
+
+// The items of a parenthesised argument such as `(x, y)` or `(x)`, or the
+// argument itself if it is not a tuple. Only parentheses around the whole
+// argument make a tuple, not those of `(x - 1)*(x + 1)`.
+pub fn tuple_sources(argument: &str) -> Vec<String> {
+    let argument = argument.trim();
+    match argument.strip_prefix('(') {
+        Some(inner) if find_closing_paren(inner, 0).map(|end| end + 1) == Some(inner.len()) => {
+            split_arguments(&inner[..inner.len() - 1])
+        }
+        _ => vec![argument.to_string()],
+    }
+}
+
+pub fn tuple_numbers(value: Value) -> Result<Vec<f64>, Box<dyn Error>> {
+    match value {
+        Value::Tuple(values) => values.into_iter().map(value_to_number).collect(),
+        value => Ok(vec![value_to_number(value)?]),
+    }
+}
+
+fn take_assign_flag(arguments: &[String]) -> (&[String], bool) {
+    match arguments.split_last() {
+        Some((last, rest)) if last == "assign" => (rest, true),
+        _ => (arguments, false),
+    }
+}
+
+fn norm(values: &[f64]) -> f64 {
+    values.iter().map(|value| value * value).sum::<f64>().sqrt()
+}
+
+/// Solve a square linear system by Gaussian elimination with partial pivoting.
+pub fn solve_linear(mut matrix: Vec<Vec<f64>>, mut rhs: Vec<f64>) -> Result<Vec<f64>, ReplError> {
+    let n = rhs.len();
+    if n == 0 || matrix.len() != n || matrix.iter().any(|row| row.len() != n) {
+        return Err(ReplError::InvalidArgument);
+    }
+
+    let scale = matrix
+        .iter()
+        .flatten()
+        .fold(0.0, |scale: f64, value| scale.max(value.abs()));
+
+    for column in 0..n {
+        let pivot = (column..n)
+            .max_by(|a, b| {
+                matrix[*a][column]
+                    .abs()
+                    .total_cmp(&matrix[*b][column].abs())
+            })
+            .unwrap();
+
+        if matrix[pivot][column].abs() <= 1e-12 * scale {
+            return Err(ReplError::SingularMatrix);
+        }
+
+        matrix.swap(column, pivot);
+        rhs.swap(column, pivot);
+
+        let (upper, lower) = matrix.split_at_mut(column + 1);
+        let pivot_row = &upper[column];
+        for (offset, row) in lower.iter_mut().enumerate() {
+            let factor = row[column] / pivot_row[column];
+            for (entry, pivot) in row[column..].iter_mut().zip(&pivot_row[column..]) {
+                *entry -= factor * pivot;
+            }
+            rhs[column + 1 + offset] -= factor * rhs[column];
+        }
+    }
+
+    let mut solution = vec![0.0; n];
+    for row in (0..n).rev() {
+        let known: f64 = (row + 1..n).map(|k| matrix[row][k] * solution[k]).sum();
+        solution[row] = (rhs[row] - known) / matrix[row][row];
+    }
+
+    Ok(solution)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repl::ReplResult;
+
+    #[test]
+    fn tuple_sources_strip_only_enclosing_parentheses() {
+        assert_eq!(tuple_sources("(x, y)"), vec!["x", "y"]);
+        assert_eq!(tuple_sources(" (x^2 - 2 = 0) "), vec!["x^2 - 2 = 0"]);
+        assert_eq!(tuple_sources("x"), vec!["x"]);
+        assert_eq!(tuple_sources("(x - 1)*(x + 1)"), vec!["(x - 1)*(x + 1)"]);
+    }
+
+    #[test]
+    fn nsolve_takes_single_unknowns_with_or_without_parentheses() {
+        let mut repl = Repl::new();
+        for input in [
+            "nsolve(x^2 - 2 = 0, x, 1)",
+            "nsolve((x^2 - 2 = 0), (x), (1))",
+        ] {
+            match repl.process_input(input).unwrap() {
+                ReplResult::Bindings(bindings) => {
+                    assert_eq!(bindings[0].0, "x");
+                    assert!((bindings[0].1 - 2f64.sqrt()).abs() < 1e-9);
+                }
+                result => panic!("{} gave {:?}", input, result),
+            }
+        }
+    }
+
+    #[test]
+    fn systems_have_known_solutions() {
+        let mut repl = Repl::new();
+        for (input, expected) in [
+            ("linsolve((2x + 3y = 1, x - y = 2), (x, y))", [1.4, -0.6]),
+            (
+                "nsolve((x^2 + y^2 = 4, x = y), (x, y), (1, 1))",
+                [2f64.sqrt(), 2f64.sqrt()],
+            ),
+        ] {
+            match repl.process_input(input).unwrap() {
+                ReplResult::Bindings(bindings) => {
+                    for ((_, value), expected) in bindings.iter().zip(expected) {
+                        assert!((value - expected).abs() < 1e-9, "{} gave {}", input, value);
+                    }
+                }
+                result => panic!("{} gave {:?}", input, result),
+            }
+        }
+    }
+
+    #[test]
+    fn singular_system_is_an_error() {
+        let mut repl = Repl::new();
+        let error = repl
+            .process_input("linsolve((x + y = 1, 2x + 2y = 2), (x, y))")
+            .unwrap_err();
+        assert_eq!(error.to_string(), ReplError::SingularMatrix.to_string());
+    }
+}
@@ -0,0 +1,479 @@
+use super::ReplError;
+use evalexpr::build_operator_tree;
+use evalexpr::Node;
+use evalexpr::Operator;
+use evalexpr::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fmt;
+use std::ops;
+
+/// A symbolic expression, built from the operator tree evalexpr produces, for
+/// the places where the REPL rewrites expressions instead of evaluating them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    Variable(String),
+    Neg(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    Div(Box<Expr>, Box<Expr>),
+    Pow(Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+impl Expr {
+    pub fn parse(input: &str) -> Result<Expr, Box<dyn Error>> {
+        Expr::from_node(&build_operator_tree(input)?)
+    }
+
+    pub fn from_node(node: &Node) -> Result<Expr, Box<dyn Error>> {
+        let children = node.children();
+        let child = |index: usize| -> Result<Expr, Box<dyn Error>> {
+            match children.get(index) {
+                Some(child) => Expr::from_node(child),
+                None => Err(Box::new(ReplError::InvalidArgument)),
+            }
+        };
+
+        match node.operator() {
+            Operator::RootNode if children.len() == 1 => child(0),
+            Operator::Add => Ok(child(0)? + child(1)?),
+            Operator::Sub => Ok(child(0)? - child(1)?),
+            Operator::Mul => Ok(child(0)? * child(1)?),
+            Operator::Div => Ok(child(0)? / child(1)?),
+            Operator::Exp => Ok(child(0)?.pow(child(1)?)),
+            Operator::Neg => Ok(-child(0)?),
+            Operator::Const {
+                value: Value::Int(value),
+            } => Ok(Expr::Number(*value as f64)),
+            Operator::Const {
+                value: Value::Float(value),
+            } => Ok(Expr::Number(*value)),
+            Operator::VariableIdentifierRead { identifier } => {
+                Ok(Expr::Variable(identifier.clone()))
+            }
+            Operator::FunctionIdentifier { identifier } => {
+                let arguments = match children.first() {
+                    Some(argument) => Expr::tuple_items(argument)?,
+                    None => Vec::new(),
+                };
+                Ok(Expr::Call(identifier.clone(), arguments))
+            }
+            operator => Err(Box::new(ReplError::Unsupported(format!(
+                "operator {}",
+                operator
+            )))),
+        }
+    }
+
+    /// The items of a parenthesised, comma separated list such as `(x, y)`.
+    pub fn tuple_items(node: &Node) -> Result<Vec<Expr>, Box<dyn Error>> {
+        match node.operator() {
+            Operator::RootNode if node.children().is_empty() => Ok(Vec::new()),
+            Operator::RootNode if node.children().len() == 1 => {
+                Expr::tuple_items(&node.children()[0])
+            }
+            Operator::Tuple => node.children().iter().map(Expr::from_node).collect(),
+            _ => Ok(vec![Expr::from_node(node)?]),
+        }
+    }
+
+    pub fn pow(self, exponent: Expr) -> Expr {
+        Expr::Pow(Box::new(self), Box::new(exponent))
+    }
+
+    pub fn call(name: &str, arguments: Vec<Expr>) -> Expr {
+        Expr::Call(name.to_string(), arguments)
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Expr::Number(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, variable: &str) -> bool {
+        match self {
+            Expr::Number(_) => false,
+            Expr::Variable(name) => name == variable,
+            Expr::Neg(u) => u.contains(variable),
+            Expr::Add(u, v)
+            | Expr::Sub(u, v)
+            | Expr::Mul(u, v)
+            | Expr::Div(u, v)
+            | Expr::Pow(u, v) => u.contains(variable) || v.contains(variable),
+            Expr::Call(_, arguments) => arguments.iter().any(|a| a.contains(variable)),
+        }
+    }
+
+    /// The names of the variables in order of first appearance.
+    pub fn variables(&self) -> Vec<String> {
+        let mut variables = Vec::new();
+        self.collect_variables(&mut variables);
+        variables
+    }
+
+    fn collect_variables(&self, variables: &mut Vec<String>) {
+        match self {
+            Expr::Number(_) => {}
+            Expr::Variable(name) => {
+                if !variables.contains(name) {
+                    variables.push(name.clone());
+                }
+            }
+            Expr::Neg(u) => u.collect_variables(variables),
+            Expr::Add(u, v)
+            | Expr::Sub(u, v)
+            | Expr::Mul(u, v)
+            | Expr::Div(u, v)
+            | Expr::Pow(u, v) => {
+                u.collect_variables(variables);
+                v.collect_variables(variables);
+            }
+            Expr::Call(_, arguments) => {
+                for argument in arguments {
+                    argument.collect_variables(variables);
+                }
+            }
+        }
+    }
+
+    /// Replace variables by expressions, all at once.
+    pub fn substitute(&self, values: &HashMap<String, Expr>) -> Expr {
+        let map = |e: &Expr| Box::new(e.substitute(values));
+
+        match self {
+            Expr::Number(_) => self.clone(),
+            Expr::Variable(name) => values.get(name).cloned().unwrap_or_else(|| self.clone()),
+            Expr::Neg(u) => Expr::Neg(map(u)),
+            Expr::Add(u, v) => Expr::Add(map(u), map(v)),
+            Expr::Sub(u, v) => Expr::Sub(map(u), map(v)),
+            Expr::Mul(u, v) => Expr::Mul(map(u), map(v)),
+            Expr::Div(u, v) => Expr::Div(map(u), map(v)),
+            Expr::Pow(u, v) => Expr::Pow(map(u), map(v)),
+            Expr::Call(name, arguments) => Expr::Call(
+                name.clone(),
+                arguments.iter().map(|a| a.substitute(values)).collect(),
+            ),
+        }
+    }
+
+    /// The derivative with respect to `variable`, simplified.
+    pub fn derivative(&self, variable: &str) -> Result<Expr, Box<dyn Error>> {
+        Ok(self.differentiate(variable)?.simplify())
+    }
+
+    fn differentiate(&self, x: &str) -> Result<Expr, Box<dyn Error>> {
+        if !self.contains(x) {
+            return Ok(Expr::Number(0.0));
+        }
+
+        let derivative = match self {
+            Expr::Number(_) => Expr::Number(0.0),
+            Expr::Variable(_) => Expr::Number(1.0),
+            Expr::Neg(u) => -u.differentiate(x)?,
+            Expr::Add(u, v) => u.differentiate(x)? + v.differentiate(x)?,
+            Expr::Sub(u, v) => u.differentiate(x)? - v.differentiate(x)?,
+            Expr::Mul(u, v) => {
+                u.differentiate(x)? * *v.clone() + *u.clone() * v.differentiate(x)?
+            }
+            Expr::Div(u, v) => {
+                (u.differentiate(x)? * *v.clone() - *u.clone() * v.differentiate(x)?)
+                    / v.clone().pow(Expr::Number(2.0))
+            }
+            Expr::Pow(u, v) if !v.contains(x) => {
+                *v.clone() * u.clone().pow(*v.clone() - Expr::Number(1.0)) * u.differentiate(x)?
+            }
+            Expr::Pow(u, v) if !u.contains(x) => {
+                self.clone() * Expr::call("ln", vec![*u.clone()]) * v.differentiate(x)?
+            }
+            Expr::Pow(u, v) => {
+                self.clone()
+                    * (v.differentiate(x)? * Expr::call("ln", vec![*u.clone()])
+                        + *v.clone() * u.differentiate(x)? / *u.clone())
+            }
+            Expr::Call(name, arguments) => {
+                let name = name.strip_prefix("math::").unwrap_or(name);
+
+                if let (Some(u), 1) = (arguments.first(), arguments.len()) {
+                    chain_rule(name, u)? * u.differentiate(x)?
+                } else if let ("log", [u, base]) = (name, arguments.as_slice()) {
+                    if base.contains(x) {
+                        return Err(Box::new(ReplError::Unsupported(
+                            "derivative of log with a variable base".to_string(),
+                        )));
+                    }
+                    u.differentiate(x)? / (u.clone() * Expr::call("ln", vec![base.clone()]))
+                } else {
+                    return Err(Box::new(ReplError::Unsupported(format!(
+                        "derivative of {}",
+                        name
+                    ))));
+                }
+            }
+        };
+
+        Ok(derivative)
+    }
+
+    /// Fold constants and remove identities such as `x*1` and `x + 0`.
+    pub fn simplify(&self) -> Expr {
+        use Expr::*;
+
+        match self {
+            Number(_) | Variable(_) => self.clone(),
+            Neg(u) => match u.simplify() {
+                Number(a) => Number(-a),
+                Neg(inner) => *inner,
+                u => Neg(Box::new(u)),
+            },
+            Add(u, v) => match (u.simplify(), v.simplify()) {
+                (Number(a), Number(b)) => Number(a + b),
+                (Number(0.0), v) => v,
+                (u, Number(0.0)) => u,
+                (u, Number(b)) if b < 0.0 => Sub(Box::new(u), Box::new(Number(-b))),
+                (u, Neg(v)) => Sub(Box::new(u), v),
+                (u, Mul(a, v)) if a.as_number().is_some_and(|a| a < 0.0) => Sub(
+                    Box::new(u),
+                    Box::new(Mul(Box::new(Number(-a.as_number().unwrap())), v).simplify()),
+                ),
+                (u, v) => Add(Box::new(u), Box::new(v)),
+            },
+            Sub(u, v) => match (u.simplify(), v.simplify()) {
+                (Number(a), Number(b)) => Number(a - b),
+                (Number(0.0), v) => Neg(Box::new(v)).simplify(),
+                (u, Number(0.0)) => u,
+                (u, Number(b)) if b < 0.0 => Add(Box::new(u), Box::new(Number(-b))),
+                (u, Neg(v)) => Add(Box::new(u), v),
+                (u, v) if u == v => Number(0.0),
+                (u, v) => Sub(Box::new(u), Box::new(v)),
+            },
+            Mul(u, v) => match (u.simplify(), v.simplify()) {
+                (Number(a), Number(b)) => Number(a * b),
+                (Number(0.0), _) | (_, Number(0.0)) => Number(0.0),
+                (Number(1.0), v) => v,
+                (u, Number(1.0)) => u,
+                (Number(-1.0), v) => Neg(Box::new(v)),
+                (u, Number(-1.0)) => Neg(Box::new(u)),
+                (u, Number(b)) => Mul(Box::new(Number(b)), Box::new(u)).simplify(),
+                (Number(a), Mul(b, v)) if b.as_number().is_some() => {
+                    Mul(Box::new(Number(a * b.as_number().unwrap())), v).simplify()
+                }
+                (Neg(u), Neg(v)) => Mul(u, v).simplify(),
+                (Neg(u), v) | (v, Neg(u)) => Neg(Box::new(Mul(u, Box::new(v)).simplify())),
+                (u, v) if u == v => Pow(Box::new(u), Box::new(Number(2.0))),
+                (u, v) => Mul(Box::new(u), Box::new(v)),
+            },
+            Div(u, v) => match (u.simplify(), v.simplify()) {
+                (Number(a), Number(b)) if b != 0.0 => Number(a / b),
+                (Number(0.0), _) => Number(0.0),
+                (u, Number(1.0)) => u,
+                (u, v) if u == v => Number(1.0),
+                (u, v) => Div(Box::new(u), Box::new(v)),
+            },
+            Pow(u, v) => match (u.simplify(), v.simplify()) {
+                (Number(a), Number(b)) => Number(a.powf(b)),
+                (_, Number(0.0)) => Number(1.0),
+                (u, Number(1.0)) => u,
+                (Number(1.0), _) => Number(1.0),
+                (Pow(u, a), Number(b)) if a.as_number().is_some() => {
+                    Pow(u, Box::new(Number(a.as_number().unwrap() * b)))
+                }
+                (u, v) => Pow(Box::new(u), Box::new(v)),
+            },
+            Call(name, arguments) => {
+                Call(name.clone(), arguments.iter().map(Expr::simplify).collect())
+            }
+        }
+    }
+
+    /// The expression as text evalexpr evaluates correctly, with float literals
+    /// in denominators so that `1/2` does not become an integer division.
+    pub fn to_source(&self) -> String {
+        let mut source = String::new();
+        self.write(&mut source, true).unwrap();
+        source
+    }
+
+    pub(super) fn precedence(&self) -> u8 {
+        match self {
+            Expr::Add(..) | Expr::Sub(..) => 1,
+            Expr::Mul(..) | Expr::Div(..) => 2,
+            Expr::Neg(_) => 3,
+            Expr::Number(value) if *value < 0.0 => 3,
+            Expr::Pow(..) => 4,
+            _ => 5,
+        }
+    }
+
+    fn write(&self, f: &mut impl fmt::Write, source: bool) -> fmt::Result {
+        let operand = |f: &mut dyn fmt::Write, e: &Expr, min: u8| -> fmt::Result {
+            let mut text = String::new();
+            e.write(&mut text, source)?;
+            if e.precedence() < min {
+                write!(f, "({})", text)
+            } else {
+                write!(f, "{}", text)
+            }
+        };
+
+        match self {
+            Expr::Number(value) => write!(f, "{}", format_number(*value)),
+            Expr::Variable(name) => write!(f, "{}", name),
+            Expr::Neg(u) => {
+                write!(f, "-")?;
+                operand(f, u, 4)
+            }
+            Expr::Add(u, v) => {
+                operand(f, u, 1)?;
+                write!(f, " + ")?;
+                operand(f, v, 2)
+            }
+            Expr::Sub(u, v) => {
+                operand(f, u, 1)?;
+                write!(f, " - ")?;
+                operand(f, v, 2)
+            }
+            Expr::Mul(u, v) => {
+                operand(f, u, 2)?;
+                write!(f, "*")?;
+                operand(f, v, 3)
+            }
+            Expr::Div(u, v) => {
+                operand(f, u, 2)?;
+                write!(f, "/")?;
+                match v.as_number() {
+                    Some(value) if source && value.fract() == 0.0 && value > 0.0 => {
+                        write!(f, "{:.1}", value)
+                    }
+                    _ => operand(f, v, 5),
+                }
+            }
+            Expr::Pow(u, v) => {
+                operand(f, u, 5)?;
+                write!(f, "^")?;
+                operand(f, v, 5)
+            }
+            Expr::Call(name, arguments) => {
+                write!(f, "{}(", name)?;
+                for (index, argument) in arguments.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    argument.write(f, source)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, false)
+    }
+}
+
+impl ops::Add for Expr {
+    type Output = Expr;
+
+    fn add(self, rhs: Expr) -> Expr {
+        Expr::Add(Box::new(self), Box::new(rhs))
+    }
+}
+
+impl ops::Sub for Expr {
+    type Output = Expr;
+
+    fn sub(self, rhs: Expr) -> Expr {
+        Expr::Sub(Box::new(self), Box::new(rhs))
+    }
+}
+
+impl ops::Mul for Expr {
+    type Output = Expr;
+
+    fn mul(self, rhs: Expr) -> Expr {
+        Expr::Mul(Box::new(self), Box::new(rhs))
+    }
+}
+
+impl ops::Div for Expr {
+    type Output = Expr;
+
+    fn div(self, rhs: Expr) -> Expr {
+        Expr::Div(Box::new(self), Box::new(rhs))
+    }
+}
+
+impl ops::Neg for Expr {
+    type Output = Expr;
+
+    fn neg(self) -> Expr {
+        Expr::Neg(Box::new(self))
+    }
+}
+
+/// Integers are written without a fractional part, very large and very small
+/// numbers in scientific notation.
+pub fn format_number(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else if value != 0.0 && (value.abs() < 1e-4 || value.abs() >= 1e15) {
+        format!("{:e}", value)
+    } else {
+        format!("{}", value)
+    }
+}
+
+// The derivative of a function of one argument, evaluated at `u`.
+fn chain_rule(name: &str, u: &Expr) -> Result<Expr, Box<dyn Error>> {
+    let one = || Expr::Number(1.0);
+    let two = || Expr::Number(2.0);
+    let f = |name: &str| Expr::call(name, vec![u.clone()]);
+    let square = || u.clone().pow(two());
+
+    let derivative = match name {
+        "sin" => f("cos"),
+        "cos" => -f("sin"),
+        "tan" => f("sec").pow(two()),
+        "cot" => -f("cosec").pow(two()),
+        "sec" => f("sec") * f("tan"),
+        "cosec" => -(f("cosec") * f("cot")),
+        "asin" => one() / Expr::call("sqrt", vec![one() - square()]),
+        "acos" => -(one() / Expr::call("sqrt", vec![one() - square()])),
+        "atan" => one() / (one() + square()),
+        "acot" => -(one() / (one() + square())),
+        "sinh" => f("cosh"),
+        "cosh" => f("sinh"),
+        "tanh" => f("sech").pow(two()),
+        "coth" => -f("cosech").pow(two()),
+        "sech" => -(f("sech") * f("tanh")),
+        "cosech" => -(f("cosech") * f("coth")),
+        "asinh" => one() / Expr::call("sqrt", vec![square() + one()]),
+        "acosh" => one() / Expr::call("sqrt", vec![square() - one()]),
+        "atanh" | "acoth" => one() / (one() - square()),
+        "exp" => f("exp"),
+        "ln" => one() / u.clone(),
+        "sqrt" => one() / (two() * f("sqrt")),
+        "abs" => u.clone() / f("abs"),
+        "gamma" => f("gamma") * f("digamma"),
+        "lgamma" => f("digamma"),
+        "factorial" => f("factorial") * Expr::call("digamma", vec![u.clone() + one()]),
+        "erf" => Expr::Number(2.0 / PI.sqrt()) * Expr::call("exp", vec![-square()]),
+        "erfc" => -(Expr::Number(2.0 / PI.sqrt()) * Expr::call("exp", vec![-square()])),
+        "lambertw" => f("lambertw") / (u.clone() * (one() + f("lambertw"))),
+        _ => {
+            return Err(Box::new(ReplError::Unsupported(format!(
+                "derivative of {}",
+                name
+            ))))
+        }
+    };
+
+    Ok(derivative)
+}
@@ -0,0 +1,621 @@
+use super::forms::expect_arguments;
+use super::solve::split_equation;
+use super::solve::tuple_sources;
+use super::symbolic::format_number;
+use super::symbolic::Expr;
+use super::Repl;
+use super::ReplError;
+use super::ReplResult;
+use evalexpr::Value;
+use std::error::Error;
+
+/// Functions with a name of their own in print, and how LaTeX and MathML
+/// write it.
+const NAMED_FUNCTIONS: &[(&str, &str, &str)] = &[
+    ("sin", "\\sin", "sin"),
+    ("cos", "\\cos", "cos"),
+    ("tan", "\\tan", "tan"),
+    ("sec", "\\sec", "sec"),
+    ("cosec", "\\csc", "csc"),
+    ("cot", "\\cot", "cot"),
+    ("asin", "\\arcsin", "arcsin"),
+    ("acos", "\\arccos", "arccos"),
+    ("atan", "\\arctan", "arctan"),
+    ("sinh", "\\sinh", "sinh"),
+    ("cosh", "\\cosh", "cosh"),
+    ("tanh", "\\tanh", "tanh"),
+    ("coth", "\\coth", "coth"),
+    ("ln", "\\ln", "ln"),
+    ("log", "\\log", "log"),
+    ("gamma", "\\Gamma", "Γ"),
+    ("zeta", "\\zeta", "ζ"),
+];
+
+/// Greek letters that variables may be named after.
+const GREEK_LETTERS: &[(&str, char)] = &[
+    ("alpha", 'α'),
+    ("beta", 'β'),
+    ("gamma", 'γ'),
+    ("delta", 'δ'),
+    ("epsilon", 'ε'),
+    ("eta", 'η'),
+    ("theta", 'θ'),
+    ("iota", 'ι'),
+    ("kappa", 'κ'),
+    ("lambda", 'λ'),
+    ("mu", 'μ'),
+    ("nu", 'ν'),
+    ("xi", 'ξ'),
+    ("rho", 'ρ'),
+    ("sigma", 'σ'),
+    ("tau", 'τ'),
+    ("phi", 'φ'),
+    ("chi", 'χ'),
+    ("psi", 'ψ'),
+    ("omega", 'ω'),
+];
+
+/// The languages results and expressions can be typeset in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Markup {
+    Latex,
+    MathMl,
+}
+
+impl Markup {
+    /// A complete formula: LaTeX as it is, MathML inside a `<math>` element.
+    fn document(self, body: String) -> String {
+        match self {
+            Markup::Latex => body,
+            Markup::MathMl => format!(
+                "<math xmlns=\"http://www.w3.org/1998/Math/MathML\">{}</math>",
+                body
+            ),
+        }
+    }
+
+    fn row(self, parts: &[String]) -> String {
+        match self {
+            Markup::Latex => parts.concat(),
+            Markup::MathMl => format!("<mrow>{}</mrow>", parts.concat()),
+        }
+    }
+
+    fn number(self, value: f64) -> String {
+        let text = format_number(value);
+        let (mantissa, exponent) = match text.split_once('e') {
+            Some((mantissa, exponent)) => (mantissa.to_string(), Some(exponent.to_string())),
+            None => (text, None),
+        };
+
+        let number = |digits: &str| match (self, digits.strip_prefix('-')) {
+            (Markup::Latex, _) => digits.to_string(),
+            (Markup::MathMl, Some(digits)) => format!("<mo>-</mo><mn>{}</mn>", digits),
+            (Markup::MathMl, None) => format!("<mn>{}</mn>", digits),
+        };
+
+        match exponent {
+            Some(exponent) => self.row(&[
+                number(&mantissa),
+                self.operator("×"),
+                self.power(number("10"), number(&exponent)),
+            ]),
+            None if self == Markup::MathMl && mantissa.starts_with('-') => {
+                self.row(&[number(&mantissa)])
+            }
+            None => number(&mantissa),
+        }
+    }
+
+    // A variable, with Greek letters spelled as such and anything after an
+    // underscore as a subscript, e.g. `x_1`.
+    fn identifier(self, name: &str) -> String {
+        let (base, subscript) = match name.split_once('_') {
+            Some((base, subscript)) if !base.is_empty() && !subscript.is_empty() => {
+                (base, Some(subscript))
+            }
+            _ => (name, None),
+        };
+        let greek = GREEK_LETTERS
+            .iter()
+            .find(|(letter, _)| *letter == base)
+            .map(|(_, symbol)| *symbol);
+
+        let base = match (self, greek) {
+            (Markup::Latex, Some(_)) => format!("\\{}", base),
+            (Markup::Latex, None) if base.chars().count() == 1 => base.to_string(),
+            (Markup::Latex, None) => format!("\\mathrm{{{}}}", base),
+            (Markup::MathMl, Some(symbol)) => format!("<mi>{}</mi>", symbol),
+            (Markup::MathMl, None) => format!("<mi>{}</mi>", base),
+        };
+
+        match (self, subscript) {
+            (_, None) => base,
+            (Markup::Latex, Some(subscript)) => format!("{}_{{{}}}", base, subscript),
+            (Markup::MathMl, Some(subscript)) => {
+                format!("<msub>{}<mi>{}</mi></msub>", base, subscript)
+            }
+        }
+    }
+
+    // An infix operator such as `+` or `=`.
+    fn operator(self, operator: &str) -> String {
+        match (self, operator) {
+            (Markup::Latex, "×") => " \\times ".to_string(),
+            (Markup::Latex, "±") => " \\pm ".to_string(),
+            (Markup::Latex, ",") => ", ".to_string(),
+            (Markup::Latex, operator) => format!(" {} ", operator),
+            (Markup::MathMl, operator) => format!("<mo>{}</mo>", operator),
+        }
+    }
+
+    fn prefix_minus(self) -> String {
+        match self {
+            Markup::Latex => "-".to_string(),
+            Markup::MathMl => "<mo>-</mo>".to_string(),
+        }
+    }
+
+    // A product sign, or none for factors written side by side as in `2x`.
+    fn times(self, is_juxtaposed: bool) -> String {
+        match (self, is_juxtaposed) {
+            (Markup::Latex, true) => " ".to_string(),
+            (Markup::Latex, false) => " \\cdot ".to_string(),
+            (Markup::MathMl, true) => "<mo>&#x2062;</mo>".to_string(),
+            (Markup::MathMl, false) => "<mo>⋅</mo>".to_string(),
+        }
+    }
+
+    fn parentheses(self, inner: &str) -> String {
+        self.fence(inner, "(", ")")
+    }
+
+    fn fence(self, inner: &str, open: &str, close: &str) -> String {
+        match self {
+            Markup::Latex => format!("\\left{}{}\\right{}", open, inner, close),
+            Markup::MathMl => format!("<mrow><mo>{}</mo>{}<mo>{}</mo></mrow>", open, inner, close),
+        }
+    }
+
+    fn fraction(self, numerator: &str, denominator: &str) -> String {
+        match self {
+            Markup::Latex => format!("\\frac{{{}}}{{{}}}", numerator, denominator),
+            Markup::MathMl => format!(
+                "<mfrac><mrow>{}</mrow><mrow>{}</mrow></mfrac>",
+                numerator, denominator
+            ),
+        }
+    }
+
+    fn power(self, base: String, exponent: String) -> String {
+        match self {
+            Markup::Latex => format!("{}^{{{}}}", base, exponent),
+            Markup::MathMl => format!(
+                "<msup><mrow>{}</mrow><mrow>{}</mrow></msup>",
+                base, exponent
+            ),
+        }
+    }
+
+    fn root(self, radicand: &str) -> String {
+        match self {
+            Markup::Latex => format!("\\sqrt{{{}}}", radicand),
+            Markup::MathMl => format!("<msqrt>{}</msqrt>", radicand),
+        }
+    }
+
+    // A function applied to its arguments, with an optional subscript as in
+    // `log_2(x)` and an optional power as in `sin^2(x)`.
+    fn function(
+        self,
+        name: &str,
+        subscript: Option<String>,
+        power: Option<String>,
+        arguments: &[String],
+    ) -> String {
+        let named = NAMED_FUNCTIONS
+            .iter()
+            .find(|(function, _, _)| *function == name);
+        let mut head = match (self, named) {
+            (Markup::Latex, Some((_, latex, _))) => latex.to_string(),
+            (Markup::Latex, None) if name.chars().count() == 1 => name.to_string(),
+            (Markup::Latex, None) => format!("\\operatorname{{{}}}", name),
+            (Markup::MathMl, Some((_, _, mathml))) => format!("<mi>{}</mi>", mathml),
+            (Markup::MathMl, None) => format!("<mi>{}</mi>", name),
+        };
+
+        head = match (self, subscript, power) {
+            (_, None, None) => head,
+            (Markup::Latex, subscript, power) => format!(
+                "{}{}{}",
+                head,
+                subscript.map_or(String::new(), |subscript| format!("_{{{}}}", subscript)),
+                power.map_or(String::new(), |power| format!("^{{{}}}", power))
+            ),
+            (Markup::MathMl, Some(subscript), None) => {
+                format!("<msub>{}<mrow>{}</mrow></msub>", head, subscript)
+            }
+            (Markup::MathMl, None, Some(power)) => {
+                format!("<msup>{}<mrow>{}</mrow></msup>", head, power)
+            }
+            (Markup::MathMl, Some(subscript), Some(power)) => format!(
+                "<msubsup>{}<mrow>{}</mrow><mrow>{}</mrow></msubsup>",
+                head, subscript, power
+            ),
+        };
+
+        let arguments = self.parentheses(&self.join(arguments));
+        match self {
+            Markup::Latex => format!("{}{}", head, arguments),
+            Markup::MathMl => format!("<mrow>{}<mo>&#x2061;</mo>{}</mrow>", head, arguments),
+        }
+    }
+
+    fn join(self, items: &[String]) -> String {
+        items.join(&self.operator(","))
+    }
+
+    fn matrix(self, rows: &[Vec<String>]) -> String {
+        match self {
+            Markup::Latex => format!(
+                "\\begin{{pmatrix}} {} \\end{{pmatrix}}",
+                rows.iter()
+                    .map(|row| row.join(" & "))
+                    .collect::<Vec<String>>()
+                    .join(" \\\\ ")
+            ),
+            Markup::MathMl => self.parentheses(&format!(
+                "<mtable>{}</mtable>",
+                rows.iter()
+                    .map(|row| format!(
+                        "<mtr>{}</mtr>",
+                        row.iter()
+                            .map(|cell| format!("<mtd>{}</mtd>", cell))
+                            .collect::<String>()
+                    ))
+                    .collect::<String>()
+            )),
+        }
+    }
+
+    // The items of a tuple in parentheses, or a matrix if every item is a row
+    // of the same length.
+    fn tuple(self, items: &[String], rows: Option<Vec<Vec<String>>>) -> String {
+        match rows {
+            Some(rows) if rows.len() > 1 && rows.iter().all(|row| row.len() == rows[0].len()) => {
+                self.matrix(&rows)
+            }
+            _ => self.parentheses(&self.join(items)),
+        }
+    }
+
+    fn expression(self, expression: &Expr) -> String {
+        let operand = |expression: &Expr, min: u8| {
+            let text = self.expression(expression);
+            if expression.precedence() < min {
+                self.parentheses(&text)
+            } else {
+                text
+            }
+        };
+
+        match expression {
+            Expr::Number(value) => self.number(*value),
+            Expr::Variable(name) => self.identifier(name),
+            Expr::Neg(u) => self.row(&[self.prefix_minus(), operand(u, 4)]),
+            Expr::Add(u, v) => self.row(&[operand(u, 1), self.operator("+"), operand(v, 2)]),
+            Expr::Sub(u, v) => self.row(&[operand(u, 1), self.operator("-"), operand(v, 2)]),
+            // A factor of one, as in `1.0*x` from a LaTeX fraction, is left out.
+            Expr::Mul(u, v) if u.as_number() == Some(1.0) => self.expression(v),
+            Expr::Mul(u, v) if v.as_number() == Some(1.0) => self.expression(u),
+            // A negative right factor is put in parentheses, as in `x (-2)`.
+            Expr::Mul(u, v) => {
+                let right = match v.as_ref() {
+                    Expr::Neg(_) => operand(v, 4),
+                    Expr::Number(value) if *value < 0.0 => operand(v, 4),
+                    _ => operand(v, 3),
+                };
+                self.row(&[operand(u, 2), self.times(!starts_with_number(v)), right])
+            }
+            Expr::Div(u, v) => self.fraction(&self.expression(u), &self.expression(v)),
+            Expr::Pow(u, v) => match u.as_ref() {
+                Expr::Call(name, arguments)
+                    if NAMED_FUNCTIONS
+                        .iter()
+                        .any(|(function, _, _)| function == name) =>
+                {
+                    self.call(name, arguments, Some(self.expression(v)))
+                }
+                _ => self.power(operand(u, 5), self.expression(v)),
+            },
+            Expr::Call(name, arguments) => self.call(name, arguments, None),
+        }
+    }
+
+    fn call(self, name: &str, arguments: &[Expr], power: Option<String>) -> String {
+        let text = match (name, arguments) {
+            ("sqrt", [u]) => self.root(&self.expression(u)),
+            ("abs", [u]) => self.fence(&self.expression(u), "|", "|"),
+            ("exp", [u]) => self.power(self.identifier("e"), self.expression(u)),
+            ("factorial", [u]) => {
+                let text = self.expression(u);
+                let text = if u.precedence() < 5 {
+                    self.parentheses(&text)
+                } else {
+                    text
+                };
+                self.row(&[text, self.operator("!").trim().to_string()])
+            }
+            ("log", [u, base]) => {
+                return self.function(
+                    name,
+                    Some(self.expression(base)),
+                    power,
+                    &[self.expression(u)],
+                )
+            }
+            _ => {
+                let arguments: Vec<String> = arguments
+                    .iter()
+                    .map(|argument| self.expression(argument))
+                    .collect();
+                return self.function(name, None, power, &arguments);
+            }
+        };
+
+        match power {
+            Some(power) => self.power(self.parentheses(&text), power),
+            None => text,
+        }
+    }
+
+    // The source of an expression, a tuple of them, or an equation. Roots from
+    // `solve_exact` such as `1 ± sqrt(2)` are also understood.
+    fn source(self, source: &str) -> Result<String, Box<dyn Error>> {
+        let source = source.trim();
+
+        if let Some((left, right)) = split_equation(source) {
+            return Ok(self.row(&[self.source(left)?, self.operator("="), self.source(right)?]));
+        }
+
+        if source.contains('±') {
+            let mut parts = Vec::new();
+            for (index, part) in source.split('±').enumerate() {
+                if index > 0 {
+                    parts.push(self.operator("±"));
+                }
+                if !part.trim().is_empty() {
+                    parts.push(self.source(part)?);
+                }
+            }
+            return Ok(self.row(&parts));
+        }
+
+        let items = tuple_sources(source);
+        if items.len() > 1 {
+            let mut typeset = Vec::new();
+            let mut rows = Some(Vec::new());
+            for item in &items {
+                typeset.push(self.source(item)?);
+                let cells = tuple_sources(item);
+                rows = match rows {
+                    Some(mut rows) if cells.len() > 1 => {
+                        rows.push(
+                            cells
+                                .iter()
+                                .map(|cell| self.source(cell))
+                                .collect::<Result<Vec<String>, Box<dyn Error>>>()?,
+                        );
+                        Some(rows)
+                    }
+                    _ => None,
+                };
+            }
+            return Ok(self.tuple(&typeset, rows));
+        }
+
+        Ok(self.expression(&Expr::parse(source)?))
+    }
+
+    // A value computed by the REPL.
+    fn result(self, result: &ReplResult) -> Option<String> {
+        match result {
+            ReplResult::Number(value) => Some(self.number(*value)),
+            ReplResult::Point(point) => {
+                Some(self.parentheses(&self.join(&[self.number(point.x), self.number(point.y)])))
+            }
+            ReplResult::List(list) => {
+                let items = list
+                    .items
+                    .iter()
+                    .map(|item| self.result(item))
+                    .collect::<Option<Vec<String>>>()?;
+                let rows = list
+                    .items
+                    .iter()
+                    .map(|item| match item {
+                        ReplResult::List(row) => row
+                            .items
+                            .iter()
+                            .map(|cell| self.result(cell))
+                            .collect::<Option<Vec<String>>>(),
+                        // Rows of two numbers come out of the REPL as points.
+                        ReplResult::Point(point) => {
+                            Some(vec![self.number(point.x), self.number(point.y)])
+                        }
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<Vec<String>>>>();
+                match rows {
+                    Some(rows)
+                        if rows.len() > 1 && rows.iter().all(|row| row.len() == rows[0].len()) =>
+                    {
+                        Some(self.matrix(&rows))
+                    }
+                    _ => Some(self.fence(&self.join(&items), "[", "]")),
+                }
+            }
+            ReplResult::Expression(source) => self.source(source).ok(),
+            ReplResult::Bindings(bindings) => Some(
+                self.join(
+                    &bindings
+                        .iter()
+                        .map(|(name, value)| {
+                            self.row(&[
+                                self.identifier(name),
+                                self.operator("="),
+                                self.number(*value),
+                            ])
+                        })
+                        .collect::<Vec<String>>(),
+                ),
+            ),
+            ReplResult::ExactBindings(bindings) => Some(
+                self.join(
+                    &bindings
+                        .iter()
+                        .map(|(name, value)| {
+                            Some(self.row(&[
+                                self.identifier(name),
+                                self.operator("="),
+                                self.source(value).ok()?,
+                            ]))
+                        })
+                        .collect::<Option<Vec<String>>>()?,
+                ),
+            ),
+            ReplResult::Empty | ReplResult::Boolean(_) | ReplResult::String(_) => None,
+        }
+    }
+}
+
+impl ReplResult {
+    /// The result typeset as LaTeX or MathML, for those that are mathematics.
+    pub fn typeset(&self, markup: Markup) -> Option<String> {
+        markup.result(self).map(|body| markup.document(body))
+    }
+}
+
+impl Repl {
+    /// `latex(expr)` and `mathml(expr)`, the expression typeset as a string.
+    pub(super) fn eval_typeset(
+        &mut self,
+        name: &str,
+        arguments: &[String],
+    ) -> Result<Value, Box<dyn Error>> {
+        expect_arguments(arguments, 1)?;
+
+        let markup = match name {
+            "latex" => Markup::Latex,
+            _ => Markup::MathMl,
+        };
+        Ok(Value::String(self.typeset(&arguments[0], markup)?))
+    }
+
+    /// Typeset an input as LaTeX or MathML: an expression or equation, a
+    /// tuple or matrix, the name of a user function, which is shown with its
+    /// definition, or a name bound to a list. Expression forms such as
+    /// `taylor(...)` are replaced by the expression they produce.
+    pub fn typeset(&mut self, input: &str, markup: Markup) -> Result<String, Box<dyn Error>> {
+        let normalized = self.normalize(input)?;
+        let input = normalized.trim();
+        self.sync_globals();
+
+        let body = if let Some(function) = self.functions.get(input) {
+            markup.source(&format!(
+                "{}({}) = {}",
+                input,
+                function.params.join(", "),
+                function.body
+            ))?
+        } else if let Some(Value::String(_) | Value::Tuple(_)) =
+            evalexpr::Context::get_value(&self.context, input)
+        {
+            let value = self.evaluate(input, &[])?;
+            let result = self.value_to_result(value)?;
+            markup.result(&result).ok_or(ReplError::InvalidType)?
+        } else if let Some(derivatives) = self.derivative_form(input)? {
+            markup.source(&derivatives)?
+        } else {
+            markup.source(&self.expand_expression_forms(input)?)?
+        };
+
+        Ok(markup.document(body))
+    }
+
+    /// The typeset form of an entry in the REPL output: the result, or for
+    /// definitions and assignments, which have none, the input itself.
+    pub fn typeset_entry(
+        &mut self,
+        input: &str,
+        result: &ReplResult,
+        markup: Markup,
+    ) -> Option<String> {
+        match result {
+            ReplResult::Empty if split_equation(input).is_some() => {
+                self.typeset(input, markup).ok()
+            }
+            result => result.typeset(markup),
+        }
+    }
+}
+
+// Whether a product's right factor begins with a digit, so that it needs a
+// product sign to stay apart from the left factor, as in `2 \cdot 3^x`.
+fn starts_with_number(expression: &Expr) -> bool {
+    match expression {
+        Expr::Number(value) => *value >= 0.0,
+        Expr::Pow(base, _) => starts_with_number(base),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latex_of_fractions_and_powers() {
+        let mut repl = Repl::new();
+        for (input, expected) in [
+            ("1/2", "\\frac{1}{2}"),
+            ("(x + 1)/(x - 1)", "\\frac{x + 1}{x - 1}"),
+            ("x^2", "x^{2}"),
+            ("x^(n + 1)", "x^{n + 1}"),
+            ("sqrt(x)", "\\sqrt{x}"),
+            ("x^2 = 1", "x^{2} = 1"),
+        ] {
+            assert_eq!(
+                repl.typeset(input, Markup::Latex).unwrap(),
+                expected,
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn latex_of_definitions_and_matrices() {
+        let mut repl = Repl::new();
+        repl.process_input("f(x) = x^2/2").unwrap();
+        repl.process_input("m = ((1, 2), (3, 4))").unwrap();
+        assert_eq!(
+            repl.typeset("f", Markup::Latex).unwrap(),
+            "f\\left(x\\right) = \\frac{x^{2}}{2}"
+        );
+        assert_eq!(
+            repl.typeset("m", Markup::Latex).unwrap(),
+            "\\begin{pmatrix} 1 & 2 \\\\ 3 & 4 \\end{pmatrix}"
+        );
+    }
+
+    #[test]
+    fn mathml_of_a_power() {
+        let mut repl = Repl::new();
+        assert_eq!(
+            repl.typeset("x^2", Markup::MathMl).unwrap(),
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\">\
+             <msup><mrow><mi>x</mi></mrow><mrow><mn>2</mn></mrow></msup></math>"
+        );
+    }
+}